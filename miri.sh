@@ -0,0 +1,6 @@
+#!/bin/sh
+# 全てのテストを Miri の Stacked Borrows と Tree Borrows の両方で走らせる
+# 事前に rustup +nightly component add miri が必要
+set -e
+cargo +nightly miri test "$@"
+MIRIFLAGS="$MIRIFLAGS -Zmiri-tree-borrows" cargo +nightly miri test "$@"
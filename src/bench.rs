@@ -0,0 +1,483 @@
+// 各 List の benchmark。
+// 同じ seed から作った同じ操作列を全ての実装に適用し、warmup の後に samples 回計測して統計を出す。
+use crate::memory::{self, Usage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::hint::black_box;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub n: usize,   // 最初に push_back しておく要素の数
+    pub ops: usize, // 1 sample あたりの操作の数
+    pub seed: u64,
+    pub warmup: usize,
+    pub samples: usize,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            n: 1000000,
+            ops: 1000000,
+            seed: 0,
+            warmup: 1,
+            samples: 5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Workload {
+    Mixed, // 元の benchmark と同じ比率
+    ReadHeavy,
+    InsertHeavy,
+    RemoveHeavy,
+    Scan,             // iter で先頭から末尾まで辿るのを繰り返す
+    Custom([u32; 3]), // 移動・挿入・削除の百分率を直接指定する
+}
+impl Workload {
+    pub const ALL: [Workload; 5] = [
+        Workload::Mixed,
+        Workload::ReadHeavy,
+        Workload::InsertHeavy,
+        Workload::RemoveHeavy,
+        Workload::Scan,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Workload::Mixed => "mixed",
+            Workload::ReadHeavy => "read-heavy",
+            Workload::InsertHeavy => "insert-heavy",
+            Workload::RemoveHeavy => "remove-heavy",
+            Workload::Scan => "scan",
+            Workload::Custom(_) => "custom",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|w| w.name() == name)
+    }
+    // 移動・挿入・削除の百分率
+    pub fn mix(&self) -> [u32; 3] {
+        match self {
+            Workload::Mixed => [50, 25, 25],
+            Workload::ReadHeavy => [90, 5, 5],
+            Workload::InsertHeavy => [20, 70, 10],
+            Workload::RemoveHeavy => [20, 10, 70],
+            Workload::Scan => [100, 0, 0],
+            Workload::Custom(mix) => *mix,
+        }
+    }
+    fn ops(&self, rng: &mut StdRng, count: usize) -> Vec<Op> {
+        let [moves, inserts, _] = self.mix();
+        (0..count)
+            .map(|k| {
+                let r = rng.gen_range(0, 100);
+                if r < moves {
+                    Op::Move(rng.gen())
+                } else if r < moves + inserts {
+                    Op::Insert(k)
+                } else {
+                    Op::Remove
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Move(bool), // true なら next、false なら prev
+    Insert(usize),
+    Remove,
+}
+
+// 手元の Ptr を一つ持って List を操作する。targets::Cursor が LinkedList を実装した全ての List に用意する
+pub trait Target {
+    const NAME: &'static str;
+    fn new(n: usize) -> Self;
+    fn move_next(&mut self);
+    fn move_prev(&mut self);
+    // 今の位置の前に挿入する。sentinel にいれば末尾に追加する
+    fn insert(&mut self, value: usize);
+    // 今の位置を削除して次に進む。sentinel にいれば先頭に移る
+    fn remove(&mut self);
+    fn len(&self) -> usize;
+    // iter で全ての値を辿った和
+    fn sum(&self) -> u64;
+    fn memory(&self) -> Usage;
+}
+
+fn apply<L: Target>(list: &mut L, workload: Workload, ops: &[Op]) {
+    if workload == Workload::Scan {
+        let passes = (ops.len() / list.len().max(1)).max(1);
+        for _ in 0..passes {
+            black_box(list.sum());
+        }
+        return;
+    }
+    for op in ops {
+        match *op {
+            Op::Move(true) => list.move_next(),
+            Op::Move(false) => list.move_prev(),
+            Op::Insert(value) => list.insert(value),
+            Op::Remove => list.remove(),
+        }
+    }
+}
+
+// 最近傍順位法の百分位数。samples は整列済みで空でないこと
+fn percentile(samples: &[Duration], p: usize) -> Duration {
+    let rank = (samples.len() * p).div_ceil(100);
+    samples[rank.max(1) - 1]
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub min: Duration,
+    pub median: Duration,
+    pub p90: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+}
+impl Stats {
+    pub fn new(mut samples: Vec<Duration>) -> Self {
+        assert!(!samples.is_empty());
+        samples.sort();
+        let total: Duration = samples.iter().sum();
+        Self {
+            min: samples[0],
+            median: percentile(&samples, 50),
+            p90: percentile(&samples, 90),
+            max: *samples.last().unwrap(),
+            mean: total / samples.len() as u32,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub target: &'static str,
+    pub workload: Workload,
+    pub config: Config,
+    pub stats: Stats,
+    pub len: usize,    // 操作した後の長さ
+    pub checksum: u64, // 操作した後の値の和。同じ seed なら実装によらず一致する
+    pub memory: Usage, // 最後の sample の Pool の使用量。peak はその sample の中での最大値
+    // count-alloc のときだけ、最後の sample で List が heap に確保している (バイト数, 最大値)
+    pub heap: Option<(usize, usize)>,
+}
+
+pub fn run<L: Target>(workload: Workload, config: &Config) -> Record {
+    let ops = workload.ops(&mut StdRng::seed_from_u64(config.seed), config.ops);
+    let mut samples = Vec::new();
+    let mut result = (0, 0, Usage::default(), None);
+    let allocator = memory::allocator();
+    for i in 0..config.warmup + config.samples.max(1) {
+        // 前の sample の List を解放してから測り始める
+        let base = allocator.map(|a| {
+            a.reset_peak();
+            a.current()
+        });
+        // List の構築と解放は計測しない
+        let mut list = L::new(config.n);
+        let instant = Instant::now();
+        apply(&mut list, workload, &ops);
+        let elapsed = instant.elapsed();
+        if i >= config.warmup {
+            samples.push(elapsed);
+        }
+        let heap = allocator.zip(base).map(|(a, base)| {
+            (
+                a.current().saturating_sub(base),
+                a.peak().saturating_sub(base),
+            )
+        });
+        result = (list.len(), list.sum(), list.memory(), heap);
+    }
+    Record {
+        target: L::NAME,
+        workload,
+        config: config.clone(),
+        stats: Stats::new(samples),
+        len: result.0,
+        checksum: result.1,
+        memory: result.2,
+        heap: result.3,
+    }
+}
+
+const COLUMNS: [&str; 23] = [
+    "target",
+    "workload",
+    "move_pct",
+    "insert_pct",
+    "remove_pct",
+    "n",
+    "ops",
+    "seed",
+    "warmup",
+    "samples",
+    "min_ns",
+    "median_ns",
+    "p90_ns",
+    "max_ns",
+    "mean_ns",
+    "live",
+    "free_slots",
+    "bytes",
+    "peak_bytes",
+    "heap_bytes",
+    "heap_peak_bytes",
+    "len",
+    "checksum",
+];
+
+impl Record {
+    // 測っていない値は空にする
+    fn fields(&self) -> [String; 23] {
+        let s = &self.stats;
+        let m = &self.memory;
+        let heap = |f: fn((usize, usize)) -> usize| {
+            self.heap.map(f).map_or(String::new(), |b| b.to_string())
+        };
+        let [moves, inserts, removes] = self.workload.mix();
+        [
+            self.target.to_string(),
+            self.workload.name().to_string(),
+            moves.to_string(),
+            inserts.to_string(),
+            removes.to_string(),
+            self.config.n.to_string(),
+            self.config.ops.to_string(),
+            self.config.seed.to_string(),
+            self.config.warmup.to_string(),
+            self.config.samples.to_string(),
+            s.min.as_nanos().to_string(),
+            s.median.as_nanos().to_string(),
+            s.p90.as_nanos().to_string(),
+            s.max.as_nanos().to_string(),
+            s.mean.as_nanos().to_string(),
+            m.live.to_string(),
+            m.free.to_string(),
+            m.bytes.map_or(String::new(), |b| b.to_string()),
+            m.peak.map_or(String::new(), |b| b.to_string()),
+            heap(|(bytes, _)| bytes),
+            heap(|(_, peak)| peak),
+            self.len.to_string(),
+            self.checksum.to_string(),
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+// target と workload 以外は数値なので、文字列の列だけを引用符で囲めばよい。測っていない値は JSON では null
+pub fn write(records: &[Record], format: Format, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        Format::Csv => {
+            writeln!(out, "{}", COLUMNS.join(","))?;
+            for record in records {
+                writeln!(out, "{}", record.fields().join(","))?;
+            }
+        }
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (i, record) in records.iter().enumerate() {
+                let fields = record.fields();
+                let members = COLUMNS
+                    .iter()
+                    .zip(fields.iter())
+                    .enumerate()
+                    .map(|(j, (key, value))| {
+                        if j < 2 {
+                            format!("\"{}\": \"{}\"", key, value)
+                        } else if value.is_empty() {
+                            format!("\"{}\": null", key)
+                        } else {
+                            format!("\"{}\": {}", key, value)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let comma = if i + 1 < records.len() { "," } else { "" };
+                writeln!(out, "  {{{}}}{}", members.join(", "), comma)?;
+            }
+            writeln!(out, "]")?;
+        }
+    }
+    Ok(())
+}
+
+pub mod targets {
+    use super::Target;
+    use crate::linked_list::LinkedList;
+    use crate::memory::Usage;
+
+    // current が None なら sentinel にいる
+    pub struct Cursor<L: LinkedList<usize>> {
+        list: L,
+        current: Option<L::Handle>,
+    }
+    impl<L: LinkedList<usize>> Target for Cursor<L> {
+        const NAME: &'static str = L::NAME;
+        fn new(n: usize) -> Self {
+            let mut list = L::new();
+            for i in 0..n {
+                list.push_back(i);
+            }
+            let current = list.head();
+            Self { list, current }
+        }
+        fn move_next(&mut self) {
+            self.current = match self.current {
+                Some(h) => self.list.next(h),
+                None => self.list.head(),
+            };
+        }
+        fn move_prev(&mut self) {
+            self.current = match self.current {
+                Some(h) => self.list.prev(h),
+                None => self.list.tail(),
+            };
+        }
+        fn insert(&mut self, value: usize) {
+            match self.current {
+                Some(h) => assert!(self.list.insert(h, value).is_some()),
+                None => {
+                    self.list.push_back(value);
+                }
+            }
+        }
+        fn remove(&mut self) {
+            self.current = match self.current {
+                Some(h) => {
+                    let next = self.list.next(h);
+                    self.list.remove(h);
+                    next
+                }
+                None => self.list.head(),
+            };
+        }
+        fn len(&self) -> usize {
+            self.list.len()
+        }
+        fn sum(&self) -> u64 {
+            self.list.iter().map(|&v| v as u64).sum()
+        }
+        fn memory(&self) -> Usage {
+            self.list.memory()
+        }
+    }
+
+    pub type HashMapList = Cursor<crate::test05_nodes_in_hashmap::List<usize>>;
+    pub type MepooList = Cursor<crate::test06_nodes_in_mepoo::List<usize>>;
+    pub type ArenaList = Cursor<crate::test07_nodes_in_arena::List<usize>>;
+    pub type SlabList = Cursor<crate::test10_nodes_in_slab::List<usize>>;
+    // 操作のたびに新しい版を作って古い版を捨てる
+    pub type PersistentList = Cursor<crate::test13_persistent::List<usize>>;
+}
+
+#[cfg(test)]
+fn small_config() -> Config {
+    Config {
+        n: 100,
+        ops: if cfg!(miri) { 50 } else { 2000 },
+        seed: 7,
+        warmup: 0,
+        samples: 2,
+    }
+}
+
+#[test]
+fn test_stats() {
+    let ms = Duration::from_millis;
+    let stats = Stats::new((1..=10).rev().map(ms).collect());
+    assert_eq!(stats.min, ms(1));
+    assert_eq!(stats.median, ms(5));
+    assert_eq!(stats.p90, ms(9));
+    assert_eq!(stats.max, ms(10));
+    assert_eq!(stats.mean, Duration::from_micros(5500));
+    let stats = Stats::new(vec![ms(3)]);
+    assert_eq!((stats.min, stats.median, stats.p90), (ms(3), ms(3), ms(3)));
+}
+
+#[test]
+fn test_same_result_for_every_target() {
+    use targets::*;
+    let config = small_config();
+    for &workload in Workload::ALL.iter() {
+        let records = [
+            run::<HashMapList>(workload, &config),
+            run::<MepooList>(workload, &config),
+            run::<ArenaList>(workload, &config),
+            run::<SlabList>(workload, &config),
+            run::<PersistentList>(workload, &config),
+        ];
+        for record in &records[1..] {
+            assert_eq!(record.len, records[0].len, "{:?}", workload);
+            assert_eq!(record.checksum, records[0].checksum, "{:?}", workload);
+        }
+        for record in &records {
+            assert!(record.memory.peak >= record.memory.bytes);
+            assert_eq!(record.heap.is_some(), cfg!(feature = "count-alloc"));
+            // mepoo だけは Pool の使用量を測れない
+            assert_eq!(
+                record.memory.bytes.is_none(),
+                record.target == MepooList::NAME,
+                "{}",
+                record.target
+            );
+        }
+        // Pool には sentinel も入っている
+        for record in &records[..4] {
+            assert_eq!(record.memory.live, record.len + 1, "{}", record.target);
+        }
+        // 永続 List は order と index の二つの木に一つずつノードを持つ
+        assert_eq!(records[4].memory.live, 2 * records[4].len);
+    }
+    // 削除が多いと短くなり、挿入が多いと長くなる
+    assert!(run::<ArenaList>(Workload::RemoveHeavy, &config).len < config.n);
+    assert!(run::<ArenaList>(Workload::InsertHeavy, &config).len > config.n);
+    assert_eq!(run::<ArenaList>(Workload::Scan, &config).len, config.n);
+}
+
+#[test]
+fn test_write() {
+    let config = small_config();
+    let records = vec![
+        run::<targets::HashMapList>(Workload::Mixed, &config),
+        run::<targets::ArenaList>(Workload::Scan, &config),
+        run::<targets::MepooList>(Workload::Scan, &config),
+    ];
+    let mut csv = Vec::new();
+    write(&records, Format::Csv, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("target,workload,move_pct,insert_pct,remove_pct,n,ops,seed,"));
+    assert!(lines[1].starts_with("hashmap,mixed,50,25,25,100,"));
+    assert!(lines[2].starts_with("arena,scan,100,0,0,100,"));
+    assert!(lines
+        .iter()
+        .all(|line| line.split(',').count() == COLUMNS.len()));
+
+    let mut json = Vec::new();
+    write(&records, Format::Json, &mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    let lines = json.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[1]
+        .starts_with("  {\"target\": \"hashmap\", \"workload\": \"mixed\", \"move_pct\": 50,"));
+    assert!(lines[1].ends_with("},"));
+    if cfg!(not(feature = "count-alloc")) {
+        assert!(lines[1].contains("\"heap_bytes\": null, \"heap_peak_bytes\": null,"));
+        assert!(csv.lines().nth(1).unwrap().contains(",,"));
+    }
+    // mepoo の Pool の使用量は測らないので空になる
+    assert!(lines[3].contains("\"bytes\": null, \"peak_bytes\": null,"));
+    assert!(lines[3].ends_with("}"));
+}
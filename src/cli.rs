@@ -0,0 +1,205 @@
+// main の引数の解析。依存を増やさないように手で書いている
+use crate::bench::{Config, Format, Workload};
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+usage: graph_in_rust_with_unsafe [options]
+
+options:
+  -t, --target NAME[,NAME...]    implementations to run (default: all)
+  -w, --workload NAME[,NAME...]  workloads to run (default: all presets)
+      --mix MOVE,INSERT,REMOVE   custom workload given in percent (sum must be 100)
+  -n, --size N                   initial list length
+      --ops N                    operations per sample
+      --seed N                   RNG seed
+      --warmup N                 untimed runs before sampling
+      --samples N                timed runs (at least 1)
+  -f, --format csv|json          output format (default: csv)
+      --list                     list implementations and workloads
+  -h, --help                     show this help
+";
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub targets: Vec<String>,
+    pub workloads: Vec<Workload>,
+    pub config: Config,
+    pub format: Format,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Options),
+    List,
+    Help,
+}
+
+fn number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a non-negative integer, got '{}'", option, value))
+}
+
+fn mix(value: &str) -> Result<Workload, String> {
+    let parts = value
+        .split(',')
+        .map(|part| number::<u32>("--mix", part))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts.as_slice() {
+        &[moves, inserts, removes] if parts.iter().map(|&p| p as u64).sum::<u64>() == 100 => {
+            Ok(Workload::Custom([moves, inserts, removes]))
+        }
+        _ => Err(format!(
+            "--mix expects three percentages summing to 100, got '{}'",
+            value
+        )),
+    }
+}
+
+// targets は選べる実装の名前。--help と --list は他の引数より優先する
+pub fn parse(args: impl IntoIterator<Item = String>, targets: &[&str]) -> Result<Command, String> {
+    let mut options = Options {
+        targets: Vec::new(),
+        workloads: Vec::new(),
+        config: Config::default(),
+        format: Format::Csv,
+    };
+    let mut list = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // --name=value も --name value と同じに扱う
+        let (name, inline) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => {
+                (arg[..i].to_string(), Some(arg[i + 1..].to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} expects a value", name))
+        };
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--list" => list = true,
+            "-t" | "--target" => {
+                for target in value()?.split(',') {
+                    if !targets.contains(&target) {
+                        return Err(format!("unknown target '{}'", target));
+                    }
+                    options.targets.push(target.to_string());
+                }
+            }
+            "-w" | "--workload" => {
+                for workload in value()?.split(',') {
+                    let workload = Workload::from_name(workload)
+                        .ok_or_else(|| format!("unknown workload '{}'", workload))?;
+                    options.workloads.push(workload);
+                }
+            }
+            "--mix" => options.workloads.push(mix(&value()?)?),
+            "-n" | "--size" => options.config.n = number(&name, &value()?)?,
+            "--ops" => options.config.ops = number(&name, &value()?)?,
+            "--seed" => options.config.seed = number(&name, &value()?)?,
+            "--warmup" => options.config.warmup = number(&name, &value()?)?,
+            "--samples" => options.config.samples = number(&name, &value()?)?,
+            "-f" | "--format" => {
+                options.format = match value()?.as_str() {
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format '{}'", other)),
+                }
+            }
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+    if list {
+        return Ok(Command::List);
+    }
+    if options.config.samples == 0 {
+        return Err("--samples must be at least 1".to_string());
+    }
+    if options.targets.is_empty() {
+        options.targets = targets.iter().map(|t| t.to_string()).collect();
+    }
+    if options.workloads.is_empty() {
+        options.workloads = Workload::ALL.to_vec();
+    }
+    Ok(Command::Run(options))
+}
+
+#[cfg(test)]
+fn parse_str(args: &str) -> Result<Command, String> {
+    parse(
+        args.split_whitespace().map(String::from),
+        &["hashmap", "mepoo", "arena"],
+    )
+}
+
+#[test]
+fn test_parse() {
+    match parse_str("").unwrap() {
+        Command::Run(options) => {
+            assert_eq!(options.targets, vec!["hashmap", "mepoo", "arena"]);
+            assert_eq!(options.workloads, Workload::ALL.to_vec());
+            assert_eq!(options.config, Config::default());
+            assert_eq!(options.format, Format::Csv);
+        }
+        command => panic!("{:?}", command),
+    }
+
+    let command =
+        parse_str("-t arena,hashmap --workload=scan -w mixed --mix 10,60,30 -n 10 --ops=20 --seed 3 --warmup 0 --samples 7 -f json")
+            .unwrap();
+    assert_eq!(
+        command,
+        Command::Run(Options {
+            targets: vec!["arena".to_string(), "hashmap".to_string()],
+            workloads: vec![
+                Workload::Scan,
+                Workload::Mixed,
+                Workload::Custom([10, 60, 30])
+            ],
+            config: Config {
+                n: 10,
+                ops: 20,
+                seed: 3,
+                warmup: 0,
+                samples: 7,
+            },
+            format: Format::Json,
+        })
+    );
+
+    assert_eq!(parse_str("--seed 1 --list").unwrap(), Command::List);
+    assert_eq!(parse_str("--list --help").unwrap(), Command::Help);
+}
+
+#[test]
+fn test_parse_error() {
+    assert_eq!(parse_str("-t vec").unwrap_err(), "unknown target 'vec'");
+    assert_eq!(
+        parse_str("-w random").unwrap_err(),
+        "unknown workload 'random'"
+    );
+    assert_eq!(
+        parse_str("--bogus").unwrap_err(),
+        "unknown option '--bogus'"
+    );
+    assert_eq!(parse_str("--seed").unwrap_err(), "--seed expects a value");
+    assert_eq!(
+        parse_str("-n -1").unwrap_err(),
+        "-n expects a non-negative integer, got '-1'"
+    );
+    assert_eq!(parse_str("-f xml").unwrap_err(), "unknown format 'xml'");
+    assert_eq!(
+        parse_str("--mix 50,50").unwrap_err(),
+        "--mix expects three percentages summing to 100, got '50,50'"
+    );
+    assert!(parse_str("--mix 50,50,1").is_err());
+    assert_eq!(
+        parse_str("--samples 0").unwrap_err(),
+        "--samples must be at least 1"
+    );
+}
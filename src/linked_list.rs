@@ -0,0 +1,426 @@
+// test05 / test06 / test07 / test10 の List を同じコードで扱うための trait。
+// Handle は値を持つノードを指し、sentinel の位置は None で表す。
+// 別のリストの Handle や削除済みの Handle を渡したときは None / false を返すか、何もしない。
+use crate::memory::Usage;
+use crate::validate::Report;
+#[cfg(test)]
+use std::iter::FromIterator;
+
+// bench が使うのは一部のメソッドだけで、残りはテストから使う
+#[cfg_attr(not(test), allow(dead_code))]
+pub trait LinkedList<T>: Sized {
+    type Handle: Copy;
+    type Iter<'a>: DoubleEndedIterator<Item = &'a T>
+    where
+        Self: 'a,
+        T: 'a;
+    const NAME: &'static str; // benchmark の出力などに使う実装の名前
+
+    fn new() -> Self;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn iter(&self) -> Self::Iter<'_>;
+    fn head(&self) -> Option<Self::Handle>;
+    fn tail(&self) -> Option<Self::Handle>;
+    fn next(&self, h: Self::Handle) -> Option<Self::Handle>;
+    fn prev(&self, h: Self::Handle) -> Option<Self::Handle>;
+    fn nth(&self, i: usize) -> Option<Self::Handle>;
+    // 後ろから数えて i 番目。末尾から辿れる List は上書きする
+    fn nth_back(&self, i: usize) -> Option<Self::Handle> {
+        let len = self.len();
+        if i < len {
+            self.nth(len - 1 - i)
+        } else {
+            None
+        }
+    }
+    fn position(&self, h: Self::Handle) -> Option<usize>;
+    fn get(&self, h: Self::Handle) -> Option<&T>;
+    fn get_mut(&mut self, h: Self::Handle) -> Option<&mut T>;
+    // h の直前に挿入して、挿入したノードを返す
+    fn insert(&mut self, h: Self::Handle, value: T) -> Option<Self::Handle>;
+    fn push_back(&mut self, value: T) -> Self::Handle;
+    fn push_front(&mut self, value: T) -> Self::Handle;
+    fn remove(&mut self, h: Self::Handle) -> Option<T>;
+    fn pop_front(&mut self) -> Option<T> {
+        let head = self.head()?;
+        self.remove(head)
+    }
+    fn pop_back(&mut self) -> Option<T> {
+        let tail = self.tail()?;
+        self.remove(tail)
+    }
+    fn validate(&self) -> Report;
+    // ノードを確保している Pool の使用量
+    fn memory(&self) -> Usage;
+}
+
+#[cfg(test)]
+fn exercise<L: LinkedList<usize>>() {
+    let mut list = L::new();
+    assert!(list.is_empty());
+    assert!(list.head().is_none());
+    assert!(list.tail().is_none());
+
+    let h2 = list.push_back(2);
+    let h0 = list.push_front(0);
+    let h1 = list.insert(h2, 1).unwrap();
+    let h3 = list.push_back(3);
+    assert_eq!(list.len(), 4);
+    assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    assert_eq!(
+        list.iter().rev().copied().collect::<Vec<_>>(),
+        vec![3, 2, 1, 0]
+    );
+    assert_eq!(list.get(list.head().unwrap()), Some(&0));
+    assert_eq!(list.get(list.tail().unwrap()), Some(&3));
+    assert_eq!(list.position(h1), Some(1));
+    assert_eq!(list.position(list.nth(2).unwrap()), Some(2));
+    assert!(list.nth(4).is_none());
+
+    // 端では None になる
+    assert_eq!(list.get(list.next(h0).unwrap()), Some(&1));
+    assert_eq!(list.get(list.prev(h3).unwrap()), Some(&2));
+    assert!(list.prev(h0).is_none());
+    assert!(list.next(h3).is_none());
+
+    *list.get_mut(h2).unwrap() = 20;
+    assert_eq!(list.remove(h1), Some(1));
+    assert!(list.get(h1).is_none());
+    assert!(list.remove(h1).is_none());
+    assert_eq!(list.get(list.next(h0).unwrap()), Some(&20));
+    assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![0, 20, 3]);
+    list.validate().assert_ok();
+    // sentinel の分だけ多い
+    assert_eq!(list.memory().live, 3 + 1);
+    assert_ne!(list.memory().bytes, Some(0));
+    assert!(list.memory().peak >= list.memory().bytes);
+
+    // 別のリストの Handle は使えない
+    let mut other = L::new();
+    let foreign = other.push_back(9);
+    assert!(list.get(foreign).is_none());
+    assert!(list.insert(foreign, 5).is_none());
+    assert!(list.remove(foreign).is_none());
+    assert_eq!(list.len(), 3);
+    assert_eq!(other.len(), 1);
+}
+
+// nth / nth_back / position は挿入や削除の後も互いに一致する
+#[cfg(test)]
+fn exercise_nth<L: LinkedList<usize>>() {
+    let mut list = L::new();
+    for i in 0..5 {
+        list.push_back(i);
+    }
+    assert_eq!(list.get(list.nth(0).unwrap()), Some(&0));
+    assert_eq!(list.get(list.nth(4).unwrap()), Some(&4));
+    assert!(list.nth(5).is_none());
+    assert_eq!(list.get(list.nth_back(0).unwrap()), Some(&4));
+    assert_eq!(list.get(list.nth_back(3).unwrap()), Some(&1));
+    assert!(list.nth_back(5).is_none());
+
+    let h = list.nth(3).unwrap();
+    assert_eq!(list.position(h), Some(3));
+    list.push_front(100);
+    assert!(list.insert(h, 200).is_some());
+    assert_eq!(list.len(), 7);
+    assert_eq!(list.position(h), Some(5));
+    assert_eq!(list.get(list.nth(4).unwrap()), Some(&200));
+    assert_eq!(list.remove(h), Some(3));
+    assert_eq!(list.len(), 6);
+    assert_eq!(list.position(h), None);
+    let len = list.len();
+    for i in 0..len {
+        assert_eq!(list.position(list.nth(i).unwrap()), Some(i));
+        assert_eq!(list.position(list.nth_back(i).unwrap()), Some(len - 1 - i));
+    }
+}
+
+// pop_front / pop_back で両端から値を取り出す。空なら None
+#[cfg(test)]
+fn exercise_pop<L: LinkedList<usize>>() {
+    let mut list = L::new();
+    assert!(list.pop_front().is_none());
+    assert!(list.pop_back().is_none());
+
+    list.push_back(2);
+    let h3 = list.push_back(3);
+    list.push_front(1);
+    // キューとして使う
+    list.push_back(4);
+    assert_eq!(list.pop_front(), Some(1));
+    assert_eq!(list.pop_front(), Some(2));
+    // スタックとして使う
+    list.push_back(5);
+    assert_eq!(list.pop_back(), Some(5));
+    assert_eq!(list.pop_back(), Some(4));
+    assert_eq!(list.len(), 1);
+    assert_eq!(list.pop_back(), Some(3));
+    assert!(list.get(h3).is_none());
+    assert!(list.pop_front().is_none());
+    assert!(list.pop_back().is_none());
+    assert!(list.is_empty());
+    list.validate().assert_ok();
+}
+
+// Iter / IntoIter / FromIterator / Extend は trait の外にあるが、どの List でも同じように振る舞う
+#[cfg(test)]
+fn exercise_iter<L>()
+where
+    L: LinkedList<usize> + FromIterator<usize> + Extend<usize> + IntoIterator<Item = usize>,
+    L::IntoIter: DoubleEndedIterator + ExactSizeIterator,
+    for<'a> &'a L: IntoIterator<Item = &'a usize>,
+{
+    let mut list: L = (1..=4).collect();
+    assert_eq!(list.len(), 4);
+    assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    assert_eq!(
+        list.iter().rev().copied().collect::<Vec<_>>(),
+        vec![4, 3, 2, 1]
+    );
+    assert_eq!((&list).into_iter().sum::<usize>(), 10);
+
+    {
+        // Self::Iter は Drop を持つかもしれないので、借用をブロックの中で終わらせる
+        let mut iter = list.iter();
+        assert_eq!(iter.size_hint(), (4, Some(4)));
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&4));
+        assert_eq!(iter.size_hint(), (2, Some(2)));
+        assert_eq!(iter.next_back(), Some(&3));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    list.extend(vec![5, 6]);
+    assert_eq!(list.len(), 6);
+    list.remove(list.head().unwrap());
+    assert_eq!(list.len(), 5);
+
+    let mut iter = list.into_iter();
+    assert_eq!(iter.len(), 5);
+    assert_eq!(iter.next_back(), Some(6));
+    assert_eq!(iter.collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+}
+
+// test10 以外の List は &mut List からも値を書き換えながら辿れる
+#[cfg(test)]
+fn exercise_iter_mut<L>()
+where
+    L: LinkedList<usize> + FromIterator<usize>,
+    for<'a> &'a mut L: IntoIterator<Item = &'a mut usize>,
+    for<'a> <&'a mut L as IntoIterator>::IntoIter: DoubleEndedIterator,
+{
+    let mut list: L = (1..=4).collect();
+    for value in &mut list {
+        *value *= 10;
+    }
+    (&mut list)
+        .into_iter()
+        .rev()
+        .take(1)
+        .for_each(|value| *value += 1);
+    assert_eq!(
+        list.iter().copied().collect::<Vec<_>>(),
+        vec![10, 20, 30, 41]
+    );
+}
+
+// test05 / test06 / test07 の CursorMut は型が違うだけで移動と挿入・削除は同じ API を持つので、テストではこの trait を通して確かめる。
+// split_after / splice_after は test05 にしか無いので test05 のテストで確かめる
+#[cfg(test)]
+trait Cursor<L> {
+    fn move_next(&mut self);
+    fn move_prev(&mut self);
+    fn current(&mut self) -> Option<&mut usize>;
+    fn insert_before(&mut self, value: usize);
+    fn insert_after(&mut self, value: usize);
+    fn remove_current(&mut self) -> Option<usize>;
+}
+
+#[cfg(test)]
+trait CursorList: LinkedList<usize> {
+    type CursorMut<'a>: Cursor<Self>
+    where
+        Self: 'a;
+    fn cursor_front_mut(&mut self) -> Self::CursorMut<'_>;
+    fn cursor_back_mut(&mut self) -> Self::CursorMut<'_>;
+    fn cursor_mut(&mut self, h: Self::Handle) -> Option<Self::CursorMut<'_>>;
+}
+
+// 同名の inherent method に転送するだけの impl を各 List に用意する
+#[cfg(test)]
+macro_rules! impl_cursor_list {
+    ($module:ident) => {
+        impl<'a> Cursor<crate::$module::List<usize>> for crate::$module::CursorMut<'a, usize> {
+            fn move_next(&mut self) {
+                Self::move_next(self)
+            }
+            fn move_prev(&mut self) {
+                Self::move_prev(self)
+            }
+            fn current(&mut self) -> Option<&mut usize> {
+                Self::current(self)
+            }
+            fn insert_before(&mut self, value: usize) {
+                Self::insert_before(self, value)
+            }
+            fn insert_after(&mut self, value: usize) {
+                Self::insert_after(self, value)
+            }
+            fn remove_current(&mut self) -> Option<usize> {
+                Self::remove_current(self)
+            }
+        }
+        impl CursorList for crate::$module::List<usize> {
+            type CursorMut<'a> = crate::$module::CursorMut<'a, usize>;
+            fn cursor_front_mut(&mut self) -> Self::CursorMut<'_> {
+                Self::cursor_front_mut(self)
+            }
+            fn cursor_back_mut(&mut self) -> Self::CursorMut<'_> {
+                Self::cursor_back_mut(self)
+            }
+            fn cursor_mut(&mut self, h: Self::Handle) -> Option<Self::CursorMut<'_>> {
+                Self::cursor_mut(self, h)
+            }
+        }
+    };
+}
+#[cfg(test)]
+impl_cursor_list!(test05_nodes_in_hashmap);
+#[cfg(test)]
+impl_cursor_list!(test06_nodes_in_mepoo);
+#[cfg(test)]
+impl_cursor_list!(test07_nodes_in_arena);
+
+#[cfg(test)]
+fn exercise_cursor<L: CursorList + FromIterator<usize>>() {
+    let values = |list: &L| list.iter().copied().collect::<Vec<_>>();
+    let mut list: L = (1..=5).collect();
+    let h3 = list.nth(2).unwrap();
+    {
+        let mut cursor = list.cursor_front_mut();
+        assert_eq!(cursor.current(), Some(&mut 1));
+        cursor.move_prev();
+        assert_eq!(cursor.current(), None); // sentinel
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&mut 5));
+        cursor.move_next();
+        cursor.move_next();
+        *cursor.current().unwrap() = 10;
+        cursor.insert_before(0);
+        cursor.insert_after(2);
+    }
+    assert_eq!(values(&list), vec![0, 10, 2, 2, 3, 4, 5]);
+    assert_eq!(list.len(), 7);
+
+    {
+        let mut cursor = list.cursor_mut(h3).unwrap();
+        assert_eq!(cursor.remove_current(), Some(3));
+        assert_eq!(cursor.current(), Some(&mut 4));
+        cursor.move_next();
+        cursor.move_next();
+        // sentinel は削除できない
+        assert_eq!(cursor.remove_current(), None);
+    }
+    assert_eq!(values(&list), vec![0, 10, 2, 2, 4, 5]);
+    assert_eq!(list.len(), 6);
+    assert!(list.cursor_mut(h3).is_none());
+    {
+        let mut cursor = list.cursor_back_mut();
+        assert_eq!(cursor.current(), Some(&mut 5));
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+    }
+    list.validate().assert_ok();
+}
+
+// list1 の 2 番目のノードを list2 の先頭と丸ごと入れ替えると、前後のリンクが list2 を指すようになる。
+// swap はノードの中身を入れ替える List ごとの手段で、reason はそのとき validate が報告する理由
+#[cfg(test)]
+pub fn exercise_validate<L>(swap: impl Fn(&mut L, L::Handle, &mut L, L::Handle), reason: &str)
+where
+    L: LinkedList<usize> + FromIterator<usize>,
+{
+    use crate::validate::Violation;
+    let mut list1: L = (1..=3).collect();
+    let mut list2: L = (4..=5).collect();
+    list1.validate().assert_ok();
+    list2.validate().assert_ok();
+
+    let h1 = list1.nth(1).unwrap();
+    let h2 = list2.head().unwrap();
+    swap(&mut list1, h1, &mut list2, h2);
+    assert_eq!(
+        list1.validate().violations,
+        vec![
+            Violation::InvalidPtr {
+                at: 2,
+                link: "prev",
+                reason: reason.to_string()
+            },
+            Violation::InvalidPtr {
+                at: 2,
+                link: "next",
+                reason: reason.to_string()
+            },
+        ]
+    );
+    assert!(!list2.validate().is_ok());
+    swap(&mut list1, h1, &mut list2, h2);
+    list1.validate().assert_ok();
+    list2.validate().assert_ok();
+}
+
+#[test]
+fn test_hashmap_list() {
+    type L = crate::test05_nodes_in_hashmap::List<usize>;
+    exercise::<L>();
+    exercise_nth::<L>();
+    exercise_pop::<L>();
+    exercise_iter::<L>();
+    exercise_iter_mut::<L>();
+    exercise_cursor::<L>();
+}
+
+#[test]
+fn test_mepoo_list() {
+    type L = crate::test06_nodes_in_mepoo::List<usize>;
+    exercise::<L>();
+    exercise_nth::<L>();
+    exercise_pop::<L>();
+    exercise_iter::<L>();
+    exercise_iter_mut::<L>();
+    exercise_cursor::<L>();
+}
+
+#[test]
+fn test_arena_list() {
+    type L = crate::test07_nodes_in_arena::List<usize>;
+    exercise::<L>();
+    exercise_nth::<L>();
+    exercise_pop::<L>();
+    exercise_iter::<L>();
+    exercise_iter_mut::<L>();
+    exercise_cursor::<L>();
+}
+
+#[test]
+fn test_slab_list() {
+    type L = crate::test10_nodes_in_slab::List<usize>;
+    exercise::<L>();
+    exercise_nth::<L>();
+    exercise_pop::<L>();
+    exercise_iter::<L>();
+}
+
+#[test]
+fn test_persistent_list() {
+    type L = crate::test13_persistent::List<usize>;
+    exercise_nth::<L>();
+    exercise_pop::<L>();
+}
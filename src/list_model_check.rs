@@ -0,0 +1,354 @@
+#![cfg(test)]
+// main.rs の benchmark と同じようにランダムな操作を各 List に適用し、VecDeque と結果を比べる。
+// 失敗したら操作列を縮めて最小の再現手順を報告する。
+use crate::linked_list::LinkedList;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+const CASES: u64 = if cfg!(miri) { 4 } else { 200 };
+const STEPS: usize = if cfg!(miri) { 30 } else { 200 };
+
+// 位置は長さ（+1）で割った余りを使うので、操作列を縮めてもそのまま適用できる
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    PushBack(usize),
+    PushFront(usize),
+    Insert(usize, usize), // 位置, 値。位置が長さと等しければ末尾
+    Remove(usize),
+    PopFront,
+    PopBack,
+    Set(usize, usize),
+    // 以下は benchmark と同じく手元の Ptr を辿って操作する
+    Move(bool), // true なら next、false なら prev
+    InsertHere(usize),
+    RemoveHere,
+}
+
+fn random_ops(rng: &mut StdRng, n: usize) -> Vec<Op> {
+    (0..n)
+        .map(|_| {
+            let i = rng.gen_range(0, 100);
+            let v = rng.gen_range(0, 1000);
+            match rng.gen_range(0, 10) {
+                0 => Op::PushBack(v),
+                1 => Op::PushFront(v),
+                2 => Op::Insert(i, v),
+                3 => Op::Remove(i),
+                4 => Op::PopFront,
+                5 => Op::PopBack,
+                6 => Op::Set(i, v),
+                7 => Op::Move(rng.gen()),
+                8 => Op::InsertHere(v),
+                _ => Op::RemoveHere,
+            }
+        })
+        .collect()
+}
+
+// 参照モデル。current は手元の Ptr が指す位置で、len なら sentinel を指している。
+struct Model {
+    deque: VecDeque<usize>,
+    current: usize,
+}
+impl Model {
+    fn new() -> Self {
+        Self {
+            deque: VecDeque::new(),
+            current: 0,
+        }
+    }
+    fn remove(&mut self, i: usize) {
+        self.deque.remove(i);
+        if i < self.current {
+            self.current -= 1;
+        }
+    }
+    fn insert(&mut self, i: usize, v: usize) {
+        self.deque.insert(i, v);
+        if i <= self.current {
+            self.current += 1;
+        }
+    }
+    fn apply(&mut self, op: Op) {
+        let len = self.deque.len();
+        match op {
+            Op::PushBack(v) => self.insert(len, v),
+            Op::PushFront(v) => self.insert(0, v),
+            Op::Insert(i, v) => self.insert(i % (len + 1), v),
+            Op::Remove(i) if len > 0 => self.remove(i % len),
+            Op::PopFront if len > 0 => self.remove(0),
+            Op::PopBack if len > 0 => self.remove(len - 1),
+            Op::Set(i, v) if len > 0 => self.deque[i % len] = v,
+            Op::Move(true) => self.current = (self.current + 1) % (len + 1),
+            Op::Move(false) => self.current = (self.current + len) % (len + 1),
+            Op::InsertHere(v) => self.insert(self.current, v),
+            Op::RemoveHere if self.current < len => self.remove(self.current),
+            _ => {}
+        }
+    }
+    fn current(&self) -> Option<usize> {
+        self.deque.get(self.current).copied()
+    }
+}
+
+// 検査対象の List と手元の Ptr
+trait Subject {
+    fn new() -> Self;
+    fn apply(&mut self, op: Op);
+    fn current(&self) -> Option<usize>;
+    // head から next で、tail から prev で辿って値を集める。リンクが壊れていたら Err
+    fn walk(&self) -> Result<Vec<usize>, String>;
+}
+
+// LinkedList を実装した List と手元の Handle。current が None なら sentinel を指している
+struct Checked<L: LinkedList<usize>> {
+    list: L,
+    current: Option<L::Handle>,
+}
+impl<L: LinkedList<usize>> Checked<L> {
+    // i 番目のノードを削除する前に、手元の Handle がそれを指していれば次に進める。
+    // Handle は比較できるとは限らないので位置で比べる
+    fn leave(&mut self, i: usize) {
+        if self.current.and_then(|c| self.list.position(c)) == Some(i) {
+            self.current = self.list.next(self.list.nth(i).unwrap());
+        }
+    }
+    fn remove(&mut self, i: usize) {
+        self.leave(i);
+        let h = self.list.nth(i).unwrap();
+        assert!(self.list.remove(h).is_some());
+    }
+}
+impl<L: LinkedList<usize>> Subject for Checked<L> {
+    fn new() -> Self {
+        Self {
+            list: L::new(),
+            current: None,
+        }
+    }
+    fn apply(&mut self, op: Op) {
+        let len = self.list.len();
+        match op {
+            Op::PushBack(v) => {
+                self.list.push_back(v);
+            }
+            Op::PushFront(v) => {
+                self.list.push_front(v);
+            }
+            Op::Insert(i, v) => match self.list.nth(i % (len + 1)) {
+                Some(h) => assert!(self.list.insert(h, v).is_some()),
+                None => {
+                    self.list.push_back(v);
+                }
+            },
+            Op::Remove(i) if len > 0 => self.remove(i % len),
+            Op::PopFront => {
+                if len > 0 {
+                    self.leave(0);
+                }
+                assert_eq!(self.list.pop_front().is_some(), len > 0);
+            }
+            Op::PopBack => {
+                if len > 0 {
+                    self.leave(len - 1);
+                }
+                assert_eq!(self.list.pop_back().is_some(), len > 0);
+            }
+            Op::Set(i, v) if len > 0 => {
+                let h = self.list.nth(i % len).unwrap();
+                *self.list.get_mut(h).unwrap() = v;
+            }
+            Op::Move(forward) => {
+                self.current = match (self.current, forward) {
+                    (Some(h), true) => self.list.next(h),
+                    (Some(h), false) => self.list.prev(h),
+                    (None, true) => self.list.head(),
+                    (None, false) => self.list.tail(),
+                };
+            }
+            Op::InsertHere(v) => match self.current {
+                Some(h) => assert!(self.list.insert(h, v).is_some()),
+                None => {
+                    self.list.push_back(v);
+                }
+            },
+            Op::RemoveHere => {
+                if let Some(h) = self.current {
+                    self.current = self.list.next(h);
+                    assert!(self.list.remove(h).is_some());
+                }
+            }
+            _ => {}
+        }
+    }
+    fn current(&self) -> Option<usize> {
+        self.current.and_then(|h| self.list.get(h)).copied()
+    }
+    fn walk(&self) -> Result<Vec<usize>, String> {
+        let report = self.list.validate();
+        if !report.is_ok() {
+            return Err(report.to_string());
+        }
+        // validate で一周できることは分かっているので、Handle と iter のどちらで辿っても止まる
+        let mut forward = Vec::new();
+        let mut h = self.list.head();
+        while let Some(node) = h {
+            forward.push(*self.list.get(node).unwrap());
+            h = self.list.next(node);
+        }
+        let mut backward = Vec::new();
+        let mut h = self.list.tail();
+        while let Some(node) = h {
+            backward.push(*self.list.get(node).unwrap());
+            h = self.list.prev(node);
+        }
+        backward.reverse();
+        let iter = self.list.iter().copied().collect::<Vec<_>>();
+        if forward != backward || forward != iter {
+            Err(format!(
+                "forward {:?}, backward {:?} and iter {:?} differ",
+                forward, backward, iter
+            ))
+        } else if forward.len() != self.list.len() {
+            Err(format!(
+                "len() is {} but {} nodes are linked",
+                self.list.len(),
+                forward.len()
+            ))
+        } else {
+            Ok(forward)
+        }
+    }
+}
+
+// 操作列を最初から適用して、各ステップで VecDeque と一致するか確かめる
+fn run<S: Subject>(ops: &[Op]) -> Result<(), String> {
+    let mut model = Model::new();
+    let mut subject = S::new();
+    for (step, &op) in ops.iter().enumerate() {
+        model.apply(op);
+        catch_unwind(AssertUnwindSafe(|| subject.apply(op)))
+            .map_err(|_| format!("step {}: {:?} panicked", step, op))?;
+        let values = subject
+            .walk()
+            .map_err(|e| format!("step {}: {:?}: {}", step, op, e))?;
+        let expected = model.deque.iter().copied().collect::<Vec<_>>();
+        if values != expected {
+            return Err(format!(
+                "step {}: {:?}: expected {:?} but got {:?}",
+                step, op, expected, values
+            ));
+        }
+        if subject.current() != model.current() {
+            return Err(format!(
+                "step {}: {:?}: current should be {:?} but is {:?}",
+                step,
+                op,
+                model.current(),
+                subject.current()
+            ));
+        }
+    }
+    Ok(())
+}
+
+// fails が true を返す範囲で操作列をできるだけ短く、値をできるだけ単純にする
+fn shrink(ops: &[Op], fails: impl Fn(&[Op]) -> bool) -> Vec<Op> {
+    let mut ops = ops.to_vec();
+    let mut chunk = ops.len() / 2;
+    while chunk > 0 {
+        let mut removed = false;
+        let mut i = 0;
+        while i + chunk <= ops.len() {
+            let mut candidate = ops.clone();
+            candidate.drain(i..i + chunk);
+            if fails(&candidate) {
+                ops = candidate;
+                removed = true;
+            } else {
+                i += chunk;
+            }
+        }
+        if !removed {
+            chunk /= 2;
+        }
+    }
+    for i in 0..ops.len() {
+        let simple = match ops[i] {
+            Op::PushBack(_) => Op::PushBack(0),
+            Op::PushFront(_) => Op::PushFront(0),
+            Op::Insert(_, _) => Op::Insert(0, 0),
+            Op::Remove(_) => Op::Remove(0),
+            Op::Set(_, _) => Op::Set(0, 0),
+            Op::InsertHere(_) => Op::InsertHere(0),
+            op => op,
+        };
+        if simple != ops[i] {
+            let mut candidate = ops.clone();
+            candidate[i] = simple;
+            if fails(&candidate) {
+                ops = candidate;
+            }
+        }
+    }
+    ops
+}
+
+fn model_check<S: Subject>(name: &str) {
+    for seed in 0..CASES {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let ops = random_ops(&mut rng, STEPS);
+        if let Err(e) = run::<S>(&ops) {
+            let minimal = shrink(&ops, |ops| run::<S>(ops).is_err());
+            panic!(
+                "{}: seed {} failed: {}\nminimal reproducer: {:?}\n{}",
+                name,
+                seed,
+                e,
+                minimal,
+                run::<S>(&minimal).unwrap_err()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_hashmap_list() {
+    model_check::<Checked<crate::test05_nodes_in_hashmap::List<usize>>>("test05_nodes_in_hashmap");
+}
+
+#[test]
+fn test_mepoo_list() {
+    model_check::<Checked<crate::test06_nodes_in_mepoo::List<usize>>>("test06_nodes_in_mepoo");
+}
+
+#[test]
+fn test_arena_list() {
+    model_check::<Checked<crate::test07_nodes_in_arena::List<usize>>>("test07_nodes_in_arena");
+}
+
+#[test]
+fn test_slab_list() {
+    model_check::<Checked<crate::test10_nodes_in_slab::List<usize>>>("test10_nodes_in_slab");
+}
+
+#[test]
+fn test_persistent_list() {
+    model_check::<Checked<crate::test13_persistent::List<usize>>>("test13_persistent");
+}
+
+#[test]
+fn test_shrink() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let ops = random_ops(&mut rng, STEPS);
+    let push_backs = |ops: &[Op]| {
+        ops.iter()
+            .filter(|op| matches!(op, Op::PushBack(_)))
+            .count()
+    };
+    assert!(push_backs(&ops) >= 3);
+    let minimal = shrink(&ops, |ops| push_backs(ops) >= 3);
+    assert_eq!(minimal, vec![Op::PushBack(0); 3]);
+}
@@ -1,3 +1,8 @@
+mod bench;
+mod cli;
+mod linked_list;
+mod list_model_check;
+mod memory;
 mod test01_box_and_ptr;
 mod test02_single_node_graph;
 mod test03_two_nodes_graph;
@@ -5,104 +10,73 @@ mod test04_nodes_in_vec;
 mod test05_nodes_in_hashmap;
 mod test06_nodes_in_mepoo;
 mod test07_nodes_in_arena;
+mod test08_graph_in_arena;
+mod test09_half_edge_mesh;
+mod test10_nodes_in_slab;
+mod test11_concurrent_list;
+mod test12_lock_free_queue;
+mod test13_persistent;
+mod test14_transaction;
+mod validate;
 
-use rand::Rng;
-const N: usize = 1000000;
+use bench::targets::*;
+use bench::{Config, Record, Target, Workload};
+use cli::Command;
 
-fn benchmark_05() {
-    use test05_nodes_in_hashmap::*;
-    let mut random = rand::thread_rng();
-    let mut list: List<usize> = List::new();
-    for i in 0..N {
-        list.push_back(i);
-    }
-    let mut ptr = list.head() as *const Node<_>;
-    for k in 0..N {
-        if random.gen::<i32>() % 2 == 0 {
-            if let Some(node) = list.get_ref(ptr) {
-                ptr = match random.gen::<i32>() % 2 {
-                    0 => node.next() as *const _,
-                    _ => node.prev() as *const _,
-                };
-            }
-        } else {
-            if random.gen::<i32>() % 2 == 0 {
-                list.insert(ptr, k);
-            } else if let Some(node) = list.remove(ptr) {
-                ptr = node as *const _;
-            } else {
-                ptr = list.head() as *const _;
-            }
-        }
-    }
+fn run<L: Target>(workload: Workload, config: &Config) -> Record {
+    let record = bench::run::<L>(workload, config);
+    let peak = record.heap.map(|(_, peak)| peak).or(record.memory.peak);
+    eprintln!(
+        "{:?} (median of {}), peak {} @ {} {}",
+        record.stats.median,
+        config.samples,
+        peak.map_or("unknown".to_string(), |peak| format!("{} bytes", peak)),
+        L::NAME,
+        workload.name()
+    );
+    record
 }
 
-fn benchmark_06() {
-    use mepoo::*;
-    use test06_nodes_in_mepoo::*;
-    let mut random = rand::thread_rng();
-    let mut list: List<usize> = List::new();
-    for i in 0..N {
-        list.push_back(i);
-    }
-    let mut ptr: Ptr<_> = list.head().into();
-    for k in 0..N {
-        if random.gen::<i32>() % 2 == 0 {
-            if let Some(node) = list.get_ref(ptr) {
-                ptr = match random.gen::<i32>() % 2 {
-                    0 => node.next().into(),
-                    _ => node.prev().into(),
-                };
-            }
-        } else {
-            if random.gen::<i32>() % 2 == 0 {
-                list.insert(ptr, k);
-            } else if let Some(node) = list.remove(ptr) {
-                ptr = node.into();
-            } else {
-                ptr = list.head().into();
-            }
-        }
-    }
-}
+// 一つの target を一つの workload で測る関数
+type Runner = fn(Workload, &Config) -> Record;
 
-fn benchmark_07() {
-    use test07_nodes_in_arena::list::*;
-    use test07_nodes_in_arena::pool::*;
-    let mut random = rand::thread_rng();
-    let mut list: List<usize> = List::new();
-    for i in 0..N {
-        list.push_back(i);
+const TARGETS: [(&str, Runner); 5] = [
+    (HashMapList::NAME, run::<HashMapList>),
+    (MepooList::NAME, run::<MepooList>),
+    (ArenaList::NAME, run::<ArenaList>),
+    (SlabList::NAME, run::<SlabList>),
+    (PersistentList::NAME, run::<PersistentList>),
+];
+
+fn list() {
+    println!("targets:");
+    for (name, _) in TARGETS.iter() {
+        println!("  {}", name);
     }
-    let mut ptr: Ptr<_> = list.head().into();
-    for k in 0..N {
-        if random.gen::<i32>() % 2 == 0 {
-            if let Some(node) = list.get(ptr) {
-                ptr = match random.gen::<i32>() % 2 {
-                    0 => node.next().into(),
-                    _ => node.prev().into(),
-                };
-            }
-        } else {
-            if random.gen::<i32>() % 2 == 0 {
-                list.insert(ptr, k);
-            } else if let Some(node) = list.remove(ptr) {
-                ptr = node.into();
-            } else {
-                ptr = list.head().into();
-            }
-        }
+    println!("workloads (move/insert/remove %):");
+    for workload in Workload::ALL.iter() {
+        let [moves, inserts, removes] = workload.mix();
+        println!("  {:<14}{}/{}/{}", workload.name(), moves, inserts, removes);
     }
 }
 
-fn run(action: impl Fn(), caption: &str) {
-    let instant = std::time::Instant::now();
-    action();
-    println!("{:?} @ {}", instant.elapsed(), caption);
-}
-
 fn main() {
-    run(benchmark_05, "benchmark 05");
-    run(benchmark_06, "benchmark 06");
-    run(benchmark_07, "benchmark 07");
+    let names = TARGETS.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    let options = match cli::parse(std::env::args().skip(1), &names) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::List) => return list(),
+        Ok(Command::Help) => return print!("{}", cli::USAGE),
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let mut records = Vec::new();
+    for &workload in &options.workloads {
+        for target in &options.targets {
+            let (_, run) = TARGETS.iter().find(|(name, _)| name == target).unwrap();
+            records.push(run(workload, &options.config));
+        }
+    }
+    bench::write(&records, options.format, &mut std::io::stdout()).unwrap();
 }
@@ -0,0 +1,106 @@
+// 各 Pool のメモリ使用量と、数を数えるだけの global allocator
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// bytes はノードと管理用の領域の大きさから計算した値で、allocator の端数などは含まない。
+// mepoo のように Pool が確保した量を外から知る方法が無いときは None にし、count-alloc の heap の値で比べる
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub live: usize,          // 使用中のオブジェクトの数（sentinel を含む）
+    pub free: usize,          // 解放されて再利用を待っているスロットの数
+    pub bytes: Option<usize>, // 今確保しているバイト数
+    pub peak: Option<usize>,  // bytes の最大値
+}
+
+// System に任せて、確保しているバイト数とその最大値を数える
+pub struct Counting {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+impl Counting {
+    #[cfg(any(test, feature = "count-alloc"))]
+    pub const fn new() -> Self {
+        Self {
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+    // ここから先の最大値を測るために peak を今の値に戻す
+    pub fn reset_peak(&self) {
+        self.peak.store(self.current(), Ordering::Relaxed);
+    }
+    fn grow(&self, size: usize) {
+        let current = self.current.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak.fetch_max(current, Ordering::Relaxed);
+    }
+    fn shrink(&self, size: usize) {
+        self.current.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            self.grow(layout.size());
+        }
+        ptr
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.grow(layout.size());
+        }
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.shrink(layout.size());
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.shrink(layout.size());
+            self.grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+// feature "count-alloc" を有効にしてビルドしたときだけ global allocator にする
+#[cfg(feature = "count-alloc")]
+#[global_allocator]
+static ALLOCATOR: Counting = Counting::new();
+
+pub fn allocator() -> Option<&'static Counting> {
+    #[cfg(feature = "count-alloc")]
+    return Some(&ALLOCATOR);
+    #[cfg(not(feature = "count-alloc"))]
+    None
+}
+
+#[test]
+fn test_counting() {
+    let counting = Counting::new();
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let p = counting.alloc(layout);
+        assert_eq!((counting.current(), counting.peak()), (64, 64));
+        let q = counting.alloc_zeroed(layout);
+        assert_eq!(*q, 0);
+        assert_eq!((counting.current(), counting.peak()), (128, 128));
+        counting.dealloc(q, layout);
+        assert_eq!((counting.current(), counting.peak()), (64, 128));
+
+        counting.reset_peak();
+        let p = counting.realloc(p, layout, 256);
+        assert_eq!((counting.current(), counting.peak()), (256, 256));
+        counting.dealloc(p, Layout::from_size_align(256, 8).unwrap());
+        assert_eq!((counting.current(), counting.peak()), (0, 256));
+    }
+}
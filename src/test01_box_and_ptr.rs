@@ -1,8 +1,38 @@
 #![cfg(test)]
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
 
 struct Data(usize);
 
+// Box は move される度に「唯一の参照」として扱われるので、move する前に作った生ポインタは
+// Stacked Borrows / Tree Borrows の上では無効になる（アドレスは変わらないのに Miri に怒られる）。
+// 生ポインタと一緒に持ち回りたいときは Box::into_raw したポインタで所有する。
+pub struct AliasableBox<T>(NonNull<T>);
+impl<T> AliasableBox<T> {
+    pub fn new(value: T) -> Self {
+        Self(unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(value))) })
+    }
+    pub fn as_ptr(&self) -> *mut T {
+        self.0.as_ptr()
+    }
+}
+impl<T> Deref for AliasableBox<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.0.as_ptr() }
+    }
+}
+impl<T> DerefMut for AliasableBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0.as_ptr() }
+    }
+}
+impl<T> Drop for AliasableBox<T> {
+    fn drop(&mut self) {
+        unsafe { drop(Box::from_raw(self.0.as_ptr())) }
+    }
+}
+
 #[test]
 fn test01_0() {
     let obj1: Data = Data(123);
@@ -14,6 +44,9 @@ fn test01_0() {
     assert_ne!(ptr1, ptr2);
 }
 
+// move した後に ptr1 を介して読むのは Stacked Borrows 違反なので Miri では動かさない。
+// Miri でも動く書き方は test01_1_aliasable を参照
+#[cfg(not(miri))]
 #[test]
 fn test01_1() {
     let obj1: Box<Data> = Box::new(Data(123));
@@ -33,6 +66,23 @@ fn test01_1() {
     assert_eq!(ref2.0, 123);
 }
 
+#[test]
+fn test01_1_aliasable() {
+    let obj1: AliasableBox<Data> = AliasableBox::new(Data(123));
+    let ptr1 = obj1.as_ptr() as *const Data;
+
+    let obj2: AliasableBox<Data> = obj1;
+    let ptr2 = obj2.as_ptr() as *const Data;
+
+    // AliasableBox なら move した後でも ptr1 を使える
+    assert_eq!(ptr1, ptr2);
+    assert_eq!(unsafe { (*ptr1).0 }, 123);
+    assert_eq!(unsafe { (*ptr2).0 }, 123);
+
+    let ref1: &Data = unsafe { &*ptr1 };
+    assert_eq!(ref1.0, 123);
+}
+
 /*
 fn not_compilable1<'a>() -> &'a X {
     let x = Box::new(X);
@@ -82,9 +132,9 @@ error[E0505]: cannot move out of `x` because it is borrowed
 mod test01_2 {
     use super::*;
 
-    fn get_obj_and_ptr() -> (Box<Data>, *const Data) {
-        let obj: Box<Data> = Box::new(Data(123));
-        let ptr = obj.deref() as *const Data;
+    fn get_obj_and_ptr() -> (AliasableBox<Data>, *const Data) {
+        let obj: AliasableBox<Data> = AliasableBox::new(Data(123));
+        let ptr = obj.as_ptr() as *const Data;
         (obj, ptr)
     }
 
@@ -93,7 +143,7 @@ mod test01_2 {
         let (obj, ptr) = get_obj_and_ptr();
         assert_eq!(obj.deref() as *const Data, ptr);
 
-        let refer: &Data = unsafe { std::mem::transmute(ptr) };
+        let refer: &Data = unsafe { &*ptr };
         assert_eq!(refer.0, 123);
     }
 }
@@ -102,21 +152,21 @@ mod test01_3 {
     use super::*;
 
     struct ObjAndPtr {
-        obj: Box<Data>,
+        obj: AliasableBox<Data>,
         ptr: *const Data,
     }
 
     impl ObjAndPtr {
         fn new(value: usize) -> Self {
-            let obj: Box<Data> = Box::new(Data(value));
-            let ptr = obj.deref() as *const Data;
+            let obj: AliasableBox<Data> = AliasableBox::new(Data(value));
+            let ptr = obj.as_ptr() as *const Data;
             Self { obj, ptr }
         }
         fn ref_safe(&self) -> &Data {
             self.obj.deref()
         }
         fn ref_unsafe(&self) -> &Data {
-            unsafe { std::mem::transmute(self.ptr) }
+            unsafe { &*self.ptr }
         }
     }
 
@@ -1,5 +1,4 @@
 #![cfg(test)]
-use std::ops::Deref;
 
 /*
 mod not_compilable {
@@ -48,32 +47,45 @@ error: aborting due to 2 previous errors
 */
 
 mod graph {
-    use super::*;
+    use crate::test01_box_and_ptr::AliasableBox;
+    use crate::validate::{Report, Violation};
 
     pub struct Node<T> {
         pub value: T,
     }
 
+    // node を Box にすると Graph を move した時点で head が無効になる（Stacked Borrows 違反）
     pub struct Graph<T> {
-        node: Box<Node<T>>,
-        head: *const Node<T>,
+        node: AliasableBox<Node<T>>,
+        head: *mut Node<T>,
     }
 
     impl<T> Graph<T> {
         pub fn new(value: T) -> Self {
-            let node = Box::new(Node { value });
-            let head = node.deref() as *const Node<T>;
+            let node = AliasableBox::new(Node { value });
+            let head = node.as_ptr();
             Self { node, head }
         }
         pub fn head(&self) -> &Node<T> {
-            unsafe { std::mem::transmute(self.head) }
+            unsafe { &*self.head }
         }
         pub fn head_mut(&mut self) -> &mut Node<T> {
-            unsafe { std::mem::transmute(self.head) }
+            unsafe { &mut *self.head }
         }
         pub fn reset_node(&mut self, value: T) {
-            self.node = Box::new(Node { value });
-            self.head = self.node.deref() as *const Node<T>; // CAUTION! これを書かないと head が dangling pointer になる！
+            self.node = AliasableBox::new(Node { value });
+            self.head = self.node.as_ptr(); // CAUTION! これを書かないと head が dangling pointer になる！
+        }
+        pub fn validate(&self) -> Report {
+            let mut report = Report::default();
+            if self.head != self.node.as_ptr() {
+                report.push(Violation::InvalidPtr {
+                    at: 0,
+                    link: "head",
+                    reason: "does not point to the owned node".to_string(),
+                });
+            }
+            report
         }
     }
 }
@@ -89,6 +101,7 @@ fn test02_1() {
 
     graph.reset_node(789);
     assert_eq!(graph.head().value, 789);
+    graph.validate().assert_ok();
 
     /*  not compilable
     let head = graph.head();
@@ -111,4 +124,5 @@ fn test02_1() {
     let moved_graph: Graph<usize> = graph;
     assert_eq!(moved_graph.head().value, 789);
     assert_eq!(moved_graph.head() as *const Node<usize>, ptr);
+    moved_graph.validate().assert_ok();
 }
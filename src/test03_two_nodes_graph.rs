@@ -85,15 +85,16 @@ mod with_cell {
 }
 
 mod graph {
-    use super::*;
+    use crate::test01_box_and_ptr::AliasableBox;
+    use crate::validate::{Report, Violation};
 
     pub struct Node<T> {
         pub value: T,
-        other: *const Self,
+        other: *mut Self,
     }
     impl<T> Node<T> {
         pub fn other(&self) -> &Self {
-            unsafe { std::mem::transmute(self.other) }
+            unsafe { &*self.other }
         }
     }
 
@@ -106,27 +107,29 @@ mod graph {
             &mut self.0.value
         }
         pub fn other(&mut self) -> Self {
-            unsafe { Self(std::mem::transmute(self.0.other)) }
+            unsafe { Self(&mut *self.0.other) }
         }
     }
 
+    // other は *const から作った参照を &mut にしたり、Box の move で無効になったりしないように
+    // AliasableBox から取り出した *mut で持つ
     pub struct Graph<T> {
-        node1: Box<Node<T>>,
-        node2: Box<Node<T>>,
+        node1: AliasableBox<Node<T>>,
+        node2: AliasableBox<Node<T>>,
     }
     impl<T> Graph<T> {
         pub fn new(value1: T, value2: T) -> Self {
-            let mut node1 = Box::new(Node {
+            let mut node1 = AliasableBox::new(Node {
                 value: value1,
-                other: std::ptr::null(), // 一旦 other は null で初期化
+                other: std::ptr::null_mut(), // 一旦 other は null で初期化
             });
-            let mut node2 = Box::new(Node {
+            let mut node2 = AliasableBox::new(Node {
                 value: value2,
-                other: std::ptr::null(), // 一旦 other は null で初期化
+                other: std::ptr::null_mut(), // 一旦 other は null で初期化
             });
             // ここで相互参照を作る
-            node1.other = node2.deref() as *const Node<T>;
-            node2.other = node1.deref() as *const Node<T>;
+            node1.other = node2.as_ptr();
+            node2.other = node1.as_ptr();
             Self { node1, node2 }
         }
         pub fn node1(&self) -> &Node<T> {
@@ -139,18 +142,35 @@ mod graph {
             NodeRefMut(&mut self.node1)
         }
         pub fn reset_node1(&mut self, value: T) {
-            self.node1 = Box::new(Node {
+            self.node1 = AliasableBox::new(Node {
                 value,
-                other: self.node2.deref() as *const Node<T>,
+                other: self.node2.as_ptr(),
             });
-            self.node2.other = self.node1.deref() as *const Node<T>;
+            self.node2.other = self.node1.as_ptr();
         }
         pub fn reset_node2(&mut self, value: T) {
-            self.node2 = Box::new(Node {
+            self.node2 = AliasableBox::new(Node {
                 value,
-                other: self.node1.deref() as *const Node<T>,
+                other: self.node1.as_ptr(),
             });
-            self.node1.other = self.node2.deref() as *const Node<T>;
+            self.node1.other = self.node2.as_ptr();
+        }
+        // node1 と node2 が互いを指しているか
+        pub fn validate(&self) -> Report {
+            let mut report = Report::default();
+            if self.node1.other != self.node2.as_ptr() {
+                report.push(Violation::BrokenLink {
+                    at: 0,
+                    link: "other",
+                });
+            }
+            if self.node2.other != self.node1.as_ptr() {
+                report.push(Violation::BrokenLink {
+                    at: 1,
+                    link: "other",
+                });
+            }
+            report
         }
     }
 }
@@ -161,6 +181,7 @@ use graph::*;
 fn test_invariant<T>(graph: &Graph<T>) {
     assert_eq!(graph.node1().other() as *const _, graph.node2() as *const _);
     assert_eq!(graph.node2().other() as *const _, graph.node1() as *const _);
+    graph.validate().assert_ok();
 }
 
 #[test]
@@ -1,8 +1,9 @@
 #![cfg(test)]
-use std::ops::DerefMut;
 
 mod list {
-    use super::*;
+    use crate::test01_box_and_ptr::AliasableBox;
+    use crate::validate::{Report, Violation};
+    use std::collections::HashSet;
 
     pub struct Node<T> {
         pub value: T,
@@ -10,12 +11,13 @@ mod list {
     }
     impl<T> Node<T> {
         pub fn next(&self) -> Option<&Self> {
-            unsafe { std::mem::transmute(self.next) }
+            unsafe { self.next.as_ref() }
         }
     }
 
+    // Vec<Box<Node>> だと push で Box を move した時点で head / tail / next が無効になる
     pub struct List<T> {
-        nodes: Vec<Box<Node<T>>>,
+        nodes: Vec<AliasableBox<Node<T>>>,
         head: *mut Node<T>,
         tail: *mut Node<T>,
     }
@@ -28,20 +30,46 @@ mod list {
             }
         }
         pub fn head(&self) -> Option<&Node<T>> {
-            unsafe { std::mem::transmute(self.head) }
+            unsafe { self.head.as_ref() }
         }
         pub fn tail(&self) -> Option<&Node<T>> {
-            unsafe { std::mem::transmute(self.tail) }
+            unsafe { self.tail.as_ref() }
         }
         pub fn is_empty(&self) -> bool {
             self.head.is_null()
         }
+        pub fn len(&self) -> usize {
+            self.nodes.len()
+        }
+        pub fn nth(&self, i: usize) -> Option<&Node<T>> {
+            let mut node = self.head();
+            for _ in 0..i {
+                node = node?.next();
+            }
+            node
+        }
+        // 片方向リストなので先頭から辿るしかない
+        pub fn nth_back(&self, i: usize) -> Option<&Node<T>> {
+            self.nth(self.len().checked_sub(i + 1)?)
+        }
+        pub fn position(&self, node: *const Node<T>) -> Option<usize> {
+            let mut current = self.head();
+            let mut i = 0;
+            while let Some(n) = current {
+                if std::ptr::eq(n, node) {
+                    return Some(i);
+                }
+                current = n.next();
+                i += 1;
+            }
+            None
+        }
         pub fn push_back(&mut self, value: T) {
-            let mut node = Box::new(Node {
+            let node = AliasableBox::new(Node {
                 value,
                 next: std::ptr::null_mut(),
             });
-            let ptr = node.deref_mut() as *mut Node<T>;
+            let ptr = node.as_ptr();
             if self.is_empty() {
                 self.head = ptr;
                 self.tail = ptr;
@@ -51,6 +79,44 @@ mod list {
             }
             self.nodes.push(node);
         }
+        // head から辿ったノードが全て nodes の中にあり、最後が tail で、数が nodes と一致するか
+        pub fn validate(&self) -> Report {
+            let mut report = Report::default();
+            let owned: HashSet<*mut Node<T>> =
+                self.nodes.iter().map(|node| node.as_ptr()).collect();
+            let mut visited = HashSet::new();
+            let mut last = std::ptr::null_mut();
+            let mut node = self.head;
+            let mut at = 0;
+            while !node.is_null() {
+                if !owned.contains(&node) {
+                    report.push(Violation::InvalidPtr {
+                        at,
+                        link: if at == 0 { "head" } else { "next" },
+                        reason: "not in this list".to_string(),
+                    });
+                    return report;
+                }
+                if !visited.insert(node) {
+                    report.push(Violation::Cycle { at });
+                    return report;
+                }
+                last = node;
+                node = unsafe { (*node).next };
+                at += 1;
+            }
+            if last != self.tail {
+                report.push(Violation::BrokenLink { at, link: "tail" });
+            }
+            if at != self.nodes.len() {
+                report.push(Violation::CountMismatch {
+                    what: "len",
+                    recorded: self.nodes.len(),
+                    actual: at,
+                });
+            }
+            report
+        }
     }
 }
 
@@ -77,6 +143,7 @@ fn test() {
     assert!(list.head().is_none());
     assert!(list.tail().is_none());
     assert!(list.is_empty());
+    list.validate().assert_ok();
 
     list.push_back(123);
     assert!(list.head().is_some());
@@ -89,4 +156,29 @@ fn test() {
     assert_eq!(list.head_f().value, 123);
     assert_eq!(list.tail_f().value, 456);
     assert_eq!(list.head_f().next_f().value, 456);
+    list.validate().assert_ok();
+}
+
+#[test]
+fn test_len_and_nth() {
+    let mut list: List<usize> = List::new();
+    assert_eq!(list.len(), 0);
+    assert!(list.nth(0).is_none());
+    assert!(list.nth_back(0).is_none());
+
+    for i in 0..5 {
+        list.push_back(i * 10);
+    }
+    assert_eq!(list.len(), 5);
+    assert_eq!(list.nth(0).unwrap().value, 0);
+    assert_eq!(list.nth(3).unwrap().value, 30);
+    assert!(list.nth(5).is_none());
+    assert_eq!(list.nth_back(0).unwrap().value, 40);
+    assert_eq!(list.nth_back(4).unwrap().value, 0);
+    assert!(list.nth_back(5).is_none());
+
+    let node = list.nth(2).unwrap() as *const Node<usize>;
+    assert_eq!(list.position(node), Some(2));
+    assert_eq!(list.position(list.tail_f()), Some(4));
+    assert_eq!(list.position(std::ptr::null()), None);
 }
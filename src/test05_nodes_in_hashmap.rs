@@ -1,59 +1,247 @@
 mod pool {
+    use crate::memory::Usage;
+    use lazy_static::lazy_static;
     use std::collections::HashMap;
-    use std::ops::{Deref, DerefMut};
+    use std::ptr::NonNull;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    lazy_static! {
+        static ref GENERATION: AtomicU64 = AtomicU64::new(1);
+    }
 
-    pub struct Pool<T>(HashMap<*const T, Box<T>>);
+    // アドレスだけで引くと、解放したアドレスを allocator が使い回したときに古いポインタが新しい
+    // オブジェクトを指してしまう（ABA）。そこで確保の度に全ての Pool で一意な世代を振り、
+    // アドレスと世代が両方一致したときだけ引けるようにする。
+    pub struct Ptr<T> {
+        ptr: *const T,
+        generation: u64,
+    }
+    impl<T> Clone for Ptr<T> {
+        fn clone(&self) -> Self {
+            *self
+        }
+    }
+    impl<T> Copy for Ptr<T> {}
+    impl<T> PartialEq for Ptr<T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.ptr == other.ptr && self.generation == other.generation
+        }
+    }
+    impl<T> Eq for Ptr<T> {}
+    impl<T> std::hash::Hash for Ptr<T> {
+        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+            self.ptr.hash(state);
+            self.generation.hash(state);
+        }
+    }
+    impl<T> std::fmt::Debug for Ptr<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.debug_struct("Ptr")
+                .field("ptr", &self.ptr)
+                .field("generation", &self.generation)
+                .finish()
+        }
+    }
+    impl<T> Ptr<T> {
+        pub const DANGLING: Self = Self {
+            ptr: std::ptr::null(),
+            generation: 0,
+        };
+        pub fn as_ptr(&self) -> *const T {
+            self.ptr
+        }
+        // 世代はそのままで ptr のアドレスを指す Ptr。アドレスが一致して世代だけが違う Ptr を
+        // テストで確かめるのに使う
+        #[cfg(test)]
+        pub fn with_address(self, ptr: *const T) -> Self {
+            Self { ptr, ..self }
+        }
+    }
+
+    // Box のまま持つと get_mut の度に Box から一意な参照が作られ、それ以前にリストが覚えた
+    // 生ポインタが Stacked Borrows / Tree Borrows の上で無効になる。
+    // そのため Box::into_raw した生ポインタで持ち、リストにも同じポインタを渡す。
+    pub struct Pool<T> {
+        map: HashMap<*const T, (NonNull<T>, u64)>,
+        peak: usize,
+    }
     impl<T> Pool<T> {
         pub fn new() -> Self {
-            Self(HashMap::new())
+            Self {
+                map: HashMap::new(),
+                peak: 0,
+            }
+        }
+        pub fn alloc(&mut self, x: T) -> Ptr<T> {
+            let generation = GENERATION.fetch_add(1, Ordering::Relaxed);
+            unsafe {
+                self.adopt(
+                    NonNull::new_unchecked(Box::into_raw(Box::new(x))),
+                    generation,
+                )
+            }
+        }
+        // 別の Pool から release したポインタをアドレスも世代も変えずに引き取る
+        pub unsafe fn adopt(&mut self, ptr: NonNull<T>, generation: u64) -> Ptr<T> {
+            self.map.insert(ptr.as_ptr(), (ptr, generation));
+            self.peak = self.peak.max(self.bytes());
+            Ptr {
+                ptr: ptr.as_ptr(),
+                generation,
+            }
         }
-        pub fn alloc(&mut self, x: T) -> &mut T {
-            let obj = Box::new(x);
-            self.0.entry(obj.deref() as *const T).or_insert(obj)
+        // 所有権を手放す。返したポインタは adopt するか Box::from_raw で解放すること。
+        // リストが自分で繋いだノードをアドレスで移すためのもので、世代は確かめない
+        pub fn release(&mut self, ptr: *const T) -> Option<(NonNull<T>, u64)> {
+            self.map.remove(&ptr)
         }
-        pub fn free(&mut self, ptr: *const T) -> Option<Box<T>> {
-            self.0.remove(&ptr)
+        // 今 ptr のアドレスにあるオブジェクトの Ptr
+        pub fn find(&self, ptr: *const T) -> Option<Ptr<T>> {
+            self.map
+                .get(&ptr)
+                .map(|&(_, generation)| Ptr { ptr, generation })
+        }
+        // オブジェクトは一つずつ Box で確保して解放したらすぐに返すので、空きスロットは HashMap の空きだけ。
+        // HashMap の一つの枠はキーと値に制御用の 1 バイトを加えた大きさとして数える
+        fn bytes(&self) -> usize {
+            self.map.len() * std::mem::size_of::<T>()
+                + self.map.capacity() * (std::mem::size_of::<(*const T, (NonNull<T>, u64))>() + 1)
+        }
+        pub fn usage(&self) -> Usage {
+            Usage {
+                live: self.map.len(),
+                free: self.map.capacity() - self.map.len(),
+                bytes: Some(self.bytes()),
+                peak: Some(self.peak),
+            }
         }
-        pub fn get(&self, ptr: *const T) -> Option<&T> {
-            self.0.get(&ptr).map(Deref::deref)
+        pub fn free(&mut self, p: Ptr<T>) -> Option<Box<T>> {
+            self.get_ptr(p)?;
+            self.release(p.ptr)
+                .map(|(ptr, _)| unsafe { Box::from_raw(ptr.as_ptr()) })
         }
-        pub fn get_mut(&mut self, ptr: *const T) -> Option<&mut T> {
-            self.0.get_mut(&ptr).map(DerefMut::deref_mut)
+        pub fn get(&self, p: Ptr<T>) -> Option<&T> {
+            self.get_ptr(p).map(|ptr| unsafe { &*ptr })
+        }
+        pub fn get_mut(&mut self, p: Ptr<T>) -> Option<&mut T> {
+            self.get_ptr(p).map(|ptr| unsafe { &mut *ptr })
+        }
+        // get_mut から作ったポインタと違い、後で get_mut しても無効にならない
+        pub fn get_ptr(&self, p: Ptr<T>) -> Option<*mut T> {
+            match self.map.get(&p.ptr) {
+                Some(&(ptr, generation)) if generation == p.generation => Some(ptr.as_ptr()),
+                _ => None,
+            }
+        }
+    }
+    impl<T> Drop for Pool<T> {
+        fn drop(&mut self) {
+            for (_, (ptr, _)) in self.map.drain() {
+                unsafe { drop(Box::from_raw(ptr.as_ptr())) }
+            }
         }
     }
 }
 
 #[test]
 fn test_pool() {
-    use pool::Pool;
+    use pool::{Pool, Ptr};
     let mut pool = Pool::new();
-    let r: &usize = pool.alloc(123);
-    assert_eq!(*r, 123);
-
-    let p = r as *const usize;
+    let p = pool.alloc(123);
+    assert_eq!(unsafe { *p.as_ptr() }, 123);
     assert!(pool.get(p).is_some());
     assert_eq!(pool.get(p).unwrap(), &123);
 
     *pool.get_mut(p).unwrap() = 456;
     assert_eq!(pool.get(p).unwrap(), &456);
+    assert_eq!(pool.find(p.as_ptr()), Some(p));
 
     assert!(pool.free(p).is_some());
     assert!(pool.get(p).is_none());
+    assert!(pool.free(p).is_none());
+    assert!(pool.find(p.as_ptr()).is_none());
+    assert!(pool.get(Ptr::DANGLING).is_none());
+
+    // release して adopt しても同じ Ptr で引ける
+    let mut other = Pool::new();
+    let q = pool.alloc(789);
+    let (ptr, generation) = pool.release(q.as_ptr()).unwrap();
+    assert_eq!(unsafe { other.adopt(ptr, generation) }, q);
+    assert!(pool.get(q).is_none());
+    assert_eq!(other.get(q), Some(&789));
+}
+
+// 解放したアドレスが使い回されても、古い Ptr では新しいオブジェクトを引けない
+#[test]
+fn test_pool_address_reuse() {
+    use pool::Pool;
+    let mut pool = Pool::new();
+    let keep = pool.alloc(u64::MAX);
+    // allocator が解放したアドレスを使い回すまで確保と解放を繰り返す。
+    // 外れたオブジェクトは残しておき、次の確保で同じアドレスが返らないようにする
+    let (old, reused) = (0..1000u64)
+        .find_map(|i| {
+            let old = pool.alloc(i);
+            pool.free(old);
+            let reused = pool.alloc(i);
+            Some((old, reused)).filter(|_| reused.as_ptr() == old.as_ptr())
+        })
+        .expect("allocator did not reuse the freed address");
+    assert_ne!(reused, old);
+    assert_eq!(pool.find(old.as_ptr()), Some(reused));
+    // 別のオブジェクトの世代をこのアドレスに付けても引けない
+    assert!(pool.get(keep.with_address(reused.as_ptr())).is_none());
+    assert!(pool.get(old).is_none());
+    assert!(pool.get_ptr(old).is_none());
+    assert!(pool.free(old).is_none());
+    assert!(pool.get(reused).is_some());
+}
+
+#[test]
+fn test_pool_usage() {
+    use pool::Pool;
+    let mut pool = Pool::new();
+    assert_eq!(pool.usage().live, 0);
+    let ptrs = (0..10).map(|i| pool.alloc(i as u64)).collect::<Vec<_>>();
+    let usage = pool.usage();
+    assert_eq!(usage.live, 10);
+    assert!(usage.bytes.unwrap() >= 10 * std::mem::size_of::<u64>());
+    assert_eq!(usage.peak, usage.bytes);
+    for &p in &ptrs[..5] {
+        pool.free(p);
+    }
+    // Box はすぐに解放されるが、HashMap は縮まないので空きが増える
+    let after = pool.usage();
+    assert_eq!(after.live, 5);
+    assert_eq!(after.free, usage.free + 5);
+    assert!(after.bytes < usage.bytes);
+    assert_eq!(after.peak, usage.peak);
 }
 
 mod list {
-    use super::pool::Pool;
+    use super::pool::{Pool, Ptr};
+    use crate::linked_list::LinkedList;
+    use crate::memory::Usage;
+    use crate::validate::{self, NodeLinks, Report};
+    use std::iter::FromIterator;
+    use std::marker::PhantomData;
     use std::ops::{Deref, DerefMut};
 
+    // リスト同士のリンクは生ポインタのまま。外に渡すときだけ世代付きの ptr を使う
     pub struct Node<T> {
         value: Option<T>,
         next: *mut Self,
         prev: *mut Self,
+        ptr: Ptr<Self>,
     }
     impl<T> Node<T> {
         pub fn is_sentinel(&self) -> bool {
             self.value.is_none()
         }
+        // このノードを後で引くための Ptr。削除された後は使えなくなる
+        pub fn ptr(&self) -> Ptr<Self> {
+            self.ptr
+        }
         pub fn next(&self) -> &Self {
             unsafe { &*self.next }
         }
@@ -78,28 +266,47 @@ mod list {
             self.0.value.as_mut().unwrap()
         }
     }
+    impl<'a, T> NodeMut<'a, T> {
+        pub fn replace(&mut self, value: T) -> T {
+            std::mem::replace(&mut **self, value)
+        }
+        // 値だけを入れ替えて next / prev には触らないので、別々のリストのノード同士でも安全
+        pub fn swap(&mut self, other: &mut NodeMut<T>) {
+            std::mem::swap(&mut **self, &mut **other)
+        }
+    }
 
     pub struct List<T> {
         nodes: Pool<Node<T>>,
         sentinel: *mut Node<T>,
+        len: usize,
     }
     impl<T> List<T> {
         pub fn new() -> Self {
             let mut nodes = Pool::new();
-            let mut sentinel = nodes.alloc(Node {
-                value: None,
-                next: std::ptr::null_mut(),
-                prev: std::ptr::null_mut(),
-            });
-            sentinel.next = sentinel.deref_mut() as *mut Node<T>;
-            sentinel.prev = sentinel.deref_mut() as *mut Node<T>;
-            let sentinel = sentinel as *mut Node<T>;
-            Self { nodes, sentinel }
-        }
-        pub fn sentinel(&self) -> *const Node<T> {
-            self.sentinel
-        }
-        pub fn get_ref(&self, ptr: *const Node<T>) -> Option<&Node<T>> {
+            let sentinel = alloc(
+                &mut nodes,
+                Node {
+                    value: None,
+                    next: std::ptr::null_mut(),
+                    prev: std::ptr::null_mut(),
+                    ptr: Ptr::DANGLING,
+                },
+            );
+            unsafe {
+                (*sentinel).next = sentinel;
+                (*sentinel).prev = sentinel;
+            }
+            Self {
+                nodes,
+                sentinel,
+                len: 0,
+            }
+        }
+        pub fn sentinel(&self) -> Ptr<Node<T>> {
+            unsafe { (*self.sentinel).ptr }
+        }
+        pub fn get_ref(&self, ptr: Ptr<Node<T>>) -> Option<&Node<T>> {
             self.nodes.get(ptr)
         }
 
@@ -112,12 +319,13 @@ mod list {
         //  let node2 = list2.get_mut_unchecked(ptr2).unwrap();
         //  std::mem::swap(node1, node2);    // 壊れる！
         // ```
-        pub unsafe fn get_mut_unchecked(&mut self, ptr: *const Node<T>) -> Option<&mut Node<T>> {
+        // 値の入れ替えは NodeMut::swap か swap_values、ノードの入れ替えは swap_nodes を使うこと
+        pub unsafe fn get_mut_unchecked(&mut self, ptr: Ptr<Node<T>>) -> Option<&mut Node<T>> {
             self.nodes.get_mut(ptr)
         }
 
         // こちらは安全に使える
-        pub fn get_mut(&mut self, ptr: *const Node<T>) -> Option<NodeMut<T>> {
+        pub fn get_mut(&mut self, ptr: Ptr<Node<T>>) -> Option<NodeMut<'_, T>> {
             self.nodes.get_mut(ptr).map(NodeMut)
         }
 
@@ -130,19 +338,121 @@ mod list {
         pub fn is_empty(&self) -> bool {
             self.head().is_sentinel()
         }
+        pub fn len(&self) -> usize {
+            self.len
+        }
+        // sentinel から一周してリンクと len を検査する。変更の後に debug build で呼ぶ
+        pub fn validate(&self) -> Report {
+            validate::check_sentinel_list(self.sentinel as *const Node<T>, Some(self.len), |ptr| {
+                self.nodes
+                    .find(ptr)
+                    .and_then(|p| self.nodes.get(p))
+                    .map(|node| NodeLinks {
+                        key: ptr as usize,
+                        is_sentinel: node.is_sentinel(),
+                        next: node.next as *const Node<T>,
+                        prev: node.prev as *const Node<T>,
+                    })
+                    .ok_or_else(|| "not in this list".to_string())
+            })
+        }
+        // 近い方の端から辿る
+        pub fn nth(&self, i: usize) -> Option<&Node<T>> {
+            if i >= self.len {
+                None
+            } else if i <= self.len / 2 {
+                let mut node = self.head();
+                for _ in 0..i {
+                    node = node.next();
+                }
+                Some(node)
+            } else {
+                self.nth_back(self.len - 1 - i)
+            }
+        }
+        pub fn nth_back(&self, i: usize) -> Option<&Node<T>> {
+            if i >= self.len {
+                None
+            } else if i <= self.len / 2 {
+                let mut node = self.tail();
+                for _ in 0..i {
+                    node = node.prev();
+                }
+                Some(node)
+            } else {
+                self.nth(self.len - 1 - i)
+            }
+        }
+        pub fn position(&self, ptr: Ptr<Node<T>>) -> Option<usize> {
+            let mut node = self.head();
+            let mut i = 0;
+            while !node.is_sentinel() {
+                if node.ptr == ptr {
+                    return Some(i);
+                }
+                node = node.next();
+                i += 1;
+            }
+            None
+        }
+        pub fn iter(&self) -> Iter<'_, T> {
+            unsafe {
+                Iter {
+                    head: (*self.sentinel).next,
+                    tail: (*self.sentinel).prev,
+                    len: self.len,
+                    _marker: PhantomData,
+                }
+            }
+        }
+        pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+            unsafe {
+                IterMut {
+                    head: (*self.sentinel).next,
+                    tail: (*self.sentinel).prev,
+                    len: self.len,
+                    _marker: PhantomData,
+                }
+            }
+        }
+        pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+            let current = unsafe { (*self.sentinel).next };
+            CursorMut {
+                list: self,
+                current,
+            }
+        }
+        pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+            let current = unsafe { (*self.sentinel).prev };
+            CursorMut {
+                list: self,
+                current,
+            }
+        }
+        pub fn cursor_mut(&mut self, ptr: Ptr<Node<T>>) -> Option<CursorMut<'_, T>> {
+            let current = self.nodes.get_ptr(ptr)?;
+            Some(CursorMut {
+                list: self,
+                current,
+            })
+        }
         unsafe fn insert_unsafe(&mut self, next: *mut Node<T>, value: T) {
             let prev: *mut Node<T> = (*next).prev;
-            let node: *mut Node<T> = self.nodes.alloc(Node {
-                value: Some(value),
-                next,
-                prev,
-            }) as *mut Node<T>;
+            let node: *mut Node<T> = alloc(
+                &mut self.nodes,
+                Node {
+                    value: Some(value),
+                    next,
+                    prev,
+                    ptr: Ptr::DANGLING,
+                },
+            );
             (*next).prev = node;
             (*prev).next = node;
+            self.len += 1;
         }
-        pub fn insert(&mut self, pos: *const Node<T>, value: T) -> bool {
-            if let Some(next) = self.nodes.get_mut(pos) {
-                let next = next as *mut Node<T>;
+        pub fn insert(&mut self, pos: Ptr<Node<T>>, value: T) -> bool {
+            if let Some(next) = self.nodes.get_ptr(pos) {
                 unsafe { self.insert_unsafe(next, value) }
                 true
             } else {
@@ -155,20 +465,389 @@ mod list {
         pub fn push_front(&mut self, value: T) {
             unsafe { self.insert_unsafe((*self.sentinel).next, value) }
         }
-        pub fn remove(&mut self, node: *const Node<T>) -> Option<&Node<T>> {
-            if let Some(node) = self.nodes.free(node) {
-                let next = node.next as *mut Node<T>;
-                let prev = node.prev as *mut Node<T>;
-                unsafe {
-                    (*next).prev = prev;
-                    (*prev).next = next;
-                    Some(&*next)
+        pub fn remove(&mut self, node: Ptr<Node<T>>) -> Option<&Node<T>> {
+            let (_, next) = self.unlink(self.nodes.get_ptr(node)?)?;
+            Some(unsafe { &*next })
+        }
+        // remove と違い、取り除いた値をそのまま返す
+        pub fn take(&mut self, node: Ptr<Node<T>>) -> Option<T> {
+            self.unlink(self.nodes.get_ptr(node)?)
+                .map(|(value, _)| value)
+        }
+        pub fn pop_front(&mut self) -> Option<T> {
+            self.unlink(unsafe { (*self.sentinel).next })
+                .map(|(value, _)| value)
+        }
+        pub fn pop_back(&mut self) -> Option<T> {
+            self.unlink(unsafe { (*self.sentinel).prev })
+                .map(|(value, _)| value)
+        }
+        // a と b の値を入れ替える。ノードの位置はそのまま。
+        pub fn swap_values(&mut self, a: Ptr<Node<T>>, b: Ptr<Node<T>>) -> bool {
+            match (self.value_node(a), self.value_node(b)) {
+                (Some(a), Some(b)) => {
+                    if a != b {
+                        unsafe { std::mem::swap(&mut (*a).value, &mut (*b).value) }
+                    }
+                    true
                 }
-            } else {
-                None
+                _ => false,
+            }
+        }
+        // a と b のノードごと位置を入れ替える。a, b はそれぞれ元の値を指したまま。
+        pub fn swap_nodes(&mut self, a: Ptr<Node<T>>, b: Ptr<Node<T>>) -> bool {
+            let (a, b) = match (self.value_node(a), self.value_node(b)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return false,
+            };
+            if a == b {
+                return true;
+            }
+            unsafe {
+                let a_next = (*a).next;
+                let b_next = (*b).next;
+                if a_next == b {
+                    move_before(b, a);
+                } else if b_next == a {
+                    move_before(a, b);
+                } else {
+                    move_before(a, b_next);
+                    move_before(b, a_next);
+                }
+            }
+            true
+        }
+        // sentinel 以外のこのリストのノードなら書き込みに使えるポインタを返す
+        fn value_node(&mut self, ptr: Ptr<Node<T>>) -> Option<*mut Node<T>> {
+            self.nodes
+                .get_ptr(ptr)
+                .filter(|&node| unsafe { !(*node).is_sentinel() })
+        }
+        // node をリストから外して値と次のノードを返す。node はこのリストのノードであること
+        fn unlink(&mut self, node: *mut Node<T>) -> Option<(T, *mut Node<T>)> {
+            if node == self.sentinel {
+                return None;
+            }
+            let node = self.nodes.free(unsafe { (*node).ptr })?;
+            let next = node.next;
+            let prev = node.prev;
+            unsafe {
+                (*next).prev = prev;
+                (*prev).next = next;
+            }
+            self.len -= 1;
+            Some((node.value.unwrap(), next))
+        }
+    }
+
+    // 確保したノードに自分の Ptr を覚えさせる
+    fn alloc<T>(nodes: &mut Pool<Node<T>>, node: Node<T>) -> *mut Node<T> {
+        let ptr = nodes.alloc(node);
+        let node = nodes.get_ptr(ptr).unwrap();
+        unsafe { (*node).ptr = ptr };
+        node
+    }
+
+    // node を今の位置から外して pos の直前に繋ぎ直す
+    unsafe fn move_before<T>(node: *mut Node<T>, pos: *mut Node<T>) {
+        (*(*node).prev).next = (*node).next;
+        (*(*node).next).prev = (*node).prev;
+        (*node).prev = (*pos).prev;
+        (*node).next = pos;
+        (*(*pos).prev).next = node;
+        (*pos).prev = node;
+    }
+
+    // current が sentinel を指しているときは末尾と先頭の間にいるとみなす
+    pub struct CursorMut<'a, T> {
+        list: &'a mut List<T>,
+        current: *mut Node<T>,
+    }
+    impl<'a, T> CursorMut<'a, T> {
+        pub fn move_next(&mut self) {
+            self.current = unsafe { (*self.current).next };
+        }
+        pub fn move_prev(&mut self) {
+            self.current = unsafe { (*self.current).prev };
+        }
+        pub fn current(&mut self) -> Option<&mut T> {
+            unsafe { (*self.current).value.as_mut() }
+        }
+        pub fn insert_before(&mut self, value: T) {
+            unsafe { self.list.insert_unsafe(self.current, value) }
+        }
+        pub fn insert_after(&mut self, value: T) {
+            unsafe { self.list.insert_unsafe((*self.current).next, value) }
+        }
+        // 削除した後は次のノードに移動する
+        pub fn remove_current(&mut self) -> Option<T> {
+            let (value, next) = self.list.unlink(self.current)?;
+            self.current = next;
+            Some(value)
+        }
+        // current より後ろのノードを新しいリストに移す。所有権を付け替えるだけなのでアドレスも Ptr も変わらない。
+        pub fn split_after(&mut self) -> List<T> {
+            let mut other = List::new();
+            unsafe {
+                let sentinel = self.list.sentinel;
+                let first = (*self.current).next;
+                let last = (*sentinel).prev;
+                if first == sentinel {
+                    return other;
+                }
+                (*self.current).next = sentinel;
+                (*sentinel).prev = self.current;
+                let mut node = first;
+                loop {
+                    let (obj, generation) = self.list.nodes.release(node).unwrap();
+                    other.nodes.adopt(obj, generation);
+                    self.list.len -= 1;
+                    other.len += 1;
+                    if node == last {
+                        break;
+                    }
+                    node = (*node).next;
+                }
+                (*first).prev = other.sentinel;
+                (*last).next = other.sentinel;
+                (*other.sentinel).next = first;
+                (*other.sentinel).prev = last;
+            }
+            other
+        }
+        // other の全てのノードを current の直後に移す
+        pub fn splice_after(&mut self, mut other: List<T>) {
+            if other.is_empty() {
+                return;
+            }
+            unsafe {
+                let first = (*other.sentinel).next;
+                let last = (*other.sentinel).prev;
+                let mut node = first;
+                loop {
+                    let (obj, generation) = other.nodes.release(node).unwrap();
+                    self.list.nodes.adopt(obj, generation);
+                    self.list.len += 1;
+                    if node == last {
+                        break;
+                    }
+                    node = (*node).next;
+                }
+                let next = (*self.current).next;
+                (*first).prev = self.current;
+                (*last).next = next;
+                (*self.current).next = first;
+                (*next).prev = last;
+            }
+        }
+    }
+
+    pub struct Iter<'a, T> {
+        head: *const Node<T>,
+        tail: *const Node<T>,
+        len: usize,
+        _marker: PhantomData<&'a Node<T>>,
+    }
+    impl<'a, T> Iterator for Iter<'a, T> {
+        type Item = &'a T;
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = unsafe { &*self.head };
+            self.head = node.next;
+            node.value.as_ref()
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
+    }
+    impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = unsafe { &*self.tail };
+            self.tail = node.prev;
+            node.value.as_ref()
+        }
+    }
+    impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+    // 同じノードを二度返さないように len で終わりを判定する
+    pub struct IterMut<'a, T> {
+        head: *mut Node<T>,
+        tail: *mut Node<T>,
+        len: usize,
+        _marker: PhantomData<&'a mut Node<T>>,
+    }
+    impl<'a, T> Iterator for IterMut<'a, T> {
+        type Item = &'a mut T;
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            unsafe {
+                let node = self.head;
+                self.head = (*node).next;
+                (*node).value.as_mut()
+            }
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
+    }
+    impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            unsafe {
+                let node = self.tail;
+                self.tail = (*node).prev;
+                (*node).value.as_mut()
+            }
+        }
+    }
+    impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+    pub struct IntoIter<T>(List<T>);
+    impl<T> Iterator for IntoIter<T> {
+        type Item = T;
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0.pop_front()
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.0.len, Some(self.0.len))
+        }
+    }
+    impl<T> DoubleEndedIterator for IntoIter<T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            self.0.pop_back()
+        }
+    }
+    impl<T> ExactSizeIterator for IntoIter<T> {}
+
+    impl<T> IntoIterator for List<T> {
+        type Item = T;
+        type IntoIter = IntoIter<T>;
+        fn into_iter(self) -> Self::IntoIter {
+            IntoIter(self)
+        }
+    }
+    impl<'a, T> IntoIterator for &'a List<T> {
+        type Item = &'a T;
+        type IntoIter = Iter<'a, T>;
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+    impl<'a, T> IntoIterator for &'a mut List<T> {
+        type Item = &'a mut T;
+        type IntoIter = IterMut<'a, T>;
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter_mut()
+        }
+    }
+    impl<T> FromIterator<T> for List<T> {
+        fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+            let mut list = Self::new();
+            list.extend(iter);
+            list
+        }
+    }
+    impl<T> Extend<T> for List<T> {
+        fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+            for value in iter {
+                self.push_back(value);
             }
         }
     }
+
+    // Handle は sentinel 以外のノードの Ptr。同名の inherent method と区別するために List:: を付けて呼ぶ
+    fn handle<T>(node: &Node<T>) -> Option<Ptr<Node<T>>> {
+        if node.is_sentinel() {
+            None
+        } else {
+            Some(node.ptr)
+        }
+    }
+    impl<T> LinkedList<T> for List<T> {
+        type Handle = Ptr<Node<T>>;
+        type Iter<'a>
+            = Iter<'a, T>
+        where
+            T: 'a;
+        const NAME: &'static str = "hashmap";
+        fn new() -> Self {
+            List::new()
+        }
+        fn len(&self) -> usize {
+            self.len
+        }
+        fn iter(&self) -> Iter<'_, T> {
+            List::iter(self)
+        }
+        fn head(&self) -> Option<Ptr<Node<T>>> {
+            handle(List::head(self))
+        }
+        fn tail(&self) -> Option<Ptr<Node<T>>> {
+            handle(List::tail(self))
+        }
+        fn next(&self, h: Ptr<Node<T>>) -> Option<Ptr<Node<T>>> {
+            handle(self.get_ref(h).filter(|node| !node.is_sentinel())?.next())
+        }
+        fn prev(&self, h: Ptr<Node<T>>) -> Option<Ptr<Node<T>>> {
+            handle(self.get_ref(h).filter(|node| !node.is_sentinel())?.prev())
+        }
+        fn nth(&self, i: usize) -> Option<Ptr<Node<T>>> {
+            List::nth(self, i).map(Node::ptr)
+        }
+        fn nth_back(&self, i: usize) -> Option<Ptr<Node<T>>> {
+            List::nth_back(self, i).map(Node::ptr)
+        }
+        fn position(&self, h: Ptr<Node<T>>) -> Option<usize> {
+            List::position(self, h)
+        }
+        fn get(&self, h: Ptr<Node<T>>) -> Option<&T> {
+            self.nodes.get(h)?.value.as_ref()
+        }
+        fn get_mut(&mut self, h: Ptr<Node<T>>) -> Option<&mut T> {
+            self.nodes.get_mut(h)?.value.as_mut()
+        }
+        fn insert(&mut self, h: Ptr<Node<T>>, value: T) -> Option<Ptr<Node<T>>> {
+            LinkedList::get(self, h)?;
+            List::insert(self, h, value);
+            handle(self.get_ref(h)?.prev())
+        }
+        fn push_back(&mut self, value: T) -> Ptr<Node<T>> {
+            List::push_back(self, value);
+            List::tail(self).ptr
+        }
+        fn push_front(&mut self, value: T) -> Ptr<Node<T>> {
+            List::push_front(self, value);
+            List::head(self).ptr
+        }
+        fn remove(&mut self, h: Ptr<Node<T>>) -> Option<T> {
+            self.take(h)
+        }
+        fn pop_front(&mut self) -> Option<T> {
+            List::pop_front(self)
+        }
+        fn pop_back(&mut self) -> Option<T> {
+            List::pop_back(self)
+        }
+        fn validate(&self) -> Report {
+            List::validate(self)
+        }
+        fn memory(&self) -> Usage {
+            self.nodes.usage()
+        }
+    }
 }
 
 pub use list::*;
@@ -195,25 +874,33 @@ fn test_list() {
     assert_eq!(*list.head().value(), 3);
     assert_eq!(*list.head().next().value(), 1);
 
-    assert!(list.insert(list.head().next() as *const _, 4));
+    assert!(list.insert(list.head().next().ptr(), 4));
     assert_eq!(*list.head().value(), 3);
     assert_eq!(*list.head().next().value(), 4);
     assert_eq!(*list.head().next().next().value(), 1);
     assert_eq!(*list.head().next().next().next().value(), 2);
 
-    assert!(list.remove(list.head() as *const _).is_some());
+    assert!(list.remove(list.head().ptr()).is_some());
     assert_eq!(*list.head().value(), 4);
+    assert!(list.remove(list.sentinel()).is_none());
 
-    let mut node = list.get_mut(list.head().next() as *const _).unwrap();
+    let mut node = list.get_mut(list.head().next().ptr()).unwrap();
     *node = 5;
     assert_eq!(*list.head().next().value(), 5);
 
-    unsafe {
+    {
+        // get_mut_unchecked で得た &mut Node を std::mem::swap すると壊れるが、
+        // NodeMut 同士なら値だけが入れ替わる
         let mut list2: List<usize> = List::new();
         list2.push_back(6);
-        let node1 = list.get_mut_unchecked(list.head() as *const _).unwrap();
-        let node2 = list2.get_mut_unchecked(list2.head() as *const _).unwrap();
-        std::mem::swap(node1, node2); // 壊れる！
+        let mut node1 = list.get_mut(list.head().ptr()).unwrap();
+        let mut node2 = list2.get_mut(list2.head().ptr()).unwrap();
+        node1.swap(&mut node2);
+        assert_eq!(node1.replace(7), 6);
+        assert_eq!(*list.head().value(), 7);
+        assert_eq!(*list2.head().value(), 4);
+        assert!(list2.head().next().is_sentinel());
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![7, 5, 2]);
     }
 
     /* not compilable
@@ -223,10 +910,134 @@ fn test_list() {
     */
     {
         let mut list = List::new();
-        list.insert(list.head(), 1); // 先頭に 1 を挿入
+        list.insert(list.head().ptr(), 1); // 先頭に 1 を挿入
         list.insert(list.sentinel(), 2); // 末尾に 2 を挿入
         assert_eq!(*list.head().value(), 1); // 先頭の値を取得
         assert_eq!(*list.head().next().value(), 2); // 2番目の値を取得
-        assert!(list.remove(list.head().next()).is_some()); // 2番目の要素を削除
+        assert!(list.remove(list.head().next().ptr()).is_some()); // 2番目の要素を削除
     }
 }
+
+#[test]
+fn test_swap() {
+    let mut list: List<usize> = (0..5).collect();
+    let ptrs: Vec<_> = (0..5).map(|i| list.nth(i).unwrap().ptr()).collect();
+    let values = |list: &List<usize>| list.iter().copied().collect::<Vec<_>>();
+
+    assert!(list.swap_values(ptrs[0], ptrs[3]));
+    assert_eq!(values(&list), vec![3, 1, 2, 0, 4]);
+    assert_eq!(*list.get_ref(ptrs[0]).unwrap().value(), 3);
+    assert!(list.swap_values(ptrs[0], ptrs[3]));
+    assert!(list.swap_values(ptrs[2], ptrs[2]));
+    assert_eq!(values(&list), vec![0, 1, 2, 3, 4]);
+
+    // 離れたノード、隣り合うノード（両方の順序）、端のノード
+    assert!(list.swap_nodes(ptrs[1], ptrs[3]));
+    assert_eq!(values(&list), vec![0, 3, 2, 1, 4]);
+    assert!(list.swap_nodes(ptrs[3], ptrs[2]));
+    assert_eq!(values(&list), vec![0, 2, 3, 1, 4]);
+    assert!(list.swap_nodes(ptrs[3], ptrs[2]));
+    assert_eq!(values(&list), vec![0, 3, 2, 1, 4]);
+    assert!(list.swap_nodes(ptrs[4], ptrs[0]));
+    assert_eq!(values(&list), vec![4, 3, 2, 1, 0]);
+    assert!(list.swap_nodes(ptrs[1], ptrs[1]));
+    assert_eq!(list.head().ptr(), ptrs[4]);
+    assert_eq!(list.tail().ptr(), ptrs[0]);
+    assert_eq!(
+        list.iter().rev().copied().collect::<Vec<_>>(),
+        vec![0, 1, 2, 3, 4]
+    );
+    assert_eq!(list.position(ptrs[3]), Some(1));
+
+    // sentinel や別のリストのノードとは入れ替えられない
+    let other: List<usize> = (0..1).collect();
+    assert!(!list.swap_values(ptrs[0], list.sentinel()));
+    assert!(!list.swap_nodes(ptrs[0], list.sentinel()));
+    assert!(!list.swap_values(ptrs[0], other.head().ptr()));
+    assert!(!list.swap_nodes(ptrs[0], other.head().ptr()));
+    assert_eq!(values(&list), vec![4, 3, 2, 1, 0]);
+}
+
+// split_after / splice_after はノードの所有権を付け替えるだけなので、移したノードの Ptr もそのまま使える
+#[test]
+fn test_cursor() {
+    let mut list: List<usize> = (1..=5).collect();
+    let ptrs: Vec<_> = (0..5).map(|i| list.nth(i).unwrap().ptr()).collect();
+    let mut tail = list.cursor_mut(ptrs[2]).unwrap().split_after();
+    assert_eq!(tail.head().ptr(), ptrs[3]);
+    assert_eq!(tail.tail().ptr(), ptrs[4]);
+    assert_eq!(*tail.get_ref(ptrs[3]).unwrap().value(), 4);
+    assert!(list.get_ref(ptrs[3]).is_none());
+
+    tail.push_front(6);
+    list.cursor_front_mut().splice_after(tail);
+    assert_eq!(
+        list.iter().copied().collect::<Vec<_>>(),
+        vec![1, 6, 4, 5, 2, 3]
+    );
+    assert_eq!(list.position(ptrs[3]), Some(2));
+    assert_eq!(list.position(ptrs[4]), Some(3));
+    assert_eq!(*list.get_ref(ptrs[4]).unwrap().value(), 5);
+    list.validate().assert_ok();
+
+    // sentinel の位置から split_after すると全てのノードが移る
+    let all = {
+        let mut cursor = list.cursor_back_mut();
+        cursor.move_next();
+        cursor.split_after()
+    };
+    assert!(list.is_empty());
+    assert_eq!((list.len(), all.len()), (0, 6));
+    assert_eq!(all.position(ptrs[0]), Some(0));
+    list.validate().assert_ok();
+    all.validate().assert_ok();
+}
+
+// 削除したノードのアドレスが新しいノードに使い回されても、古い Ptr では何もできない
+#[test]
+fn test_address_reuse() {
+    use crate::linked_list::LinkedList;
+    let mut list: List<usize> = (0..3).collect();
+    // allocator が削除したノードのアドレスを使い回すまで追加と削除を繰り返す
+    let (old, reused) = (10..1010)
+        .find_map(|i| {
+            list.push_back(i);
+            let old = list.tail().ptr();
+            assert_eq!(list.take(old), Some(i));
+            list.push_back(i);
+            let reused = list.tail().ptr();
+            Some((old, reused)).filter(|_| reused.as_ptr() == old.as_ptr())
+        })
+        .expect("allocator did not reuse the freed address");
+    assert_ne!(reused, old);
+    let len = list.len();
+
+    assert!(list.get_ref(old).is_none());
+    assert!(list.get_mut(old).is_none());
+    assert!(list.position(old).is_none());
+    assert!(list.cursor_mut(old).is_none());
+    assert!(!list.insert(old, 100));
+    assert!(!list.swap_values(old, reused));
+    assert!(!list.swap_nodes(old, reused));
+    assert!(list.remove(old).is_none());
+    assert!(list.take(old).is_none());
+    assert!(LinkedList::get(&list, old).is_none());
+    assert!(LinkedList::next(&list, old).is_none());
+    assert_eq!(list.len(), len);
+    assert_eq!(list.position(reused), Some(len - 1));
+    list.validate().assert_ok();
+}
+
+// get_mut_unchecked で別のリストのノードと丸ごと入れ替えられる
+#[test]
+fn test_validate() {
+    crate::linked_list::exercise_validate::<List<usize>>(
+        |list1, p1, list2, p2| unsafe {
+            std::mem::swap(
+                list1.get_mut_unchecked(p1).unwrap(),
+                list2.get_mut_unchecked(p2).unwrap(),
+            )
+        },
+        "not in this list",
+    );
+}
@@ -1,5 +1,10 @@
 mod list {
+    use crate::linked_list::LinkedList;
+    use crate::memory::Usage;
+    use crate::validate::{self, NodeLinks, Report};
     use mepoo::{Pool, Ptr, Ref};
+    use std::iter::FromIterator;
+    use std::marker::PhantomData;
     use std::ops::{Deref, DerefMut};
 
     pub struct Node<T> {
@@ -35,10 +40,20 @@ mod list {
             self.0.value.as_mut().unwrap()
         }
     }
+    impl<'a, T> NodeMut<'a, T> {
+        pub fn replace(&mut self, value: T) -> T {
+            std::mem::replace(&mut **self, value)
+        }
+        // 値だけを入れ替えて next / prev には触らないので、別々のリストのノード同士でも安全
+        pub fn swap(&mut self, other: &mut NodeMut<T>) {
+            std::mem::swap(&mut **self, &mut **other)
+        }
+    }
 
     pub struct List<T> {
         nodes: Pool<Node<T>>,
         sentinel: Ptr<Node<T>>,
+        len: usize,
     }
     impl<T> List<T> {
         pub fn new() -> Self {
@@ -53,7 +68,11 @@ mod list {
                 sentinel_mut.next = sentinel;
                 sentinel_mut.prev = sentinel;
             }
-            Self { nodes, sentinel }
+            Self {
+                nodes,
+                sentinel,
+                len: 0,
+            }
         }
         fn sentinel(&self) -> Ref<Node<T>> {
             unsafe { self.sentinel.as_ref() }.unwrap()
@@ -61,6 +80,8 @@ mod list {
         pub fn get_ref(&self, ptr: Ptr<Node<T>>) -> Option<Ref<Node<T>>> {
             self.nodes.get(ptr)
         }
+        // 返した &mut Node を別のリストのノードと std::mem::swap すると壊れる。
+        // 値の入れ替えは NodeMut::swap か swap_values、ノードの入れ替えは swap_nodes を使うこと
         pub unsafe fn get_mut_unchecked(&mut self, ptr: Ptr<Node<T>>) -> Option<&mut Node<T>> {
             self.nodes.get_mut(ptr)
         }
@@ -76,6 +97,100 @@ mod list {
         pub fn is_empty(&self) -> bool {
             self.head().is_sentinel()
         }
+        pub fn len(&self) -> usize {
+            self.len
+        }
+        // sentinel から一周してリンクと len を検査する。変更の後に debug build で呼ぶ
+        pub fn validate(&self) -> Report {
+            validate::check_sentinel_list(self.sentinel, Some(self.len), |ptr| {
+                self.nodes
+                    .get(ptr)
+                    .map(|node| NodeLinks {
+                        key: node.get() as *const Node<T> as usize,
+                        is_sentinel: node.is_sentinel(),
+                        next: node.next,
+                        prev: node.prev,
+                    })
+                    .ok_or_else(|| "not in this list".to_string())
+            })
+        }
+        // 近い方の端から辿る
+        pub fn nth(&self, i: usize) -> Option<Ref<'_, Node<T>>> {
+            if i >= self.len {
+                None
+            } else if i <= self.len / 2 {
+                let mut node = self.head();
+                for _ in 0..i {
+                    node = node.get().next();
+                }
+                Some(node)
+            } else {
+                self.nth_back(self.len - 1 - i)
+            }
+        }
+        pub fn nth_back(&self, i: usize) -> Option<Ref<'_, Node<T>>> {
+            if i >= self.len {
+                None
+            } else if i <= self.len / 2 {
+                let mut node = self.tail();
+                for _ in 0..i {
+                    node = node.get().prev();
+                }
+                Some(node)
+            } else {
+                self.nth(self.len - 1 - i)
+            }
+        }
+        pub fn position(&self, ptr: Ptr<Node<T>>) -> Option<usize> {
+            let target = self.get_ref(ptr)?.get() as *const Node<T>;
+            let mut node = self.head();
+            let mut i = 0;
+            while !node.is_sentinel() {
+                if std::ptr::eq(node.get(), target) {
+                    return Some(i);
+                }
+                node = node.get().next();
+                i += 1;
+            }
+            None
+        }
+        pub fn iter(&self) -> Iter<'_, T> {
+            Iter {
+                head: self.head().into(),
+                tail: self.tail().into(),
+                len: self.len,
+                _marker: PhantomData,
+            }
+        }
+        pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+            IterMut {
+                head: self.head().into(),
+                tail: self.tail().into(),
+                len: self.len,
+                _marker: PhantomData,
+            }
+        }
+        pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+            let current = self.sentinel().get().next;
+            CursorMut {
+                list: self,
+                current,
+            }
+        }
+        pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+            let current = self.sentinel().get().prev;
+            CursorMut {
+                list: self,
+                current,
+            }
+        }
+        pub fn cursor_mut(&mut self, ptr: Ptr<Node<T>>) -> Option<CursorMut<'_, T>> {
+            self.nodes.get(ptr)?;
+            Some(CursorMut {
+                list: self,
+                current: ptr,
+            })
+        }
         unsafe fn insert_unsafe(&mut self, next: Ptr<Node<T>>, value: T) {
             let prev = next.as_ref().unwrap().prev;
             let node = self.nodes.alloc(Node {
@@ -85,6 +200,7 @@ mod list {
             });
             next.as_mut().unwrap().prev = node;
             prev.as_mut().unwrap().next = node;
+            self.len += 1;
         }
         pub fn insert(&mut self, pos: Ptr<Node<T>>, value: T) -> bool {
             if self.nodes.get(pos).is_some() {
@@ -104,17 +220,343 @@ mod list {
             }
         }
         pub fn remove(&mut self, node: Ptr<Node<T>>) -> Option<Ref<Node<T>>> {
-            if let Some(node_ref) = self.nodes.get(node) {
-                let next = node_ref.next;
-                let prev = node_ref.prev;
-                assert!(self.nodes.free(node));
-                unsafe {
-                    next.as_mut().unwrap().prev = prev;
-                    prev.as_mut().unwrap().next = next;
-                    next.as_ref()
+            let (_, next) = self.unlink(node)?;
+            unsafe { next.as_ref() }
+        }
+        // remove と違い、取り除いた値をそのまま返す
+        pub fn take(&mut self, node: Ptr<Node<T>>) -> Option<T> {
+            self.unlink(node).map(|(value, _)| value)
+        }
+        pub fn pop_front(&mut self) -> Option<T> {
+            let head = self.head().into();
+            self.take(head)
+        }
+        pub fn pop_back(&mut self) -> Option<T> {
+            let tail = self.tail().into();
+            self.take(tail)
+        }
+        // a と b の値を入れ替える。ノードの位置はそのまま。
+        pub fn swap_values(&mut self, a: Ptr<Node<T>>, b: Ptr<Node<T>>) -> bool {
+            match (self.value_node(a), self.value_node(b)) {
+                (Some(a), Some(b)) => {
+                    if a != b {
+                        unsafe { std::mem::swap(&mut (*a).value, &mut (*b).value) }
+                    }
+                    true
                 }
-            } else {
-                None
+                _ => false,
+            }
+        }
+        // a と b のノードごと位置を入れ替える。a, b はそれぞれ元の値を指したまま。
+        pub fn swap_nodes(&mut self, a: Ptr<Node<T>>, b: Ptr<Node<T>>) -> bool {
+            let (a_addr, b_addr) = match (self.value_node(a), self.value_node(b)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return false,
+            };
+            if a_addr == b_addr {
+                return true;
+            }
+            unsafe {
+                let a_next = (*a_addr).next;
+                let b_next = (*b_addr).next;
+                if addr(a_next) == b_addr {
+                    move_before(b, a);
+                } else if addr(b_next) == a_addr {
+                    move_before(a, b);
+                } else {
+                    move_before(a, b_next);
+                    move_before(b, a_next);
+                }
+            }
+            true
+        }
+        // sentinel 以外のこのリストのノードなら書き込みに使えるポインタを返す
+        fn value_node(&mut self, ptr: Ptr<Node<T>>) -> Option<*mut Node<T>> {
+            self.nodes
+                .get_mut(ptr)
+                .filter(|node| !node.is_sentinel())
+                .map(|node| node as *mut Node<T>)
+        }
+        // node をリストから外して値と次のノードを返す
+        fn unlink(&mut self, node: Ptr<Node<T>>) -> Option<(T, Ptr<Node<T>>)> {
+            let node_mut = self.nodes.get_mut(node)?;
+            let value = node_mut.value.take()?;
+            let next = node_mut.next;
+            let prev = node_mut.prev;
+            assert!(self.nodes.free(node));
+            unsafe {
+                next.as_mut().unwrap().prev = prev;
+                prev.as_mut().unwrap().next = next;
+            }
+            self.len -= 1;
+            Some((value, next))
+        }
+    }
+
+    unsafe fn addr<T>(ptr: Ptr<Node<T>>) -> *mut Node<T> {
+        ptr.as_ref().unwrap().get() as *const Node<T> as *mut Node<T>
+    }
+
+    // node を今の位置から外して pos の直前に繋ぎ直す
+    unsafe fn move_before<T>(node: Ptr<Node<T>>, pos: Ptr<Node<T>>) {
+        let n = node.as_mut().unwrap();
+        n.prev.as_mut().unwrap().next = n.next;
+        n.next.as_mut().unwrap().prev = n.prev;
+        let p = pos.as_mut().unwrap();
+        n.prev = p.prev;
+        n.next = pos;
+        p.prev.as_mut().unwrap().next = node;
+        p.prev = node;
+    }
+
+    // current が sentinel を指しているときは末尾と先頭の間にいるとみなす。
+    // mepoo の Pool もリストごとなので、test07 の CursorMut と同じく split_after / splice_after は持たせない
+    pub struct CursorMut<'a, T> {
+        list: &'a mut List<T>,
+        current: Ptr<Node<T>>,
+    }
+    impl<'a, T> CursorMut<'a, T> {
+        pub fn move_next(&mut self) {
+            self.current = unsafe { self.current.as_ref() }.unwrap().next;
+        }
+        pub fn move_prev(&mut self) {
+            self.current = unsafe { self.current.as_ref() }.unwrap().prev;
+        }
+        pub fn current(&mut self) -> Option<&mut T> {
+            unsafe { self.current.as_mut() }.unwrap().value.as_mut()
+        }
+        pub fn insert_before(&mut self, value: T) {
+            unsafe { self.list.insert_unsafe(self.current, value) }
+        }
+        pub fn insert_after(&mut self, value: T) {
+            let next = unsafe { self.current.as_ref() }.unwrap().next;
+            unsafe { self.list.insert_unsafe(next, value) }
+        }
+        // 削除した後は次のノードに移動する
+        pub fn remove_current(&mut self) -> Option<T> {
+            let (value, next) = self.list.unlink(self.current)?;
+            self.current = next;
+            Some(value)
+        }
+    }
+
+    pub struct Iter<'a, T> {
+        head: Ptr<Node<T>>,
+        tail: Ptr<Node<T>>,
+        len: usize,
+        _marker: PhantomData<&'a Node<T>>,
+    }
+    impl<'a, T> Iterator for Iter<'a, T> {
+        type Item = &'a T;
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = unsafe { self.head.as_ref() }.unwrap().get();
+            self.head = node.next;
+            node.value.as_ref()
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
+    }
+    impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = unsafe { self.tail.as_ref() }.unwrap().get();
+            self.tail = node.prev;
+            node.value.as_ref()
+        }
+    }
+    impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+    // 同じノードを二度返さないように len で終わりを判定する
+    pub struct IterMut<'a, T> {
+        head: Ptr<Node<T>>,
+        tail: Ptr<Node<T>>,
+        len: usize,
+        _marker: PhantomData<&'a mut Node<T>>,
+    }
+    impl<'a, T> Iterator for IterMut<'a, T> {
+        type Item = &'a mut T;
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = unsafe { self.head.as_mut() }.unwrap();
+            self.head = node.next;
+            node.value.as_mut()
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
+    }
+    impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = unsafe { self.tail.as_mut() }.unwrap();
+            self.tail = node.prev;
+            node.value.as_mut()
+        }
+    }
+    impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+    pub struct IntoIter<T>(List<T>);
+    impl<T> Iterator for IntoIter<T> {
+        type Item = T;
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0.pop_front()
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.0.len, Some(self.0.len))
+        }
+    }
+    impl<T> DoubleEndedIterator for IntoIter<T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            self.0.pop_back()
+        }
+    }
+    impl<T> ExactSizeIterator for IntoIter<T> {}
+
+    impl<T> IntoIterator for List<T> {
+        type Item = T;
+        type IntoIter = IntoIter<T>;
+        fn into_iter(self) -> Self::IntoIter {
+            IntoIter(self)
+        }
+    }
+    impl<'a, T> IntoIterator for &'a List<T> {
+        type Item = &'a T;
+        type IntoIter = Iter<'a, T>;
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+    impl<'a, T> IntoIterator for &'a mut List<T> {
+        type Item = &'a mut T;
+        type IntoIter = IterMut<'a, T>;
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter_mut()
+        }
+    }
+    impl<T> FromIterator<T> for List<T> {
+        fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+            let mut list = Self::new();
+            list.extend(iter);
+            list
+        }
+    }
+    impl<T> Extend<T> for List<T> {
+        fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+            for value in iter {
+                self.push_back(value);
+            }
+        }
+    }
+
+    // Handle は sentinel 以外のノードの Ptr。同名の inherent method と区別するために List:: を付けて呼ぶ
+    fn handle<T>(node: Ref<Node<T>>) -> Option<Ptr<Node<T>>> {
+        if node.is_sentinel() {
+            None
+        } else {
+            Some(node.into())
+        }
+    }
+    impl<T> LinkedList<T> for List<T> {
+        type Handle = Ptr<Node<T>>;
+        type Iter<'a>
+            = Iter<'a, T>
+        where
+            T: 'a;
+        const NAME: &'static str = "mepoo";
+        fn new() -> Self {
+            List::new()
+        }
+        fn len(&self) -> usize {
+            self.len
+        }
+        fn iter(&self) -> Iter<'_, T> {
+            List::iter(self)
+        }
+        fn head(&self) -> Option<Ptr<Node<T>>> {
+            handle(List::head(self))
+        }
+        fn tail(&self) -> Option<Ptr<Node<T>>> {
+            handle(List::tail(self))
+        }
+        fn next(&self, h: Ptr<Node<T>>) -> Option<Ptr<Node<T>>> {
+            handle(
+                self.get_ref(h)
+                    .filter(|node| !node.is_sentinel())?
+                    .get()
+                    .next(),
+            )
+        }
+        fn prev(&self, h: Ptr<Node<T>>) -> Option<Ptr<Node<T>>> {
+            handle(
+                self.get_ref(h)
+                    .filter(|node| !node.is_sentinel())?
+                    .get()
+                    .prev(),
+            )
+        }
+        fn nth(&self, i: usize) -> Option<Ptr<Node<T>>> {
+            List::nth(self, i).map(Into::into)
+        }
+        fn nth_back(&self, i: usize) -> Option<Ptr<Node<T>>> {
+            List::nth_back(self, i).map(Into::into)
+        }
+        fn position(&self, h: Ptr<Node<T>>) -> Option<usize> {
+            List::position(self, h)
+        }
+        fn get(&self, h: Ptr<Node<T>>) -> Option<&T> {
+            self.nodes.get(h)?.get().value.as_ref()
+        }
+        fn get_mut(&mut self, h: Ptr<Node<T>>) -> Option<&mut T> {
+            self.nodes.get_mut(h)?.value.as_mut()
+        }
+        fn insert(&mut self, h: Ptr<Node<T>>, value: T) -> Option<Ptr<Node<T>>> {
+            LinkedList::get(self, h)?;
+            List::insert(self, h, value);
+            handle(self.get_ref(h)?.get().prev())
+        }
+        fn push_back(&mut self, value: T) -> Ptr<Node<T>> {
+            List::push_back(self, value);
+            List::tail(self).into()
+        }
+        fn push_front(&mut self, value: T) -> Ptr<Node<T>> {
+            List::push_front(self, value);
+            List::head(self).into()
+        }
+        fn remove(&mut self, h: Ptr<Node<T>>) -> Option<T> {
+            self.take(h)
+        }
+        fn pop_front(&mut self) -> Option<T> {
+            List::pop_front(self)
+        }
+        fn pop_back(&mut self) -> Option<T> {
+            List::pop_back(self)
+        }
+        fn validate(&self) -> Report {
+            List::validate(self)
+        }
+        // mepoo の Pool は中を見せないので、sentinel を含めたノードの大きさだけを数える。
+        // 空きスロットや Pool 自身の領域は含まないので、実際の使用量は count-alloc で測る
+        // mepoo の Pool は確保したバイト数を教えてくれないので bytes と peak は測らない
+        fn memory(&self) -> Usage {
+            Usage {
+                live: self.len + 1,
+                free: 0,
+                bytes: None,
+                peak: None,
             }
         }
     }
@@ -152,17 +594,25 @@ fn test_list() {
 
     assert!(list.remove(list.head().into()).is_some());
     assert_eq!(*list.head().value(), 4);
+    assert!(list.remove(list.head().prev().into()).is_none());
 
     let mut node = list.get_mut(list.head().next().into()).unwrap();
     *node = 5;
     assert_eq!(*list.head().next().value(), 5);
 
-    unsafe {
+    {
+        // get_mut_unchecked で得た &mut Node を std::mem::swap すると壊れるが、
+        // NodeMut 同士なら値だけが入れ替わる
         let mut list2: List<usize> = List::new();
         list2.push_back(6);
-        let node1 = list.get_mut_unchecked(list.head().into()).unwrap();
-        let node2 = list2.get_mut_unchecked(list2.head().into()).unwrap();
-        std::mem::swap(node1, node2); // 壊れる！
+        let mut node1 = list.get_mut(list.head().into()).unwrap();
+        let mut node2 = list2.get_mut(list2.head().into()).unwrap();
+        node1.swap(&mut node2);
+        assert_eq!(node1.replace(7), 6);
+        assert_eq!(*list.head().value(), 7);
+        assert_eq!(*list2.head().value(), 4);
+        assert!(list2.head().get().next().is_sentinel());
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![7, 5, 2]);
     }
 
     // not compilable
@@ -172,3 +622,58 @@ fn test_list() {
     println!("{}", **head);
     */
 }
+
+#[test]
+fn test_swap() {
+    let mut list: List<usize> = (0..5).collect();
+    let ptrs: Vec<mepoo::Ptr<Node<usize>>> = (0..5).map(|i| list.nth(i).unwrap().into()).collect();
+    let values = |list: &List<usize>| list.iter().copied().collect::<Vec<_>>();
+
+    assert!(list.swap_values(ptrs[0], ptrs[3]));
+    assert_eq!(values(&list), vec![3, 1, 2, 0, 4]);
+    assert_eq!(*list.get_ref(ptrs[0]).unwrap().value(), 3);
+    assert!(list.swap_values(ptrs[0], ptrs[3]));
+    assert!(list.swap_values(ptrs[2], ptrs[2]));
+    assert_eq!(values(&list), vec![0, 1, 2, 3, 4]);
+
+    // 離れたノード、隣り合うノード（両方の順序）、端のノード
+    assert!(list.swap_nodes(ptrs[1], ptrs[3]));
+    assert_eq!(values(&list), vec![0, 3, 2, 1, 4]);
+    assert!(list.swap_nodes(ptrs[3], ptrs[2]));
+    assert_eq!(values(&list), vec![0, 2, 3, 1, 4]);
+    assert!(list.swap_nodes(ptrs[3], ptrs[2]));
+    assert_eq!(values(&list), vec![0, 3, 2, 1, 4]);
+    assert!(list.swap_nodes(ptrs[4], ptrs[0]));
+    assert_eq!(values(&list), vec![4, 3, 2, 1, 0]);
+    assert!(list.swap_nodes(ptrs[1], ptrs[1]));
+    assert_eq!(
+        list.iter().rev().copied().collect::<Vec<_>>(),
+        vec![0, 1, 2, 3, 4]
+    );
+    assert_eq!(list.position(ptrs[4]), Some(0));
+    assert_eq!(list.position(ptrs[3]), Some(1));
+    assert_eq!(list.position(ptrs[0]), Some(4));
+
+    // sentinel や別のリストのノードとは入れ替えられない
+    let other: List<usize> = (0..1).collect();
+    let sentinel = list.head().get().prev().into();
+    assert!(!list.swap_values(ptrs[0], sentinel));
+    assert!(!list.swap_nodes(ptrs[0], sentinel));
+    assert!(!list.swap_values(ptrs[0], other.head().into()));
+    assert!(!list.swap_nodes(ptrs[0], other.head().into()));
+    assert_eq!(values(&list), vec![4, 3, 2, 1, 0]);
+}
+
+// get_mut_unchecked で別のリストのノードと丸ごと入れ替えられる
+#[test]
+fn test_validate() {
+    crate::linked_list::exercise_validate::<List<usize>>(
+        |list1, p1, list2, p2| unsafe {
+            std::mem::swap(
+                list1.get_mut_unchecked(p1).unwrap(),
+                list2.get_mut_unchecked(p2).unwrap(),
+            )
+        },
+        "not in this list",
+    );
+}
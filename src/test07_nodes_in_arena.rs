@@ -51,6 +51,7 @@ mod without_pointer {
 }
 
 mod with_pointer {
+    use crate::validate::{Report, Violation};
     use typed_arena::Arena;
 
     pub struct Node<T> {
@@ -78,18 +79,21 @@ mod with_pointer {
 
     pub fn construct_graph() -> Graph<usize> {
         let nodes: Arena<Node<usize>> = Arena::new();
-        let node1 = nodes.alloc(Node {
+        // alloc が返す &mut を使って書き込むと、それ以前に作った生ポインタが無効になるので
+        // 先に両方とも生ポインタにしてから相互参照を作る
+        let node1: *mut Node<usize> = nodes.alloc(Node {
             value: 123,
             other: std::ptr::null_mut(),
         });
-        let node2 = nodes.alloc(Node {
+        let node2: *mut Node<usize> = nodes.alloc(Node {
             value: 456,
             other: std::ptr::null_mut(),
         });
-        node2.other = node1 as *mut Node<usize>;
-        node1.other = node2 as *mut Node<usize>;
-        let head = node1 as *mut Node<usize>;
-        Graph { nodes, head }
+        unsafe {
+            (*node2).other = node1;
+            (*node1).other = node2;
+        }
+        Graph { nodes, head: node1 }
     }
     impl<T> Node<T> {
         pub fn other(&self) -> &Self {
@@ -105,6 +109,16 @@ mod with_pointer {
         pub fn head_mut(&mut self) -> NodeRefMut<T> {
             unsafe { NodeRefMut(&mut *self.head) }
         }
+        pub fn validate(&self) -> Report {
+            let mut report = Report::default();
+            if !std::ptr::eq(self.head().other().other(), self.head) {
+                report.push(Violation::BrokenLink {
+                    at: 0,
+                    link: "other.other",
+                });
+            }
+            report
+        }
     }
 }
 
@@ -113,6 +127,7 @@ fn test_with_pointer() {
     use with_pointer::*;
     let graph = construct_graph();
     assert_eq!(graph.head().value, graph.head().other().other().value);
+    graph.validate().assert_ok();
 
     let mut graph1 = construct_graph();
     let mut graph2 = construct_graph();
@@ -122,6 +137,8 @@ fn test_with_pointer() {
 }
 
 pub mod pool {
+    use crate::memory::Usage;
+    use std::mem::MaybeUninit;
     use std::ptr::NonNull;
     use typed_arena::Arena;
 
@@ -145,49 +162,84 @@ pub mod pool {
 
     pub use id::PoolId;
 
-    #[derive(Debug, PartialEq, Eq, Hash)]
     pub struct Ptr<T> {
         ptr: NonNull<T>,
         pool_id: PoolId,
-    }
-    impl<T> std::ops::Deref for Ptr<T> {
-        type Target = NonNull<T>;
-        fn deref(&self) -> &Self::Target {
-            &self.ptr
-        }
-    }
-    impl<T> std::ops::DerefMut for Ptr<T> {
-        fn deref_mut(&mut self) -> &mut Self::Target {
-            &mut self.ptr
-        }
+        generation: u32,
     }
     impl<T> Clone for Ptr<T> {
         fn clone(&self) -> Self {
             Self {
                 ptr: self.ptr,
                 pool_id: self.pool_id,
+                generation: self.generation,
             }
         }
     }
     impl<T> Copy for Ptr<T> {}
+    // Ptr はただの番号で、中身に触るには Pool を通すか unsafe な as_ref / as_mut を使う。
+    // そのためスレッドを跨いで渡しても、それだけで T を共有することにはならない
+    unsafe impl<T> Send for Ptr<T> {}
+    unsafe impl<T> Sync for Ptr<T> {}
+    impl<T> PartialEq for Ptr<T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.ptr == other.ptr
+                && self.pool_id == other.pool_id
+                && self.generation == other.generation
+        }
+    }
+    impl<T> Eq for Ptr<T> {}
+    impl<T> std::hash::Hash for Ptr<T> {
+        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+            self.ptr.hash(state);
+            self.pool_id.hash(state);
+            self.generation.hash(state);
+        }
+    }
+    impl<T> std::fmt::Debug for Ptr<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.debug_struct("Ptr")
+                .field("ptr", &self.ptr)
+                .field("pool_id", &self.pool_id)
+                .field("generation", &self.generation)
+                .finish()
+        }
+    }
     impl<T> Ptr<T> {
         pub const DANGLING: Self = Self {
             ptr: NonNull::dangling(),
             pool_id: PoolId::ZERO,
+            generation: 0,
         };
+        // ptr を書き換えられると check が別のアドレスを信じてしまうので、読むだけにする
+        pub fn as_ptr(&self) -> *mut T {
+            self.ptr.as_ptr()
+        }
         pub unsafe fn as_ref<'a>(&self) -> Ref<'a, T> {
             Ref {
                 value: &*self.ptr.as_ptr(),
-                pool_id: self.pool_id,
+                ptr: *self,
             }
         }
+        pub unsafe fn as_mut<'a>(&mut self) -> &'a mut T {
+            &mut *self.ptr.as_ptr()
+        }
+        fn slot(&self) -> *mut Slot<T> {
+            self.ptr.as_ptr() as *mut Slot<T>
+        }
     }
 
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[derive(Debug, PartialEq, Eq, Hash)]
     pub struct Ref<'a, T> {
         value: &'a T,
-        pool_id: PoolId,
+        ptr: Ptr<T>,
+    }
+    impl<'a, T> Clone for Ref<'a, T> {
+        fn clone(&self) -> Self {
+            *self
+        }
     }
+    impl<'a, T> Copy for Ref<'a, T> {}
     impl<'a, T> Ref<'a, T> {
         pub fn get(&self) -> &'a T {
             self.value
@@ -201,43 +253,219 @@ pub mod pool {
     }
     impl<'a, T> From<Ref<'a, T>> for Ptr<T> {
         fn from(src: Ref<'a, T>) -> Self {
-            Self {
-                ptr: src.value.into(),
-                pool_id: src.pool_id,
+            src.ptr
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub enum Error {
+        ForeignPool, // 別の Pool で確保された Ptr
+        Dangling,    // Ptr::DANGLING
+        Removed,     // free 済みのスロットを指している Ptr
+    }
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                Error::ForeignPool => write!(f, "pointer belongs to another pool"),
+                Error::Dangling => write!(f, "dangling pointer"),
+                Error::Removed => write!(f, "pointer to a removed object"),
             }
         }
     }
+    impl std::error::Error for Error {}
+
+    // value を先頭に置いているので Ptr<T> のアドレスはそのまま Slot<T> のアドレスになる
+    #[repr(C)]
+    struct Slot<T> {
+        value: MaybeUninit<T>,
+        generation: u32, // 偶数なら使用中、奇数なら free list に入っている
+    }
 
     pub struct Pool<T> {
-        arena: Arena<T>,
+        arena: Arena<Slot<T>>,
+        free: Vec<NonNull<Slot<T>>>,
         id: PoolId,
+        slots: usize, // arena から確保したスロットの数。Arena::len は chunk を辿るので自分で数える
+        peak: usize,
     }
     impl<T> Pool<T> {
         pub fn new() -> Self {
             Self {
                 arena: Arena::new(),
+                free: Vec::new(),
                 id: PoolId::gen(),
+                slots: 0,
+                peak: 0,
             }
         }
         pub fn alloc(&mut self, value: T) -> Ptr<T> {
+            let slot = if let Some(slot) = self.free.pop() {
+                unsafe {
+                    let slot = slot.as_ptr();
+                    (*slot).value = MaybeUninit::new(value);
+                    (*slot).generation = (*slot).generation.wrapping_add(1);
+                    slot
+                }
+            } else {
+                self.slots += 1;
+                self.peak = self.peak.max(self.bytes());
+                self.arena.alloc(Slot {
+                    value: MaybeUninit::new(value),
+                    generation: 0,
+                }) as *mut Slot<T>
+            };
             Ptr {
-                ptr: self.arena.alloc(value).into(),
+                ptr: unsafe { NonNull::new_unchecked(slot as *mut T) },
                 pool_id: self.id,
+                generation: unsafe { (*slot).generation },
             }
         }
-        pub fn get(&self, p: Ptr<T>) -> Ref<T> {
-            assert_eq!(p.pool_id, self.id);
-            unsafe { p.as_ref() }
+        pub fn free(&mut self, p: Ptr<T>) -> Option<T> {
+            if self.check(p).is_ok() {
+                unsafe {
+                    let slot = p.slot();
+                    (*slot).generation = (*slot).generation.wrapping_add(1);
+                    self.free.push(NonNull::new_unchecked(slot));
+                    self.peak = self.peak.max(self.bytes());
+                    Some((*slot).value.as_ptr().read())
+                }
+            } else {
+                None
+            }
         }
-        pub fn get_mut(&mut self, p: Ptr<T>) -> &mut T {
-            assert_eq!(p.pool_id, self.id);
-            unsafe { &mut *p.ptr.as_ptr() }
+        fn check(&self, p: Ptr<T>) -> Result<(), Error> {
+            // pool_id が一致して初めて p が self.arena の中を指していると言える
+            if p.pool_id == PoolId::ZERO {
+                Err(Error::Dangling)
+            } else if p.pool_id != self.id {
+                Err(Error::ForeignPool)
+            } else if unsafe { (*p.slot()).generation } != p.generation {
+                Err(Error::Removed)
+            } else {
+                Ok(())
+            }
+        }
+        pub fn try_get(&self, p: Ptr<T>) -> Result<Ref<'_, T>, Error> {
+            self.check(p)?;
+            Ok(unsafe { p.as_ref() })
+        }
+        pub fn try_get_mut(&mut self, p: Ptr<T>) -> Result<&mut T, Error> {
+            self.check(p)?;
+            Ok(unsafe { &mut *p.ptr.as_ptr() })
+        }
+        pub fn get(&self, p: Ptr<T>) -> Option<Ref<'_, T>> {
+            self.try_get(p).ok()
+        }
+        pub fn get_mut(&mut self, p: Ptr<T>) -> Option<&mut T> {
+            self.try_get_mut(p).ok()
+        }
+        // arena は解放しないので、free したスロットも数に含まれる
+        fn bytes(&self) -> usize {
+            self.slots * std::mem::size_of::<Slot<T>>()
+                + self.free.capacity() * std::mem::size_of::<NonNull<Slot<T>>>()
         }
+        pub fn usage(&self) -> Usage {
+            Usage {
+                live: self.slots - self.free.len(),
+                free: self.free.len(),
+                bytes: Some(self.bytes()),
+                peak: Some(self.peak),
+            }
+        }
+    }
+    // Pool は確保した T を全て所有している。&self のメソッドは Arena（内部に RefCell を持つ）に触らず
+    // スロットを読むだけなので、T が Sync なら複数のスレッドから同時に読んでもよい
+    unsafe impl<T: Send> Send for Pool<T> {}
+    unsafe impl<T: Sync> Sync for Pool<T> {}
+    impl<T> Drop for Pool<T> {
+        fn drop(&mut self) {
+            for slot in self.arena.iter_mut() {
+                if slot.generation % 2 == 0 {
+                    unsafe { slot.value.as_mut_ptr().drop_in_place() }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_pool() {
+    use pool::*;
+    let mut pool = Pool::new();
+    let p = pool.alloc(123);
+    assert_eq!(*pool.get(p).unwrap(), 123);
+
+    *pool.get_mut(p).unwrap() = 456;
+    assert_eq!(*pool.get(p).unwrap(), 456);
+
+    assert_eq!(pool.free(p), Some(456));
+    assert!(pool.get(p).is_none());
+    assert!(pool.get_mut(p).is_none());
+    assert!(pool.free(p).is_none());
+
+    // 解放したスロットが再利用されても古い Ptr では読めない
+    let q = pool.alloc(789);
+    assert_eq!(q.as_ptr(), p.as_ptr());
+    assert_ne!(q, p);
+    assert!(pool.get(p).is_none());
+    assert_eq!(*pool.get(q).unwrap(), 789);
+}
+
+#[test]
+fn test_pool_usage() {
+    use pool::*;
+    let mut pool = Pool::new();
+    let ptrs = (0..10).map(|i| pool.alloc(i as u64)).collect::<Vec<_>>();
+    let usage = pool.usage();
+    assert_eq!((usage.live, usage.free), (10, 0));
+    assert_eq!(usage.peak, usage.bytes);
+    for &p in &ptrs[..4] {
+        pool.free(p);
+    }
+    let usage = pool.usage();
+    assert_eq!((usage.live, usage.free), (6, 4));
+
+    // 空きスロットを使い切るまでは arena は増えない
+    let bytes = usage.bytes;
+    for i in 0..4 {
+        pool.alloc(i);
     }
+    let usage = pool.usage();
+    assert_eq!((usage.live, usage.free), (10, 0));
+    assert_eq!(usage.bytes, bytes);
+    pool.alloc(10);
+    assert!(pool.usage().bytes > bytes);
+    assert_eq!(pool.usage().peak, pool.usage().bytes);
+}
+
+#[test]
+fn test_pool_try_get() {
+    use pool::*;
+    let mut pool1 = Pool::new();
+    let mut pool2 = Pool::new();
+    let p1 = pool1.alloc(1);
+    let p2 = pool2.alloc(2);
+    assert_eq!(*pool1.try_get(p1).unwrap(), 1);
+    assert_eq!(pool1.try_get(p2), Err(Error::ForeignPool));
+    assert_eq!(pool1.try_get_mut(p2), Err(Error::ForeignPool));
+    assert_eq!(pool1.try_get(Ptr::DANGLING), Err(Error::Dangling));
+    assert!(pool1.free(p2).is_none());
+
+    assert_eq!(pool1.free(p1), Some(1));
+    assert_eq!(pool1.try_get(p1), Err(Error::Removed));
+    assert_eq!(pool1.try_get_mut(p1), Err(Error::Removed));
+    assert_eq!(*pool2.try_get_mut(p2).unwrap(), 2);
 }
 
 pub mod list {
     use super::pool::*;
+    use crate::linked_list::LinkedList;
+    use crate::memory::Usage;
+    use crate::validate::{self, NodeLinks, Report};
+    #[cfg(test)]
+    use std::collections::HashMap;
+    use std::iter::FromIterator;
+    use std::marker::PhantomData;
     use std::ops::{Deref, DerefMut};
 
     pub struct Node<T> {
@@ -259,6 +487,10 @@ pub mod list {
             assert!(!self.is_sentinel());
             self.value.as_ref().unwrap()
         }
+        // List::detach で外されて、解放されずに取っておかれている
+        fn is_detached(&self) -> bool {
+            self.next == Ptr::DANGLING
+        }
     }
 
     pub struct NodeMut<'a, T>(&'a mut Node<T>);
@@ -274,23 +506,75 @@ pub mod list {
         }
     }
 
+    fn alloc_sentinel<T>(nodes: &mut Pool<Node<T>>) -> Ptr<Node<T>> {
+        let mut sentinel = nodes.alloc(Node {
+            value: None,
+            next: Ptr::DANGLING,
+            prev: Ptr::DANGLING,
+        });
+        unsafe {
+            sentinel.as_mut().next = sentinel;
+            sentinel.as_mut().prev = sentinel;
+        }
+        sentinel
+    }
+
+    // first から last までのノードを前後から切り離す
+    #[cfg(test)]
+    unsafe fn unlink_range<T>(first: Ptr<Node<T>>, last: Ptr<Node<T>>) {
+        let mut prev = first.as_ref().prev;
+        let mut next = last.as_ref().next;
+        prev.as_mut().next = next;
+        next.as_mut().prev = prev;
+    }
+
+    // first から last までのノードを pos の直前に繋ぐ
+    #[cfg(test)]
+    unsafe fn link_range_before<T>(
+        mut pos: Ptr<Node<T>>,
+        mut first: Ptr<Node<T>>,
+        mut last: Ptr<Node<T>>,
+    ) {
+        let mut prev = pos.as_ref().prev;
+        prev.as_mut().next = first;
+        first.as_mut().prev = prev;
+        last.as_mut().next = pos;
+        pos.as_mut().prev = last;
+    }
+
+    // try_get が失敗した理由（別の Pool の Ptr か、解放済みか）もそのまま報告する
+    fn validate_list<T>(
+        nodes: &Pool<Node<T>>,
+        sentinel: Ptr<Node<T>>,
+        len: Option<usize>,
+    ) -> Report {
+        validate::check_sentinel_list(sentinel, len, |p| {
+            nodes
+                .try_get(p)
+                .map(|node| NodeLinks {
+                    key: p.as_ptr() as usize,
+                    is_sentinel: node.is_sentinel(),
+                    next: node.next,
+                    prev: node.prev,
+                })
+                .map_err(|e| e.to_string())
+        })
+    }
+
     pub struct List<T> {
         nodes: Pool<Node<T>>,
         sentinel: Ptr<Node<T>>,
+        len: usize,
     }
     impl<T> List<T> {
         pub fn new() -> Self {
             let mut nodes = Pool::new();
-            let mut sentinel = nodes.alloc(Node {
-                value: None,
-                next: Ptr::DANGLING,
-                prev: Ptr::DANGLING,
-            });
-            unsafe {
-                sentinel.as_mut().next = sentinel;
-                sentinel.as_mut().prev = sentinel;
+            let sentinel = alloc_sentinel(&mut nodes);
+            Self {
+                nodes,
+                sentinel,
+                len: 0,
             }
-            Self { nodes, sentinel }
         }
         pub fn sentinel(&self) -> Ptr<Node<T>> {
             self.sentinel
@@ -304,16 +588,110 @@ pub mod list {
         pub fn is_empty(&self) -> bool {
             self.head().is_sentinel()
         }
-        pub fn get(&self, p: Ptr<Node<T>>) -> Option<Ref<Node<T>>> {
-            let r = self.nodes.get(p);
-            if r.value.is_some() {
-                Some(r)
+        pub fn len(&self) -> usize {
+            self.len
+        }
+        // sentinel から一周してリンクと len を検査する。変更の後に debug build で呼ぶ
+        pub fn validate(&self) -> Report {
+            validate_list(&self.nodes, self.sentinel, Some(self.len))
+        }
+        // 近い方の端から辿る
+        pub fn nth(&self, i: usize) -> Option<Ref<'_, Node<T>>> {
+            if i >= self.len {
+                None
+            } else if i <= self.len / 2 {
+                let mut node = self.head();
+                for _ in 0..i {
+                    node = node.get().next();
+                }
+                Some(node)
             } else {
+                self.nth_back(self.len - 1 - i)
+            }
+        }
+        pub fn nth_back(&self, i: usize) -> Option<Ref<'_, Node<T>>> {
+            if i >= self.len {
                 None
+            } else if i <= self.len / 2 {
+                let mut node = self.tail();
+                for _ in 0..i {
+                    node = node.get().prev();
+                }
+                Some(node)
+            } else {
+                self.nth(self.len - 1 - i)
+            }
+        }
+        pub fn position(&self, ptr: Ptr<Node<T>>) -> Option<usize> {
+            self.get(ptr)?;
+            let mut node = self.head();
+            let mut i = 0;
+            while !node.is_sentinel() {
+                if Ptr::from(node) == ptr {
+                    return Some(i);
+                }
+                node = node.get().next();
+                i += 1;
+            }
+            None
+        }
+        pub fn iter(&self) -> Iter<'_, T> {
+            Iter {
+                head: self.head().into(),
+                tail: self.tail().into(),
+                len: self.len,
+                _marker: PhantomData,
+            }
+        }
+        pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+            IterMut {
+                head: self.head().into(),
+                tail: self.tail().into(),
+                len: self.len,
+                _marker: PhantomData,
+            }
+        }
+        // sentinel は値を持たないので Ptr::DANGLING と同じ扱いにし、detach したノードは削除済みとみなす
+        pub fn try_get(&self, p: Ptr<Node<T>>) -> Result<Ref<'_, Node<T>>, Error> {
+            let node = self.nodes.try_get(p)?;
+            if node.is_sentinel() {
+                Err(Error::Dangling)
+            } else if node.is_detached() {
+                Err(Error::Removed)
+            } else {
+                Ok(node)
+            }
+        }
+        pub fn try_get_mut(&mut self, p: Ptr<Node<T>>) -> Result<&mut T, Error> {
+            self.try_get(p)?;
+            Ok(self.nodes.try_get_mut(p)?.value.as_mut().unwrap())
+        }
+        pub fn get(&self, p: Ptr<Node<T>>) -> Option<Ref<Node<T>>> {
+            self.try_get(p).ok()
+        }
+        pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+            let current = unsafe { self.sentinel.as_ref() }.next;
+            CursorMut {
+                list: self,
+                current,
             }
         }
+        pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+            let current = unsafe { self.sentinel.as_ref() }.prev;
+            CursorMut {
+                list: self,
+                current,
+            }
+        }
+        pub fn cursor_mut(&mut self, ptr: Ptr<Node<T>>) -> Option<CursorMut<'_, T>> {
+            self.nodes.try_get(ptr).ok().filter(|r| !r.is_detached())?;
+            Some(CursorMut {
+                list: self,
+                current: ptr,
+            })
+        }
         unsafe fn insert_unsafe(&mut self, mut next: Ptr<Node<T>>, value: T) {
-            let mut prev = self.nodes.get(next).prev;
+            let mut prev = next.as_ref().prev;
             let node = self.nodes.alloc(Node {
                 value: Some(value),
                 next,
@@ -323,6 +701,7 @@ pub mod list {
                 next.as_mut().prev = node;
                 prev.as_mut().next = node;
             }
+            self.len += 1;
         }
         pub fn insert(&mut self, pos: Ptr<Node<T>>, value: T) -> bool {
             if self.get(pos).is_some() {
@@ -339,18 +718,617 @@ pub mod list {
             unsafe { self.insert_unsafe(self.sentinel.as_ref().next, value) }
         }
         pub fn remove(&mut self, node: Ptr<Node<T>>) -> Option<Ref<Node<T>>> {
-            if let Some(node_ref) = self.get(node) {
-                let mut next = node_ref.next;
-                let mut prev = node_ref.prev;
+            let (_, next) = self.unlink(node)?;
+            Some(unsafe { next.as_ref() })
+        }
+        // remove と違い、取り除いた値をそのまま返す
+        pub fn take(&mut self, node: Ptr<Node<T>>) -> Option<T> {
+            self.unlink(node).map(|(value, _)| value)
+        }
+        pub fn pop_front(&mut self) -> Option<T> {
+            let head = self.head().into();
+            self.take(head)
+        }
+        pub fn pop_back(&mut self) -> Option<T> {
+            let tail = self.tail().into();
+            self.take(tail)
+        }
+        // node をリストから外して値と次のノードを返す
+        fn unlink(&mut self, node: Ptr<Node<T>>) -> Option<(T, Ptr<Node<T>>)> {
+            let node_ref = self.get(node)?;
+            let mut next = node_ref.next;
+            let mut prev = node_ref.prev;
+            let value = self.nodes.free(node)?.value?;
+            unsafe {
+                next.as_mut().prev = prev;
+                prev.as_mut().next = next;
+            }
+            self.len -= 1;
+            Some((value, next))
+        }
+
+        // 以下は test14 の History が使う。外したノードを解放せずに取っておき、同じ Ptr のまま戻せるようにする。
+        // node を外して次のノード（sentinel かもしれない）を返す。node は get などからは見えなくなる
+        #[cfg(test)]
+        pub(crate) fn detach(&mut self, node: Ptr<Node<T>>) -> Option<Ptr<Node<T>>> {
+            let next = self.get(node)?.next;
+            let mut node = node;
+            unsafe {
+                unlink_range(node, node);
+                node.as_mut().next = Ptr::DANGLING;
+                node.as_mut().prev = Ptr::DANGLING;
+            }
+            self.len -= 1;
+            Some(next)
+        }
+        // detach したノードを next の直前に戻す
+        #[cfg(test)]
+        pub(crate) fn attach(&mut self, node: Ptr<Node<T>>, next: Ptr<Node<T>>) -> bool {
+            let detached = self.nodes.try_get(node).is_ok_and(|r| r.is_detached());
+            let linked = self.nodes.try_get(next).is_ok_and(|r| !r.is_detached());
+            if !detached || !linked {
+                return false;
+            }
+            unsafe { link_range_before(next, node, node) };
+            self.len += 1;
+            true
+        }
+        // detach したままのノードを解放する。繋がっているノードなら何もしない
+        #[cfg(test)]
+        pub(crate) fn free_detached(&mut self, node: Ptr<Node<T>>) -> Option<T> {
+            self.nodes.try_get(node).ok().filter(|r| r.is_detached())?;
+            self.nodes.free(node)?.value
+        }
+    }
+
+    // current が sentinel を指しているときは末尾と先頭の間にいるとみなす。
+    // List ごとに Pool を持つので、ノードを別の List に移すと Ptr が変わってしまう。そのため test05 の CursorMut と違って
+    // split_after / splice_after は持たせない。Ptr を保ったままノードを付け替えたいときは ListPool の PoolCursorMut を使う
+    pub struct CursorMut<'a, T> {
+        list: &'a mut List<T>,
+        current: Ptr<Node<T>>,
+    }
+    impl<'a, T> CursorMut<'a, T> {
+        pub fn move_next(&mut self) {
+            self.current = unsafe { self.current.as_ref() }.next;
+        }
+        pub fn move_prev(&mut self) {
+            self.current = unsafe { self.current.as_ref() }.prev;
+        }
+        pub fn current(&mut self) -> Option<&mut T> {
+            unsafe { self.current.as_mut() }.value.as_mut()
+        }
+        pub fn insert_before(&mut self, value: T) {
+            unsafe { self.list.insert_unsafe(self.current, value) }
+        }
+        pub fn insert_after(&mut self, value: T) {
+            let next = unsafe { self.current.as_ref() }.next;
+            unsafe { self.list.insert_unsafe(next, value) }
+        }
+        // 削除した後は次のノードに移動する
+        pub fn remove_current(&mut self) -> Option<T> {
+            let (value, next) = self.list.unlink(self.current)?;
+            self.current = next;
+            Some(value)
+        }
+    }
+
+    #[cfg(test)]
+    pub struct ListId<T>(Ptr<Node<T>>);
+    #[cfg(test)]
+    impl<T> Clone for ListId<T> {
+        fn clone(&self) -> Self {
+            *self
+        }
+    }
+    #[cfg(test)]
+    impl<T> Copy for ListId<T> {}
+    #[cfg(test)]
+    impl<T> PartialEq for ListId<T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+    #[cfg(test)]
+    impl<T> Eq for ListId<T> {}
+    #[cfg(test)]
+    impl<T> std::hash::Hash for ListId<T> {
+        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+            self.0.hash(state);
+        }
+    }
+    #[cfg(test)]
+    impl<T> std::fmt::Debug for ListId<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.debug_tuple("ListId").field(&self.0).finish()
+        }
+    }
+
+    // 一つの Pool を複数のリストで共有する。ノードは Pool の中で付け替えるだけなので、移したノードの Ptr もそのまま使える。
+    // ノードがどのリストに属するかは覚えていないので、リストの形を変える操作は ListId から作った PoolCursorMut を通す。
+    // カーソルは自分のリストと位置を知っているので、split_after / splice_after なども引数を確かめるのに辿る必要がなく、
+    // 長さも付け替えるノードの数から分かるので O(1) で済む。
+    #[cfg(test)]
+    pub struct ListPool<T> {
+        nodes: Pool<Node<T>>,
+        lens: HashMap<ListId<T>, usize>, // 生きているリストとその長さ
+    }
+    #[cfg(test)]
+    impl<T> ListPool<T> {
+        pub fn new() -> Self {
+            Self {
+                nodes: Pool::new(),
+                lens: HashMap::new(),
+            }
+        }
+        pub fn new_list(&mut self) -> ListId<T> {
+            let id = ListId(alloc_sentinel(&mut self.nodes));
+            self.lens.insert(id, 0);
+            id
+        }
+        // 全てのノードを解放する。以降 id は使えない。
+        pub fn remove_list(&mut self, id: ListId<T>) -> bool {
+            if self.lens.remove(&id).is_none() {
+                return false;
+            }
+            let mut node = unsafe { id.0.as_ref() }.next;
+            while node != id.0 {
+                let next = unsafe { node.as_ref() }.next;
+                self.nodes.free(node);
+                node = next;
+            }
+            self.nodes.free(id.0).is_some()
+        }
+        // lens に無い id（削除済みか別の ListPool のもの）は Pool に触らずに弾く
+        fn sentinel(&self, id: ListId<T>) -> Option<Ref<'_, Node<T>>> {
+            if self.lens.contains_key(&id) {
+                Some(unsafe { id.0.as_ref() })
+            } else {
+                None
+            }
+        }
+        pub fn sentinel_of(&self, id: ListId<T>) -> Option<Ptr<Node<T>>> {
+            self.sentinel(id).map(Ptr::from)
+        }
+        pub fn validate(&self, id: ListId<T>) -> Report {
+            validate_list(&self.nodes, id.0, self.len(id))
+        }
+        pub fn len(&self, id: ListId<T>) -> Option<usize> {
+            self.lens.get(&id).copied()
+        }
+        pub fn head(&self, id: ListId<T>) -> Option<Ref<'_, Node<T>>> {
+            self.sentinel(id)
+                .map(|sentinel| sentinel.get().next())
+                .filter(|head| !head.is_sentinel())
+        }
+        pub fn tail(&self, id: ListId<T>) -> Option<Ref<'_, Node<T>>> {
+            self.sentinel(id)
+                .map(|sentinel| sentinel.get().prev())
+                .filter(|tail| !tail.is_sentinel())
+        }
+        pub fn is_empty(&self, id: ListId<T>) -> bool {
+            self.head(id).is_none()
+        }
+        pub fn iter(&self, id: ListId<T>) -> impl Iterator<Item = &T> + '_ {
+            let mut node = self.head(id);
+            std::iter::from_fn(move || {
+                let current = node?.get();
+                let next = current.next();
+                node = if next.is_sentinel() { None } else { Some(next) };
+                current.value.as_ref()
+            })
+        }
+        pub fn get(&self, p: Ptr<Node<T>>) -> Option<Ref<'_, Node<T>>> {
+            self.nodes.try_get(p).ok().filter(|r| !r.is_sentinel())
+        }
+        pub fn get_mut(&mut self, p: Ptr<Node<T>>) -> Option<NodeMut<'_, T>> {
+            self.nodes
+                .try_get_mut(p)
+                .ok()
+                .filter(|node| !node.is_sentinel())
+                .map(NodeMut)
+        }
+        pub fn push_back(&mut self, id: ListId<T>, value: T) -> Option<Ptr<Node<T>>> {
+            self.sentinel(id)?;
+            Some(self.link_new(id, id.0, value))
+        }
+        pub fn push_front(&mut self, id: ListId<T>, value: T) -> Option<Ptr<Node<T>>> {
+            let head = self.sentinel(id)?.next;
+            Some(self.link_new(id, head, value))
+        }
+        // src の全てのノードを dst の末尾に移す。src は空になる。
+        pub fn append(&mut self, dst: ListId<T>, src: ListId<T>) -> bool {
+            self.sentinel(dst).is_some() && self.move_all(dst, dst.0, src).is_some()
+        }
+        pub fn cursor_front_mut(&mut self, id: ListId<T>) -> Option<PoolCursorMut<'_, T>> {
+            let current = self.sentinel(id)?.next;
+            Some(PoolCursorMut {
+                pool: self,
+                id,
+                current,
+                index: 0,
+            })
+        }
+        pub fn cursor_back_mut(&mut self, id: ListId<T>) -> Option<PoolCursorMut<'_, T>> {
+            let current = self.sentinel(id)?.prev;
+            // 空なら current は sentinel で、その番号は len（= 0）
+            let index = self.lens[&id].saturating_sub(1);
+            Some(PoolCursorMut {
+                pool: self,
+                id,
+                current,
+                index,
+            })
+        }
+        // p の位置を先頭から探すので O(n) かかる。p が id のノードでなければ None
+        pub fn find_cursor_mut(
+            &mut self,
+            id: ListId<T>,
+            p: Ptr<Node<T>>,
+        ) -> Option<PoolCursorMut<'_, T>> {
+            let mut cursor = self.cursor_front_mut(id)?;
+            while cursor.current()? != p {
+                cursor.move_next();
+            }
+            Some(cursor)
+        }
+        // pos は id のノードか sentinel でなければならない
+        fn link_new(&mut self, id: ListId<T>, pos: Ptr<Node<T>>, value: T) -> Ptr<Node<T>> {
+            let node = self.nodes.alloc(Node {
+                value: Some(value),
+                next: Ptr::DANGLING,
+                prev: Ptr::DANGLING,
+            });
+            unsafe { link_range_before(pos, node, node) };
+            *self.lens.get_mut(&id).unwrap() += 1;
+            node
+        }
+        // src の全てのノードを dst の pos の直前に移し、移した数を返す。pos は dst のノードか sentinel でなければならない
+        fn move_all(&mut self, dst: ListId<T>, pos: Ptr<Node<T>>, src: ListId<T>) -> Option<usize> {
+            let count = self.len(src).filter(|_| src != dst)?;
+            if count > 0 {
+                let sentinel = self.sentinel(src)?;
+                let (first, last) = (sentinel.next, sentinel.prev);
+                unsafe {
+                    unlink_range(first, last);
+                    link_range_before(pos, first, last);
+                }
+                self.lens.insert(src, 0);
+                *self.lens.get_mut(&dst).unwrap() += count;
+            }
+            Some(count)
+        }
+        // first から last までの count 個のノードを id から新しいリストに移す
+        fn split_range(
+            &mut self,
+            id: ListId<T>,
+            first: Ptr<Node<T>>,
+            last: Ptr<Node<T>>,
+            count: usize,
+        ) -> ListId<T> {
+            let other = self.new_list();
+            if count > 0 {
                 unsafe {
-                    next.as_mut().prev = prev;
-                    prev.as_mut().next = next;
-                    Some(next.as_ref())
+                    unlink_range(first, last);
+                    link_range_before(other.0, first, last);
+                }
+                *self.lens.get_mut(&id).unwrap() -= count;
+                self.lens.insert(other, count);
+            }
+            other
+        }
+    }
+
+    // ListPool の一つのリストの上を動く。pool を借りている間は他から形を変えられないので、
+    // current は必ず id のリストのノードか sentinel で、index はその番号（sentinel なら len）になっている
+    #[cfg(test)]
+    pub struct PoolCursorMut<'a, T> {
+        pool: &'a mut ListPool<T>,
+        id: ListId<T>,
+        current: Ptr<Node<T>>,
+        index: usize,
+    }
+    #[cfg(test)]
+    impl<'a, T> PoolCursorMut<'a, T> {
+        fn len(&self) -> usize {
+            self.pool.lens[&self.id]
+        }
+        fn at_sentinel(&self) -> bool {
+            self.current == self.id.0
+        }
+        // sentinel にいるときは None
+        pub fn current(&self) -> Option<Ptr<Node<T>>> {
+            if self.at_sentinel() {
+                None
+            } else {
+                Some(self.current)
+            }
+        }
+        pub fn index(&self) -> Option<usize> {
+            self.current().map(|_| self.index)
+        }
+        pub fn value(&mut self) -> Option<&mut T> {
+            unsafe { self.current.as_mut() }.value.as_mut()
+        }
+        pub fn move_next(&mut self) {
+            self.current = unsafe { self.current.as_ref() }.next;
+            self.index = (self.index + 1) % (self.len() + 1);
+        }
+        pub fn move_prev(&mut self) {
+            let len = self.len();
+            self.current = unsafe { self.current.as_ref() }.prev;
+            self.index = (self.index + len) % (len + 1);
+        }
+        pub fn insert_before(&mut self, value: T) -> Ptr<Node<T>> {
+            self.index += 1;
+            self.pool.link_new(self.id, self.current, value)
+        }
+        pub fn insert_after(&mut self, value: T) -> Ptr<Node<T>> {
+            let next = unsafe { self.current.as_ref() }.next;
+            if self.at_sentinel() {
+                self.index += 1;
+            }
+            self.pool.link_new(self.id, next, value)
+        }
+        // 削除した後は次のノードに移動する。次のノードの番号は削除したノードと同じになる
+        pub fn remove_current(&mut self) -> Option<T> {
+            let node = self.current()?;
+            self.current = unsafe { node.as_ref() }.next;
+            unsafe { unlink_range(node, node) };
+            *self.pool.lens.get_mut(&self.id).unwrap() -= 1;
+            self.pool.nodes.free(node)?.value
+        }
+        // current の後ろから末尾までを新しいリストに移す。sentinel にいるときは全てのノードが移る
+        pub fn split_after(&mut self) -> ListId<T> {
+            let sentinel = unsafe { self.id.0.as_ref() };
+            let first = unsafe { self.current.as_ref() }.next;
+            let count = if self.at_sentinel() {
+                self.index = 0;
+                self.len()
+            } else {
+                self.len() - self.index - 1
+            };
+            self.pool.split_range(self.id, first, sentinel.prev, count)
+        }
+        // 先頭から current の前までを新しいリストに移す。sentinel にいるときは全てのノードが移る
+        pub fn split_before(&mut self) -> ListId<T> {
+            let sentinel = unsafe { self.id.0.as_ref() };
+            let last = unsafe { self.current.as_ref() }.prev;
+            let count = std::mem::replace(&mut self.index, 0);
+            self.pool.split_range(self.id, sentinel.next, last, count)
+        }
+        // other の全てのノードを current の後ろに移す。other は空のまま残る。
+        // other が使えないか、このリスト自身なら何もせずに false を返す
+        pub fn splice_after(&mut self, other: ListId<T>) -> bool {
+            let next = unsafe { self.current.as_ref() }.next;
+            match self.pool.move_all(self.id, next, other) {
+                Some(count) => {
+                    if self.at_sentinel() {
+                        self.index += count;
+                    }
+                    true
+                }
+                None => false,
+            }
+        }
+        pub fn splice_before(&mut self, other: ListId<T>) -> bool {
+            match self.pool.move_all(self.id, self.current, other) {
+                Some(count) => {
+                    self.index += count;
+                    true
                 }
+                None => false,
+            }
+        }
+    }
+
+    pub struct Iter<'a, T> {
+        head: Ptr<Node<T>>,
+        tail: Ptr<Node<T>>,
+        len: usize,
+        _marker: PhantomData<&'a Node<T>>,
+    }
+    impl<'a, T> Iterator for Iter<'a, T> {
+        type Item = &'a T;
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = unsafe { self.head.as_ref() }.get();
+            self.head = node.next;
+            node.value.as_ref()
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
+    }
+    impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = unsafe { self.tail.as_ref() }.get();
+            self.tail = node.prev;
+            node.value.as_ref()
+        }
+    }
+    impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+    // 同じノードを二度返さないように len で終わりを判定する
+    pub struct IterMut<'a, T> {
+        head: Ptr<Node<T>>,
+        tail: Ptr<Node<T>>,
+        len: usize,
+        _marker: PhantomData<&'a mut Node<T>>,
+    }
+    impl<'a, T> Iterator for IterMut<'a, T> {
+        type Item = &'a mut T;
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            unsafe {
+                let node = self.head.as_ptr();
+                self.head = (*node).next;
+                (*node).value.as_mut()
+            }
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
+    }
+    impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            unsafe {
+                let node = self.tail.as_ptr();
+                self.tail = (*node).prev;
+                (*node).value.as_mut()
+            }
+        }
+    }
+    impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+    pub struct IntoIter<T>(List<T>);
+    impl<T> Iterator for IntoIter<T> {
+        type Item = T;
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0.pop_front()
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.0.len, Some(self.0.len))
+        }
+    }
+    impl<T> DoubleEndedIterator for IntoIter<T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            self.0.pop_back()
+        }
+    }
+    impl<T> ExactSizeIterator for IntoIter<T> {}
+
+    impl<T> IntoIterator for List<T> {
+        type Item = T;
+        type IntoIter = IntoIter<T>;
+        fn into_iter(self) -> Self::IntoIter {
+            IntoIter(self)
+        }
+    }
+    impl<'a, T> IntoIterator for &'a List<T> {
+        type Item = &'a T;
+        type IntoIter = Iter<'a, T>;
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+    impl<'a, T> IntoIterator for &'a mut List<T> {
+        type Item = &'a mut T;
+        type IntoIter = IterMut<'a, T>;
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter_mut()
+        }
+    }
+    impl<T> FromIterator<T> for List<T> {
+        fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+            let mut list = Self::new();
+            list.extend(iter);
+            list
+        }
+    }
+    impl<T> Extend<T> for List<T> {
+        fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+            for value in iter {
+                self.push_back(value);
+            }
+        }
+    }
+
+    // Handle は sentinel 以外のノードの Ptr。同名の inherent method と区別するために List:: を付けて呼ぶ
+    fn handle<T>(node: Ref<Node<T>>) -> Option<Ptr<Node<T>>> {
+        if node.is_sentinel() {
+            None
+        } else {
+            Some(node.into())
+        }
+    }
+    impl<T> LinkedList<T> for List<T> {
+        type Handle = Ptr<Node<T>>;
+        type Iter<'a>
+            = Iter<'a, T>
+        where
+            T: 'a;
+        const NAME: &'static str = "arena";
+        fn new() -> Self {
+            List::new()
+        }
+        fn len(&self) -> usize {
+            self.len
+        }
+        fn iter(&self) -> Iter<'_, T> {
+            List::iter(self)
+        }
+        fn head(&self) -> Option<Ptr<Node<T>>> {
+            handle(List::head(self))
+        }
+        fn tail(&self) -> Option<Ptr<Node<T>>> {
+            handle(List::tail(self))
+        }
+        fn next(&self, h: Ptr<Node<T>>) -> Option<Ptr<Node<T>>> {
+            handle(List::get(self, h)?.get().next())
+        }
+        fn prev(&self, h: Ptr<Node<T>>) -> Option<Ptr<Node<T>>> {
+            handle(List::get(self, h)?.get().prev())
+        }
+        fn nth(&self, i: usize) -> Option<Ptr<Node<T>>> {
+            List::nth(self, i).map(Into::into)
+        }
+        fn nth_back(&self, i: usize) -> Option<Ptr<Node<T>>> {
+            List::nth_back(self, i).map(Into::into)
+        }
+        fn position(&self, h: Ptr<Node<T>>) -> Option<usize> {
+            List::position(self, h)
+        }
+        fn get(&self, h: Ptr<Node<T>>) -> Option<&T> {
+            List::get(self, h)?.get().value.as_ref()
+        }
+        fn get_mut(&mut self, h: Ptr<Node<T>>) -> Option<&mut T> {
+            self.try_get_mut(h).ok()
+        }
+        fn insert(&mut self, h: Ptr<Node<T>>, value: T) -> Option<Ptr<Node<T>>> {
+            if List::insert(self, h, value) {
+                handle(List::get(self, h)?.get().prev())
             } else {
                 None
             }
         }
+        fn push_back(&mut self, value: T) -> Ptr<Node<T>> {
+            List::push_back(self, value);
+            List::tail(self).into()
+        }
+        fn push_front(&mut self, value: T) -> Ptr<Node<T>> {
+            List::push_front(self, value);
+            List::head(self).into()
+        }
+        fn remove(&mut self, h: Ptr<Node<T>>) -> Option<T> {
+            self.take(h)
+        }
+        fn pop_front(&mut self) -> Option<T> {
+            List::pop_front(self)
+        }
+        fn pop_back(&mut self) -> Option<T> {
+            List::pop_back(self)
+        }
+        fn validate(&self) -> Report {
+            List::validate(self)
+        }
+        fn memory(&self) -> Usage {
+            self.nodes.usage()
+        }
     }
 }
 
@@ -358,6 +1336,7 @@ pub use list::*;
 
 #[test]
 fn test_list() {
+    use pool::Ptr;
     let mut list: List<usize> = List::new();
     assert!(list.head().is_sentinel());
     assert!(list.tail().is_sentinel());
@@ -385,8 +1364,19 @@ fn test_list() {
     assert_eq!(*list.head().next().next().value(), 1);
     assert_eq!(*list.head().next().next().next().value(), 2);
 
-    assert!(list.remove(list.head().into()).is_some());
+    let head: Ptr<_> = list.head().into();
+    assert!(list.remove(head).is_some());
     assert_eq!(*list.head().value(), 4);
+    assert!(list.get(head).is_none());
+    assert!(list.remove(head).is_none());
+
+    // 別のリストの Ptr や DANGLING を渡しても panic しない
+    let mut list2: List<usize> = List::new();
+    list2.push_back(5);
+    assert!(list.get(list2.head().into()).is_none());
+    assert!(!list.insert(list2.head().into(), 6));
+    assert!(list.remove(list2.head().into()).is_none());
+    assert!(list.get(Ptr::DANGLING).is_none());
 
     /*
     let mut node = list.get_mut(list.head().next() as *const _).unwrap();
@@ -416,3 +1406,311 @@ fn test_list() {
     }
     */
 }
+
+#[test]
+fn test_list_try_get() {
+    use pool::{Error, Ptr};
+    let mut list: List<usize> = (1..=2).collect();
+    let other: List<usize> = (3..=4).collect();
+    let head: Ptr<_> = list.head().into();
+    assert_eq!(*list.try_get(head).unwrap().value(), 1);
+    *list.try_get_mut(head).unwrap() = 5;
+    assert_eq!(*list.head().value(), 5);
+
+    assert_eq!(
+        list.try_get(other.head().into()).err(),
+        Some(Error::ForeignPool)
+    );
+    assert_eq!(list.try_get(Ptr::DANGLING).err(), Some(Error::Dangling));
+    assert_eq!(list.try_get(list.sentinel()).err(), Some(Error::Dangling));
+    list.take(head);
+    assert_eq!(list.try_get(head).err(), Some(Error::Removed));
+    assert_eq!(list.try_get_mut(head), Err(Error::Removed));
+}
+
+#[test]
+fn test_list_pool() {
+    use pool::Ptr;
+    let mut pool: ListPool<usize> = ListPool::new();
+    let a = pool.new_list();
+    let b = pool.new_list();
+    let a1 = pool.push_back(a, 1).unwrap();
+    let a2 = pool.push_back(a, 2).unwrap();
+    let b3 = pool.push_back(b, 3).unwrap();
+    let b4 = pool.push_back(b, 4).unwrap();
+    pool.push_front(b, 0).unwrap();
+    assert_eq!(pool.iter(a).copied().collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(pool.iter(b).copied().collect::<Vec<_>>(), vec![0, 3, 4]);
+
+    // append してもノードの Ptr はそのまま使える
+    assert!(pool.append(a, b));
+    assert!(pool.is_empty(b));
+    assert_eq!(
+        pool.iter(a).copied().collect::<Vec<_>>(),
+        vec![1, 2, 0, 3, 4]
+    );
+    assert_eq!((pool.len(a), pool.len(b)), (Some(5), Some(0)));
+    assert_eq!(*pool.get(b3).unwrap().value(), 3);
+    assert_eq!(Ptr::from(pool.tail(a).unwrap()), b4);
+    assert!(!pool.append(a, a));
+
+    // 3 から後ろを新しいリストに移す
+    let c = {
+        let mut cursor = pool.find_cursor_mut(a, b3).unwrap();
+        assert_eq!(cursor.index(), Some(3));
+        cursor.move_prev();
+        let c = cursor.split_after();
+        assert_eq!(cursor.index(), Some(2));
+        c
+    };
+    assert_eq!(pool.iter(a).copied().collect::<Vec<_>>(), vec![1, 2, 0]);
+    assert_eq!(pool.iter(c).copied().collect::<Vec<_>>(), vec![3, 4]);
+    assert_eq!(Ptr::from(pool.head(c).unwrap()), b3);
+
+    // a の 1..=2 を切り出してから c の 4 の前に移す
+    let range = {
+        let mut cursor = pool.find_cursor_mut(a, a2).unwrap();
+        cursor.move_next();
+        let range = cursor.split_before();
+        assert_eq!(cursor.index(), Some(0));
+        range
+    };
+    {
+        let mut cursor = pool.find_cursor_mut(c, b4).unwrap();
+        assert!(cursor.splice_before(range));
+        assert_eq!(cursor.index(), Some(3));
+    }
+    assert_eq!(pool.iter(a).copied().collect::<Vec<_>>(), vec![0]);
+    assert_eq!(pool.iter(c).copied().collect::<Vec<_>>(), vec![3, 1, 2, 4]);
+    assert!(pool.is_empty(range));
+    assert!(pool.remove_list(range));
+
+    // 4 の前までを空の b の sentinel の後ろ、つまり先頭に移す
+    let front = pool.cursor_back_mut(c).unwrap().split_before();
+    {
+        let mut cursor = pool.cursor_front_mut(b).unwrap();
+        assert_eq!(cursor.current(), None);
+        assert!(cursor.splice_after(front));
+        cursor.move_prev();
+        assert_eq!(cursor.index(), Some(2));
+        assert_eq!(cursor.current(), Some(a2));
+    }
+    assert_eq!(pool.iter(b).copied().collect::<Vec<_>>(), vec![3, 1, 2]);
+    assert_eq!(pool.iter(c).copied().collect::<Vec<_>>(), vec![4]);
+    for &id in &[a, b, c, front] {
+        pool.validate(id).assert_ok();
+    }
+
+    *pool.get_mut(a1).unwrap() = 10;
+    {
+        let mut cursor = pool.find_cursor_mut(b, a1).unwrap();
+        assert_eq!(cursor.value(), Some(&mut 10));
+        assert_eq!(cursor.remove_current(), Some(10));
+        assert_eq!((cursor.current(), cursor.index()), (Some(a2), Some(1)));
+    }
+    assert!(pool.get(a1).is_none());
+    assert!(pool.find_cursor_mut(b, a1).is_none());
+    assert_eq!(pool.len(b), Some(2));
+
+    assert!(pool.remove_list(c));
+    assert!(pool.get(b4).is_none());
+    assert!(pool.push_back(c, 5).is_none());
+    assert!(pool.len(c).is_none());
+    assert!(!pool.remove_list(c));
+    assert!(pool.cursor_front_mut(c).is_none());
+
+    // 別の ListPool の ListId やノードは使えない
+    let mut other: ListPool<usize> = ListPool::new();
+    let d = other.new_list();
+    let d1 = other.push_back(d, 1).unwrap();
+    assert!(pool.push_back(d, 1).is_none());
+    assert!(pool.get(d1).is_none());
+    assert!(!pool.append(a, d));
+    assert!(pool.find_cursor_mut(a, d1).is_none());
+    assert!(!pool.cursor_front_mut(a).unwrap().splice_after(d));
+    assert_eq!(other.iter(d).copied().collect::<Vec<_>>(), vec![1]);
+}
+
+// 合わない引数を渡してもリストを壊さずに失敗し、端での操作も長さと番号が合う
+#[test]
+fn test_list_pool_misuse() {
+    let mut pool: ListPool<usize> = ListPool::new();
+    let a = pool.new_list();
+    let b = pool.new_list();
+    let a_nodes: Vec<_> = (0..4).map(|i| pool.push_back(a, i).unwrap()).collect();
+    let b_nodes: Vec<_> = (4..6).map(|i| pool.push_back(b, i).unwrap()).collect();
+    let sentinel_a = pool.sentinel_of(a).unwrap();
+
+    // 別のリストのノードや sentinel からはカーソルを作れない
+    assert!(pool.find_cursor_mut(a, b_nodes[0]).is_none());
+    assert!(pool.find_cursor_mut(a, sentinel_a).is_none());
+    // 自分自身は splice できない
+    assert!(!pool.cursor_front_mut(a).unwrap().splice_before(a));
+    assert!(!pool.find_cursor_mut(a, a_nodes[2]).unwrap().splice_after(a));
+
+    // 末尾の後ろや先頭の前で分けると空のリストができる
+    let empty1 = pool.cursor_back_mut(a).unwrap().split_after();
+    let empty2 = pool.cursor_front_mut(a).unwrap().split_before();
+    assert_eq!((pool.len(empty1), pool.len(empty2)), (Some(0), Some(0)));
+    // sentinel からは全てのノードが移り、カーソルは空になったリストの sentinel に残る
+    let all = {
+        let mut cursor = pool.cursor_back_mut(b).unwrap();
+        cursor.move_next();
+        assert_eq!(cursor.index(), None);
+        let all = cursor.split_after();
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        cursor.insert_after(6);
+        cursor.insert_before(7);
+        cursor.move_next();
+        assert_eq!(cursor.index(), Some(0));
+        all
+    };
+    assert_eq!(pool.iter(all).copied().collect::<Vec<_>>(), vec![4, 5]);
+    assert_eq!(pool.iter(b).copied().collect::<Vec<_>>(), vec![6, 7]);
+    for &id in &[a, b, empty1, empty2, all] {
+        pool.validate(id).assert_ok();
+    }
+    assert_eq!(pool.iter(a).copied().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    for &id in &[a, b, empty1, empty2, all] {
+        assert!(pool.remove_list(id));
+    }
+    assert!(pool.cursor_front_mut(all).is_none());
+}
+
+// 別の Pool の Ptr を指すようになり、try_get の理由がそのまま出る
+#[test]
+fn test_validate() {
+    use crate::validate::Violation;
+    use pool::Error;
+    crate::linked_list::exercise_validate::<List<usize>>(
+        |_, mut p1, _, mut p2| unsafe { std::mem::swap(p1.as_mut(), p2.as_mut()) },
+        &Error::ForeignPool.to_string(),
+    );
+
+    // ListPool では削除したリストの ListId は解放済みとして報告される
+    let mut pool: ListPool<usize> = ListPool::new();
+    let a = pool.new_list();
+    pool.push_back(a, 1);
+    pool.validate(a).assert_ok();
+    assert!(pool.remove_list(a));
+    assert_eq!(
+        pool.validate(a).violations,
+        vec![Violation::InvalidPtr {
+            at: 0,
+            link: "sentinel",
+            reason: Error::Removed.to_string()
+        }]
+    );
+}
+
+// drop された回数を数える値
+#[cfg(test)]
+struct DropCounter {
+    id: usize,
+    drops: std::rc::Rc<std::cell::RefCell<Vec<usize>>>,
+}
+#[cfg(test)]
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.drops.borrow_mut().push(self.id);
+    }
+}
+#[cfg(test)]
+fn drop_counters() -> (
+    impl FnMut(usize) -> DropCounter,
+    std::rc::Rc<std::cell::RefCell<Vec<usize>>>,
+) {
+    let drops = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let drops2 = drops.clone();
+    let make = move |id| DropCounter {
+        id,
+        drops: drops2.clone(),
+    };
+    (make, drops)
+}
+
+#[test]
+fn test_drop() {
+    let (mut make, drops) = drop_counters();
+    let dropped = || {
+        let mut v = drops.borrow().clone();
+        v.sort();
+        v
+    };
+    {
+        let mut list = List::new();
+        list.push_back(make(1));
+        list.push_back(make(2));
+        list.push_front(make(0));
+        let p2 = list.tail().into();
+        assert!(list.insert(p2, make(3)));
+        assert!(dropped().is_empty());
+
+        // remove した値はその場で drop される
+        let p3 = list.nth(2).unwrap().into();
+        list.remove(p3);
+        assert_eq!(dropped(), vec![3]);
+        // 解放済みのスロットを指す Ptr を remove しても何も drop されない
+        assert!(list.remove(p3).is_none());
+        assert_eq!(dropped(), vec![3]);
+
+        // 再利用されたスロットの値は新しい値として扱われる
+        list.push_back(make(4));
+        assert_eq!(dropped(), vec![3]);
+
+        // take や pop で取り出した値は呼び出し側が drop する
+        let taken = list.take(p2).unwrap();
+        assert_eq!(taken.id, 2);
+        assert_eq!(dropped(), vec![3]);
+        drop(taken);
+        assert_eq!(dropped(), vec![2, 3]);
+        let front = list.pop_front().unwrap();
+        assert_eq!(front.id, 0);
+        assert_eq!(dropped(), vec![2, 3]);
+        drop(front);
+        assert_eq!(dropped(), vec![0, 2, 3]);
+
+        let mut cursor = list.cursor_front_mut();
+        assert_eq!(cursor.remove_current().unwrap().id, 1);
+        assert_eq!(dropped(), vec![0, 1, 2, 3]);
+        list.push_back(make(5));
+        list.push_back(make(6));
+    }
+    // リストの drop で残りの 4, 5, 6 が一度ずつ drop される
+    assert_eq!(dropped(), vec![0, 1, 2, 3, 4, 5, 6]);
+
+    drops.borrow_mut().clear();
+    {
+        let list: List<_> = (0..4).map(&mut make).collect();
+        let mut iter = list.into_iter();
+        iter.next();
+        iter.next_back();
+        assert_eq!(dropped(), vec![0, 3]);
+    }
+    assert_eq!(dropped(), vec![0, 1, 2, 3]);
+
+    drops.borrow_mut().clear();
+    {
+        let mut pool = ListPool::new();
+        let a = pool.new_list();
+        let b = pool.new_list();
+        let mut ptrs = Vec::new();
+        for i in 0..3 {
+            pool.push_back(a, make(i));
+            ptrs.push(pool.push_back(b, make(10 + i)).unwrap());
+        }
+        pool.append(a, b);
+        assert!(dropped().is_empty());
+        pool.cursor_front_mut(a).unwrap().remove_current();
+        assert_eq!(dropped(), vec![0]);
+        let c = {
+            let mut cursor = pool.find_cursor_mut(a, ptrs[1]).unwrap();
+            cursor.move_prev();
+            cursor.split_after()
+        };
+        assert!(pool.remove_list(c));
+        assert_eq!(dropped(), vec![0, 11, 12]);
+    }
+    assert_eq!(dropped(), vec![0, 1, 2, 10, 11, 12]);
+}
@@ -0,0 +1,641 @@
+#![cfg(test)]
+pub mod graph {
+    use crate::test07_nodes_in_arena::pool::*;
+    use crate::validate::{Report, Violation};
+    use std::collections::HashSet;
+    use std::marker::PhantomData;
+
+    // first / next / prev の添字
+    // OUT: 始点ノードから出ていく辺のリスト、IN: 終点ノードに入ってくる辺のリスト
+    const OUT: usize = 0;
+    const IN: usize = 1;
+
+    pub type NodePtr<N, E> = Ptr<Node<N, E>>;
+    pub type EdgePtr<N, E> = Ptr<Edge<N, E>>;
+
+    pub trait EdgeType {
+        fn is_directed() -> bool;
+    }
+    pub enum Directed {}
+    pub enum Undirected {}
+    impl EdgeType for Directed {
+        fn is_directed() -> bool {
+            true
+        }
+    }
+    impl EdgeType for Undirected {
+        fn is_directed() -> bool {
+            false
+        }
+    }
+
+    pub type DiGraph<N, E> = Graph<N, E, Directed>;
+    pub type UnGraph<N, E> = Graph<N, E, Undirected>;
+
+    pub struct Node<N, E> {
+        value: N,
+        first: [Option<EdgePtr<N, E>>; 2],
+        next: Option<NodePtr<N, E>>,
+        prev: Option<NodePtr<N, E>>,
+    }
+    impl<N, E> Node<N, E> {
+        pub fn value(&self) -> &N {
+            &self.value
+        }
+    }
+
+    // 辺は一つだけ確保し、始点の OUT リストと終点の IN リストの両方に繋ぐ
+    // 無向グラフでも同じで、from / to は辺を追加したときの向きを覚えているだけ
+    pub struct Edge<N, E> {
+        value: E,
+        ends: [NodePtr<N, E>; 2],
+        next: [Option<EdgePtr<N, E>>; 2],
+        prev: [Option<EdgePtr<N, E>>; 2],
+    }
+    impl<N, E> Edge<N, E> {
+        pub fn value(&self) -> &E {
+            &self.value
+        }
+        pub fn from(&self) -> Ref<'_, Node<N, E>> {
+            unsafe { self.ends[OUT].as_ref() }
+        }
+        pub fn to(&self) -> Ref<'_, Node<N, E>> {
+            unsafe { self.ends[IN].as_ref() }
+        }
+        pub fn is_loop(&self) -> bool {
+            self.ends[OUT] == self.ends[IN]
+        }
+        // node の反対側の端点
+        pub fn opposite(&self, node: NodePtr<N, E>) -> Ref<'_, Node<N, E>> {
+            if self.ends[OUT] == node {
+                self.to()
+            } else {
+                self.from()
+            }
+        }
+    }
+
+    pub struct Edges<'a, N, E> {
+        next: Option<EdgePtr<N, E>>,
+        side: usize,
+        in_list: Option<Option<EdgePtr<N, E>>>, // OUT リストの後に IN リストも辿る場合は Some
+        _graph: PhantomData<&'a Edge<N, E>>,
+    }
+    impl<'a, N, E> Iterator for Edges<'a, N, E> {
+        type Item = Ref<'a, Edge<N, E>>;
+        fn next(&mut self) -> Option<Self::Item> {
+            loop {
+                if let Some(p) = self.next {
+                    let edge = unsafe { p.as_ref() };
+                    self.next = edge.next[self.side];
+                    // 両方のリストを辿るときは自己ループを二度数えない
+                    if self.side == IN && edge.is_loop() && self.in_list.is_some() {
+                        continue;
+                    }
+                    return Some(edge);
+                } else if self.side == OUT {
+                    self.next = self.in_list?;
+                    self.side = IN;
+                } else {
+                    return None;
+                }
+            }
+        }
+    }
+
+    pub struct Neighbors<'a, N, E> {
+        edges: Edges<'a, N, E>,
+        node: NodePtr<N, E>,
+    }
+    impl<'a, N, E> Iterator for Neighbors<'a, N, E> {
+        type Item = Ref<'a, Node<N, E>>;
+        fn next(&mut self) -> Option<Self::Item> {
+            self.edges.next().map(|edge| edge.get().opposite(self.node))
+        }
+    }
+
+    pub struct Nodes<'a, N, E> {
+        next: Option<NodePtr<N, E>>,
+        _graph: PhantomData<&'a Node<N, E>>,
+    }
+    impl<'a, N, E> Iterator for Nodes<'a, N, E> {
+        type Item = Ref<'a, Node<N, E>>;
+        fn next(&mut self) -> Option<Self::Item> {
+            let node = unsafe { self.next?.as_ref() };
+            self.next = node.next;
+            Some(node)
+        }
+    }
+
+    pub struct Graph<N, E, Ty = Directed> {
+        nodes: Pool<Node<N, E>>,
+        edges: Pool<Edge<N, E>>,
+        first_node: Option<NodePtr<N, E>>,
+        node_count: usize,
+        edge_count: usize,
+        allows_multi_edges: bool,
+        _ty: PhantomData<Ty>,
+    }
+    impl<N, E, Ty: EdgeType> Graph<N, E, Ty> {
+        pub fn new() -> Self {
+            Self::with_multi_edges(true)
+        }
+        // allows_multi_edges が false なら同じ端点を結ぶ辺を二本以上張れない
+        pub fn with_multi_edges(allows_multi_edges: bool) -> Self {
+            Self {
+                nodes: Pool::new(),
+                edges: Pool::new(),
+                first_node: None,
+                node_count: 0,
+                edge_count: 0,
+                allows_multi_edges,
+                _ty: PhantomData,
+            }
+        }
+        pub fn is_directed(&self) -> bool {
+            Ty::is_directed()
+        }
+        pub fn allows_multi_edges(&self) -> bool {
+            self.allows_multi_edges
+        }
+        pub fn node_count(&self) -> usize {
+            self.node_count
+        }
+        pub fn edge_count(&self) -> usize {
+            self.edge_count
+        }
+        pub fn node(&self, p: NodePtr<N, E>) -> Option<Ref<'_, Node<N, E>>> {
+            self.nodes.get(p)
+        }
+        pub fn node_mut(&mut self, p: NodePtr<N, E>) -> Option<&mut N> {
+            self.nodes.get_mut(p).map(|node| &mut node.value)
+        }
+        pub fn edge(&self, p: EdgePtr<N, E>) -> Option<Ref<'_, Edge<N, E>>> {
+            self.edges.get(p)
+        }
+        pub fn edge_mut(&mut self, p: EdgePtr<N, E>) -> Option<&mut E> {
+            self.edges.get_mut(p).map(|edge| &mut edge.value)
+        }
+        pub fn nodes(&self) -> Nodes<'_, N, E> {
+            Nodes {
+                next: self.first_node,
+                _graph: PhantomData,
+            }
+        }
+        // 無向グラフでは out_edges / in_edges / edges はどれも接続する全ての辺を返す
+        pub fn out_edges(&self, p: NodePtr<N, E>) -> Edges<'_, N, E> {
+            if Ty::is_directed() {
+                self.edges_of(p, OUT, false)
+            } else {
+                self.edges(p)
+            }
+        }
+        pub fn in_edges(&self, p: NodePtr<N, E>) -> Edges<'_, N, E> {
+            if Ty::is_directed() {
+                self.edges_of(p, IN, false)
+            } else {
+                self.edges(p)
+            }
+        }
+        pub fn edges(&self, p: NodePtr<N, E>) -> Edges<'_, N, E> {
+            self.edges_of(p, OUT, true)
+        }
+        fn edges_of(&self, p: NodePtr<N, E>, side: usize, both: bool) -> Edges<'_, N, E> {
+            let node = self.nodes.get(p);
+            Edges {
+                next: node.as_ref().and_then(|node| node.first[side]),
+                side,
+                in_list: if both {
+                    Some(node.and_then(|node| node.first[IN]))
+                } else {
+                    None
+                },
+                _graph: PhantomData,
+            }
+        }
+        // 有向グラフでは出ていく辺の先、無向グラフでは隣接する全てのノード
+        pub fn neighbors(&self, p: NodePtr<N, E>) -> Neighbors<'_, N, E> {
+            Neighbors {
+                edges: self.out_edges(p),
+                node: p,
+            }
+        }
+        pub fn find_edge(&self, from: NodePtr<N, E>, to: NodePtr<N, E>) -> Option<EdgePtr<N, E>> {
+            self.out_edges(from)
+                .find(|edge| Ptr::from(edge.get().opposite(from)) == to)
+                .map(Into::into)
+        }
+        pub fn add_node(&mut self, value: N) -> NodePtr<N, E> {
+            let node = self.nodes.alloc(Node {
+                value,
+                first: [None; 2],
+                next: self.first_node,
+                prev: None,
+            });
+            if let Some(mut first) = self.first_node {
+                unsafe { first.as_mut().prev = Some(node) };
+            }
+            self.first_node = Some(node);
+            self.node_count += 1;
+            node
+        }
+        pub fn add_edge(
+            &mut self,
+            from: NodePtr<N, E>,
+            to: NodePtr<N, E>,
+            value: E,
+        ) -> Option<EdgePtr<N, E>> {
+            if self.nodes.get(from).is_none() || self.nodes.get(to).is_none() {
+                return None;
+            }
+            if !self.allows_multi_edges && self.find_edge(from, to).is_some() {
+                return None;
+            }
+            let edge = self.edges.alloc(Edge {
+                value,
+                ends: [from, to],
+                next: [None; 2],
+                prev: [None; 2],
+            });
+            unsafe {
+                Self::link(edge, OUT);
+                Self::link(edge, IN);
+            }
+            self.edge_count += 1;
+            Some(edge)
+        }
+        pub fn remove_edge(&mut self, p: EdgePtr<N, E>) -> Option<E> {
+            self.edges.get(p)?;
+            unsafe {
+                Self::unlink(p, OUT);
+                Self::unlink(p, IN);
+            }
+            self.edge_count -= 1;
+            self.edges.free(p).map(|edge| edge.value)
+        }
+        pub fn remove_node(&mut self, p: NodePtr<N, E>) -> Option<N> {
+            let node = self.nodes.get(p)?;
+            let (next, prev) = (node.next, node.prev);
+            for side in [OUT, IN].iter() {
+                while let Some(edge) = self.nodes.get(p).and_then(|node| node.first[*side]) {
+                    self.remove_edge(edge);
+                }
+            }
+            unsafe {
+                if let Some(mut next) = next {
+                    next.as_mut().prev = prev;
+                }
+                match prev {
+                    Some(mut prev) => prev.as_mut().next = next,
+                    None => self.first_node = next,
+                }
+            }
+            self.node_count -= 1;
+            self.nodes.free(p).map(|node| node.value)
+        }
+        // ノードのリスト、各ノードの OUT / IN の辺のリスト、node_count / edge_count を検査する。
+        // 辺の違反はそのリストを持っているノードの位置で報告する
+        pub fn validate(&self) -> Report {
+            let mut report = Report::default();
+            let mut nodes = HashSet::new();
+            let mut edges = [HashSet::new(), HashSet::new()];
+            let mut prev = None;
+            let mut next = self.first_node;
+            let mut at = 0;
+            let completed = loop {
+                let p = match next {
+                    Some(p) => p,
+                    None => break true,
+                };
+                let node = match self.nodes.try_get(p) {
+                    Ok(node) => node,
+                    Err(e) => {
+                        report.push(Violation::InvalidPtr {
+                            at,
+                            link: if at == 0 { "first_node" } else { "next" },
+                            reason: e.to_string(),
+                        });
+                        break false;
+                    }
+                };
+                if !nodes.insert(p) {
+                    report.push(Violation::Cycle { at });
+                    break false;
+                }
+                if node.prev != prev {
+                    report.push(Violation::BrokenLink { at, link: "prev" });
+                }
+                for &side in [OUT, IN].iter() {
+                    self.validate_edges(p, side, at, &mut edges[side], &mut report);
+                }
+                prev = Some(p);
+                next = node.next;
+                at += 1;
+            };
+            if completed {
+                if nodes.len() != self.node_count {
+                    report.push(Violation::CountMismatch {
+                        what: "node_count",
+                        recorded: self.node_count,
+                        actual: nodes.len(),
+                    });
+                }
+                // 辺は OUT リストと IN リストの両方にちょうど一度ずつ現れる
+                for (what, edges) in ["edge_count (OUT)", "edge_count (IN)"].iter().zip(&edges) {
+                    if edges.len() != self.edge_count {
+                        report.push(Violation::CountMismatch {
+                            what,
+                            recorded: self.edge_count,
+                            actual: edges.len(),
+                        });
+                    }
+                }
+            }
+            report
+        }
+        // seen は全てのノードで共有するので、別のノードのリストに紛れ込んだ辺も Cycle として見つかる
+        fn validate_edges(
+            &self,
+            p: NodePtr<N, E>,
+            side: usize,
+            at: usize,
+            seen: &mut HashSet<EdgePtr<N, E>>,
+            report: &mut Report,
+        ) {
+            const FIRST: [&str; 2] = ["first[OUT]", "first[IN]"];
+            const NEXT: [&str; 2] = ["next[OUT]", "next[IN]"];
+            const PREV: [&str; 2] = ["next[OUT].prev", "next[IN].prev"];
+            const ENDS: [&str; 2] = ["ends[OUT]", "ends[IN]"];
+            let mut prev = None;
+            let mut next = self.nodes.get(p).and_then(|node| node.first[side]);
+            while let Some(e) = next {
+                let edge = match self.edges.try_get(e) {
+                    Ok(edge) => edge,
+                    Err(error) => {
+                        report.push(Violation::InvalidPtr {
+                            at,
+                            link: if prev.is_none() {
+                                FIRST[side]
+                            } else {
+                                NEXT[side]
+                            },
+                            reason: error.to_string(),
+                        });
+                        return;
+                    }
+                };
+                if !seen.insert(e) {
+                    report.push(Violation::Cycle { at });
+                    return;
+                }
+                if edge.prev[side] != prev {
+                    report.push(Violation::BrokenLink {
+                        at,
+                        link: PREV[side],
+                    });
+                }
+                if edge.ends[side] != p {
+                    report.push(Violation::BrokenLink {
+                        at,
+                        link: ENDS[side],
+                    });
+                }
+                if let Err(error) = self.nodes.try_get(edge.ends[1 - side]) {
+                    report.push(Violation::InvalidPtr {
+                        at,
+                        link: ENDS[1 - side],
+                        reason: error.to_string(),
+                    });
+                }
+                prev = Some(e);
+                next = edge.next[side];
+            }
+        }
+        // edge を ends[side] のノードの first[side] リストの先頭に繋ぐ
+        unsafe fn link(mut edge: EdgePtr<N, E>, side: usize) {
+            let mut node = edge.as_ref().ends[side];
+            let first = node.as_ref().first[side];
+            if let Some(mut first) = first {
+                first.as_mut().prev[side] = Some(edge);
+            }
+            edge.as_mut().next[side] = first;
+            edge.as_mut().prev[side] = None;
+            node.as_mut().first[side] = Some(edge);
+        }
+        unsafe fn unlink(edge: EdgePtr<N, E>, side: usize) {
+            let edge = edge.as_ref();
+            let (next, prev) = (edge.next[side], edge.prev[side]);
+            if let Some(mut next) = next {
+                next.as_mut().prev[side] = prev;
+            }
+            match prev {
+                Some(mut prev) => prev.as_mut().next[side] = next,
+                None => {
+                    let mut node = edge.ends[side];
+                    node.as_mut().first[side] = next;
+                }
+            }
+        }
+    }
+}
+
+use graph::*;
+
+#[cfg(test)]
+fn sorted<T: Ord>(mut v: Vec<T>) -> Vec<T> {
+    v.sort();
+    v
+}
+
+#[test]
+fn test_graph() {
+    let mut graph: Graph<&str, usize> = Graph::new();
+    let a = graph.add_node("a");
+    let b = graph.add_node("b");
+    let c = graph.add_node("c");
+    assert_eq!(graph.node_count(), 3);
+
+    let ab = graph.add_edge(a, b, 1).unwrap();
+    let ac = graph.add_edge(a, c, 2).unwrap();
+    let bc = graph.add_edge(b, c, 3).unwrap();
+    let ca = graph.add_edge(c, a, 4).unwrap();
+    assert_eq!(graph.edge_count(), 4);
+
+    assert_eq!(*graph.edge(ab).unwrap().from().value(), "a");
+    assert_eq!(*graph.edge(ab).unwrap().to().value(), "b");
+    let out_a = graph.out_edges(a).map(|e| *e.value()).collect::<Vec<_>>();
+    assert_eq!(sorted(out_a), vec![1, 2]);
+    let in_c = graph.in_edges(c).map(|e| *e.value()).collect::<Vec<_>>();
+    assert_eq!(sorted(in_c), vec![2, 3]);
+    let in_a = graph
+        .in_edges(a)
+        .map(|e| *e.from().value())
+        .collect::<Vec<_>>();
+    assert_eq!(in_a, vec!["c"]);
+
+    *graph.edge_mut(bc).unwrap() = 30;
+    *graph.node_mut(b).unwrap() = "B";
+    assert_eq!(*graph.out_edges(b).next().unwrap().value(), 30);
+    assert_eq!(*graph.in_edges(c).last().unwrap().from().value(), "a");
+
+    assert_eq!(graph.remove_edge(ac), Some(2));
+    assert!(graph.edge(ac).is_none());
+    assert!(graph.remove_edge(ac).is_none());
+    assert_eq!(graph.out_edges(a).count(), 1);
+    assert_eq!(graph.edge_count(), 3);
+
+    // ノードを削除するとそのノードに接続する辺も全て削除される
+    assert_eq!(graph.remove_node(c), Some("c"));
+    assert!(graph.node(c).is_none());
+    assert!(graph.edge(bc).is_none());
+    assert!(graph.edge(ca).is_none());
+    assert!(graph.edge(ab).is_some());
+    assert_eq!(graph.node_count(), 2);
+    assert_eq!(graph.edge_count(), 1);
+    assert_eq!(graph.in_edges(a).count(), 0);
+    assert_eq!(graph.out_edges(b).count(), 0);
+    let nodes = graph.nodes().map(|n| *n.value()).collect::<Vec<_>>();
+    assert_eq!(sorted(nodes), vec!["B", "a"]);
+    graph.validate().assert_ok();
+
+    // 削除済みのノードや別のグラフのノードには辺を張れない
+    assert!(graph.add_edge(a, c, 5).is_none());
+    let mut other: Graph<&str, usize> = Graph::new();
+    let d = other.add_node("d");
+    assert!(graph.add_edge(a, d, 6).is_none());
+    assert!(graph.node(d).is_none());
+    assert_eq!(graph.out_edges(d).count(), 0);
+}
+
+#[test]
+fn test_self_loop() {
+    let mut graph: Graph<usize, ()> = Graph::new();
+    let a = graph.add_node(1);
+    let aa = graph.add_edge(a, a, ()).unwrap();
+    assert_eq!(graph.out_edges(a).count(), 1);
+    assert_eq!(graph.in_edges(a).count(), 1);
+    assert_eq!(graph.remove_node(a), Some(1));
+    assert!(graph.edge(aa).is_none());
+    assert_eq!(graph.edge_count(), 0);
+}
+
+#[test]
+fn test_undirected() {
+    let mut graph: UnGraph<&str, usize> = UnGraph::with_multi_edges(false);
+    assert!(!graph.is_directed());
+    let a = graph.add_node("a");
+    let b = graph.add_node("b");
+    let c = graph.add_node("c");
+    let ab = graph.add_edge(a, b, 1).unwrap();
+    let bc = graph.add_edge(b, c, 2).unwrap();
+    let cc = graph.add_edge(c, c, 3).unwrap();
+
+    // 辺は向きに関係なく両端から辿れる
+    assert_eq!(graph.find_edge(a, b), Some(ab));
+    assert_eq!(graph.find_edge(b, a), Some(ab));
+    assert_eq!(graph.find_edge(c, b), Some(bc));
+    assert_eq!(graph.find_edge(a, c), None);
+    let edges_b = graph.edges(b).map(|e| *e.value()).collect::<Vec<_>>();
+    assert_eq!(sorted(edges_b), vec![1, 2]);
+    assert_eq!(graph.out_edges(b).count(), 2);
+    assert_eq!(graph.in_edges(b).count(), 2);
+    let neighbors_b = graph.neighbors(b).map(|n| *n.value()).collect::<Vec<_>>();
+    assert_eq!(sorted(neighbors_b), vec!["a", "c"]);
+
+    // 自己ループは一度だけ数える
+    let edges_c = graph.edges(c).map(|e| *e.value()).collect::<Vec<_>>();
+    assert_eq!(sorted(edges_c), vec![2, 3]);
+    let neighbors_c = graph.neighbors(c).map(|n| *n.value()).collect::<Vec<_>>();
+    assert_eq!(sorted(neighbors_c), vec!["b", "c"]);
+
+    // 多重辺は禁止されている（逆向きも同じ辺とみなす）
+    assert!(graph.add_edge(a, b, 4).is_none());
+    assert!(graph.add_edge(b, a, 4).is_none());
+    assert!(graph.add_edge(c, c, 4).is_none());
+    assert_eq!(graph.edge_count(), 3);
+
+    assert_eq!(graph.remove_node(b), Some("b"));
+    assert!(graph.edge(ab).is_none());
+    assert!(graph.edge(bc).is_none());
+    assert!(graph.edge(cc).is_some());
+    assert_eq!(graph.edges(a).count(), 0);
+    assert_eq!(graph.edges(c).count(), 1);
+    graph.validate().assert_ok();
+}
+
+#[test]
+fn test_multi_edges() {
+    let mut graph: DiGraph<usize, usize> = DiGraph::new();
+    assert!(graph.is_directed());
+    assert!(graph.allows_multi_edges());
+    let a = graph.add_node(1);
+    let b = graph.add_node(2);
+    let e1 = graph.add_edge(a, b, 10).unwrap();
+    let e2 = graph.add_edge(a, b, 20).unwrap();
+    let e3 = graph.add_edge(b, a, 30).unwrap();
+    assert_ne!(e1, e2);
+    assert_eq!(graph.out_edges(a).count(), 2);
+    assert_eq!(graph.in_edges(a).count(), 1);
+    assert_eq!(graph.edges(a).count(), 3);
+    assert_eq!(
+        graph.neighbors(a).map(|n| *n.value()).collect::<Vec<_>>(),
+        vec![2, 2]
+    );
+    assert_eq!(graph.find_edge(b, a), Some(e3));
+
+    assert_eq!(graph.remove_edge(e1), Some(10));
+    assert_eq!(graph.find_edge(a, b), Some(e2));
+
+    let mut simple: DiGraph<usize, usize> = DiGraph::with_multi_edges(false);
+    let a = simple.add_node(1);
+    let b = simple.add_node(2);
+    assert!(simple.add_edge(a, b, 10).is_some());
+    assert!(simple.add_edge(a, b, 20).is_none());
+    // 有向グラフでは逆向きの辺は別の辺
+    assert!(simple.add_edge(b, a, 30).is_some());
+}
+
+#[test]
+fn test_validate() {
+    use crate::test07_nodes_in_arena::pool::Error;
+    use crate::validate::Violation;
+    let mut graph1: Graph<usize, ()> = Graph::new();
+    let a = graph1.add_node(1);
+    let b = graph1.add_node(2);
+    graph1.add_edge(a, b, ());
+    graph1.add_edge(b, b, ());
+    let mut graph2: Graph<usize, ()> = Graph::new();
+    let c = graph2.add_node(3);
+    let d = graph2.add_node(4);
+    graph2.add_edge(c, d, ());
+    graph1.validate().assert_ok();
+    graph2.validate().assert_ok();
+
+    // ノードを丸ごと入れ替えると別のグラフの辺を指すようになり、a から出る辺も辿れなくなる
+    let (mut a, mut c) = (a, c);
+    unsafe { std::mem::swap(a.as_mut(), c.as_mut()) };
+    let foreign = Error::ForeignPool.to_string();
+    assert_eq!(
+        graph1.validate().violations,
+        vec![
+            Violation::BrokenLink {
+                at: 1,
+                link: "prev"
+            },
+            Violation::InvalidPtr {
+                at: 1,
+                link: "first[OUT]",
+                reason: foreign
+            },
+            Violation::CountMismatch {
+                what: "edge_count (OUT)",
+                recorded: 2,
+                actual: 1
+            },
+        ]
+    );
+    assert!(!graph2.validate().is_ok());
+    unsafe { std::mem::swap(a.as_mut(), c.as_mut()) };
+    graph1.validate().assert_ok();
+    graph2.validate().assert_ok();
+}
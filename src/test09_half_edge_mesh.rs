@@ -0,0 +1,936 @@
+#![cfg(test)]
+pub mod mesh {
+    use crate::test07_nodes_in_arena::pool::*;
+    use crate::validate::{Report, Violation};
+    use std::collections::HashSet;
+
+    pub type VertexPtr<V, E, F> = Ptr<Vertex<V, E, F>>;
+    pub type HalfEdgePtr<V, E, F> = Ptr<HalfEdge<V, E, F>>;
+    pub type EdgePtr<V, E, F> = Ptr<Edge<V, E, F>>;
+    pub type FacePtr<V, E, F> = Ptr<Face<V, E, F>>;
+
+    pub struct Vertex<V, E, F> {
+        value: V,
+        halfedge: HalfEdgePtr<V, E, F>, // この頂点から出ていく半辺のどれか一つ
+    }
+    impl<V, E, F> Vertex<V, E, F> {
+        pub fn value(&self) -> &V {
+            &self.value
+        }
+        pub fn halfedge(&self) -> Ref<'_, HalfEdge<V, E, F>> {
+            unsafe { self.halfedge.as_ref() }
+        }
+    }
+
+    // 境界の半辺は face が None で、境界に沿ってループを作る
+    pub struct HalfEdge<V, E, F> {
+        origin: VertexPtr<V, E, F>,
+        twin: HalfEdgePtr<V, E, F>,
+        next: HalfEdgePtr<V, E, F>,
+        prev: HalfEdgePtr<V, E, F>,
+        face: Option<FacePtr<V, E, F>>,
+        edge: EdgePtr<V, E, F>,
+    }
+    impl<V, E, F> HalfEdge<V, E, F> {
+        pub fn origin(&self) -> Ref<'_, Vertex<V, E, F>> {
+            unsafe { self.origin.as_ref() }
+        }
+        pub fn twin(&self) -> Ref<'_, Self> {
+            unsafe { self.twin.as_ref() }
+        }
+        pub fn next(&self) -> Ref<'_, Self> {
+            unsafe { self.next.as_ref() }
+        }
+        pub fn prev(&self) -> Ref<'_, Self> {
+            unsafe { self.prev.as_ref() }
+        }
+        pub fn face(&self) -> Option<Ref<'_, Face<V, E, F>>> {
+            self.face.map(|face| unsafe { face.as_ref() })
+        }
+        pub fn edge(&self) -> Ref<'_, Edge<V, E, F>> {
+            unsafe { self.edge.as_ref() }
+        }
+        pub fn is_boundary(&self) -> bool {
+            self.face.is_none()
+        }
+    }
+
+    // 辺の値は twin の組で一つだけ持つ
+    pub struct Edge<V, E, F> {
+        value: E,
+        halfedge: HalfEdgePtr<V, E, F>,
+    }
+    impl<V, E, F> Edge<V, E, F> {
+        pub fn value(&self) -> &E {
+            &self.value
+        }
+        pub fn halfedge(&self) -> Ref<'_, HalfEdge<V, E, F>> {
+            unsafe { self.halfedge.as_ref() }
+        }
+    }
+
+    pub struct Face<V, E, F> {
+        value: F,
+        halfedge: HalfEdgePtr<V, E, F>,
+    }
+    impl<V, E, F> Face<V, E, F> {
+        pub fn value(&self) -> &F {
+            &self.value
+        }
+        pub fn halfedge(&self) -> Ref<'_, HalfEdge<V, E, F>> {
+            unsafe { self.halfedge.as_ref() }
+        }
+    }
+
+    // Pool は中身を列挙できないので、生きている Ptr を別に覚えておく
+    struct Store<T> {
+        pool: Pool<T>,
+        ptrs: HashSet<Ptr<T>>,
+    }
+    impl<T> Store<T> {
+        fn new() -> Self {
+            Self {
+                pool: Pool::new(),
+                ptrs: HashSet::new(),
+            }
+        }
+        fn alloc(&mut self, value: T) -> Ptr<T> {
+            let p = self.pool.alloc(value);
+            self.ptrs.insert(p);
+            p
+        }
+        fn free(&mut self, p: Ptr<T>) -> Option<T> {
+            self.ptrs.remove(&p);
+            self.pool.free(p)
+        }
+    }
+
+    // 呼び出し側で p が生きていることと、他に参照が無いことを保証すること
+    unsafe fn deref<'a, T>(p: Ptr<T>) -> &'a T {
+        &*p.as_ptr()
+    }
+    unsafe fn deref_mut<'a, T>(p: Ptr<T>) -> &'a mut T {
+        &mut *p.as_ptr()
+    }
+
+    unsafe fn set_next<V, E, F>(h: HalfEdgePtr<V, E, F>, next: HalfEdgePtr<V, E, F>) {
+        deref_mut(h).next = next;
+        deref_mut(next).prev = h;
+    }
+
+    pub struct HalfEdgeMesh<V, E, F> {
+        vertices: Store<Vertex<V, E, F>>,
+        halfedges: Store<HalfEdge<V, E, F>>,
+        edges: Store<Edge<V, E, F>>,
+        faces: Store<Face<V, E, F>>,
+    }
+    impl<V, E, F> HalfEdgeMesh<V, E, F> {
+        pub fn new() -> Self {
+            Self {
+                vertices: Store::new(),
+                halfedges: Store::new(),
+                edges: Store::new(),
+                faces: Store::new(),
+            }
+        }
+        pub fn vertex_count(&self) -> usize {
+            self.vertices.ptrs.len()
+        }
+        pub fn edge_count(&self) -> usize {
+            self.edges.ptrs.len()
+        }
+        pub fn face_count(&self) -> usize {
+            self.faces.ptrs.len()
+        }
+        pub fn vertices(&self) -> impl Iterator<Item = VertexPtr<V, E, F>> + '_ {
+            self.vertices.ptrs.iter().copied()
+        }
+        pub fn halfedges(&self) -> impl Iterator<Item = HalfEdgePtr<V, E, F>> + '_ {
+            self.halfedges.ptrs.iter().copied()
+        }
+        pub fn edges(&self) -> impl Iterator<Item = EdgePtr<V, E, F>> + '_ {
+            self.edges.ptrs.iter().copied()
+        }
+        pub fn faces(&self) -> impl Iterator<Item = FacePtr<V, E, F>> + '_ {
+            self.faces.ptrs.iter().copied()
+        }
+        pub fn vertex(&self, p: VertexPtr<V, E, F>) -> Option<Ref<'_, Vertex<V, E, F>>> {
+            self.vertices.pool.get(p)
+        }
+        pub fn halfedge(&self, p: HalfEdgePtr<V, E, F>) -> Option<Ref<'_, HalfEdge<V, E, F>>> {
+            self.halfedges.pool.get(p)
+        }
+        pub fn edge(&self, p: EdgePtr<V, E, F>) -> Option<Ref<'_, Edge<V, E, F>>> {
+            self.edges.pool.get(p)
+        }
+        pub fn face(&self, p: FacePtr<V, E, F>) -> Option<Ref<'_, Face<V, E, F>>> {
+            self.faces.pool.get(p)
+        }
+        pub fn vertex_mut(&mut self, p: VertexPtr<V, E, F>) -> Option<&mut V> {
+            self.vertices.pool.get_mut(p).map(|v| &mut v.value)
+        }
+        pub fn edge_mut(&mut self, p: EdgePtr<V, E, F>) -> Option<&mut E> {
+            self.edges.pool.get_mut(p).map(|e| &mut e.value)
+        }
+        pub fn face_mut(&mut self, p: FacePtr<V, E, F>) -> Option<&mut F> {
+            self.faces.pool.get_mut(p).map(|f| &mut f.value)
+        }
+
+        // 全ての Ptr が自分の Store の生きている要素を指していて、半辺の繋がりが閉じているかを検査する。
+        // at は halfedges() / edges() / faces() / vertices() の中での位置
+        pub fn validate(&self) -> Report {
+            let mut report = Report::default();
+            for (at, p) in self.halfedges().enumerate() {
+                let h = unsafe { deref(p) };
+                let mut live = true;
+                let mut check = |link, error: Option<Error>| {
+                    if let Some(error) = error {
+                        live = false;
+                        report.push(Violation::InvalidPtr {
+                            at,
+                            link,
+                            reason: error.to_string(),
+                        });
+                    }
+                };
+                check("halfedge.twin", self.halfedges.pool.try_get(h.twin).err());
+                check("halfedge.next", self.halfedges.pool.try_get(h.next).err());
+                check("halfedge.prev", self.halfedges.pool.try_get(h.prev).err());
+                check(
+                    "halfedge.origin",
+                    self.vertices.pool.try_get(h.origin).err(),
+                );
+                check("halfedge.edge", self.edges.pool.try_get(h.edge).err());
+                if let Some(face) = h.face {
+                    check("halfedge.face", self.faces.pool.try_get(face).err());
+                }
+                if !live {
+                    continue;
+                }
+                let (twin, next, prev) = unsafe { (deref(h.twin), deref(h.next), deref(h.prev)) };
+                let mut expect = |link, ok: bool| {
+                    if !ok {
+                        report.push(Violation::BrokenLink { at, link });
+                    }
+                };
+                expect("halfedge.twin", h.twin != p);
+                expect("halfedge.twin.twin", twin.twin == p);
+                expect("halfedge.next.prev", next.prev == p);
+                expect("halfedge.prev.next", prev.next == p);
+                expect("halfedge.twin.edge", twin.edge == h.edge);
+                // h の終点は h.next の始点
+                expect("halfedge.next.origin", next.origin == twin.origin);
+                expect("halfedge.next.face", next.face == h.face);
+                // 両側とも境界になっている辺は無い
+                expect(
+                    "halfedge.twin.face",
+                    h.face.is_some() || twin.face.is_some(),
+                );
+            }
+            for (at, p) in self.edges().enumerate() {
+                let h = unsafe { deref(p) }.halfedge;
+                match self.halfedges.pool.try_get(h) {
+                    Ok(h) if h.edge != p => report.push(Violation::BrokenLink {
+                        at,
+                        link: "edge.halfedge.edge",
+                    }),
+                    Ok(_) => {}
+                    Err(error) => report.push(Violation::InvalidPtr {
+                        at,
+                        link: "edge.halfedge",
+                        reason: error.to_string(),
+                    }),
+                }
+            }
+            for (at, p) in self.faces().enumerate() {
+                let h = unsafe { deref(p) }.halfedge;
+                match self.halfedges.pool.try_get(h) {
+                    Ok(h) if h.face != Some(p) => report.push(Violation::BrokenLink {
+                        at,
+                        link: "face.halfedge.face",
+                    }),
+                    Ok(_) => {}
+                    Err(error) => report.push(Violation::InvalidPtr {
+                        at,
+                        link: "face.halfedge",
+                        reason: error.to_string(),
+                    }),
+                }
+            }
+            for (at, p) in self.vertices().enumerate() {
+                let h = unsafe { deref(p) }.halfedge;
+                // 孤立した頂点
+                if h == Ptr::DANGLING {
+                    continue;
+                }
+                match self.halfedges.pool.try_get(h) {
+                    Ok(h) if h.origin != p => report.push(Violation::BrokenLink {
+                        at,
+                        link: "vertex.halfedge.origin",
+                    }),
+                    Ok(_) => {}
+                    Err(error) => report.push(Violation::InvalidPtr {
+                        at,
+                        link: "vertex.halfedge",
+                        reason: error.to_string(),
+                    }),
+                }
+            }
+            if self.halfedges.ptrs.len() != self.edge_count() * 2 {
+                report.push(Violation::CountMismatch {
+                    what: "halfedges (2 per edge)",
+                    recorded: self.edge_count() * 2,
+                    actual: self.halfedges.ptrs.len(),
+                });
+            }
+            report
+        }
+
+        // v から出ていく半辺を h -> h.twin.next の順に列挙する
+        pub fn outgoing(&self, v: VertexPtr<V, E, F>) -> Vec<HalfEdgePtr<V, E, F>> {
+            let mut result = Vec::new();
+            if let Some(vertex) = self.vertices.pool.get(v) {
+                let start = vertex.halfedge;
+                if start == Ptr::DANGLING {
+                    return result;
+                }
+                let mut h = start;
+                loop {
+                    result.push(h);
+                    h = unsafe { deref(deref(h).twin).next };
+                    if h == start {
+                        break;
+                    }
+                }
+            }
+            result
+        }
+        pub fn neighbors(&self, v: VertexPtr<V, E, F>) -> Vec<VertexPtr<V, E, F>> {
+            self.outgoing(v)
+                .into_iter()
+                .map(|h| unsafe { deref(deref(h).twin).origin })
+                .collect()
+        }
+        pub fn is_boundary_vertex(&self, v: VertexPtr<V, E, F>) -> bool {
+            self.outgoing(v)
+                .into_iter()
+                .any(|h| unsafe { deref(h).face.is_none() })
+        }
+        pub fn find_halfedge(
+            &self,
+            from: VertexPtr<V, E, F>,
+            to: VertexPtr<V, E, F>,
+        ) -> Option<HalfEdgePtr<V, E, F>> {
+            self.outgoing(from)
+                .into_iter()
+                .find(|h| unsafe { deref(deref(*h).twin).origin == to })
+        }
+
+        fn new_halfedge(
+            &mut self,
+            origin: VertexPtr<V, E, F>,
+            face: Option<FacePtr<V, E, F>>,
+        ) -> HalfEdgePtr<V, E, F> {
+            self.halfedges.alloc(HalfEdge {
+                origin,
+                twin: Ptr::DANGLING,
+                next: Ptr::DANGLING,
+                prev: Ptr::DANGLING,
+                face,
+                edge: Ptr::DANGLING,
+            })
+        }
+        unsafe fn new_edge(
+            &mut self,
+            h: HalfEdgePtr<V, E, F>,
+            t: HalfEdgePtr<V, E, F>,
+            value: E,
+        ) -> EdgePtr<V, E, F> {
+            let edge = self.edges.alloc(Edge { value, halfedge: h });
+            deref_mut(h).twin = t;
+            deref_mut(t).twin = h;
+            deref_mut(h).edge = edge;
+            deref_mut(t).edge = edge;
+            edge
+        }
+
+        // 三つの頂点と一つの面からなる三角形を新しく作る
+        pub fn make_triangle(
+            &mut self,
+            vertices: [V; 3],
+            edges: [E; 3],
+            face: F,
+        ) -> FacePtr<V, E, F> {
+            let [v0, v1, v2] = vertices;
+            let [e0, e1, e2] = edges;
+            let vs = [v0, v1, v2].map(|value| {
+                self.vertices.alloc(Vertex {
+                    value,
+                    halfedge: Ptr::DANGLING,
+                })
+            });
+            let f = self.faces.alloc(Face {
+                value: face,
+                halfedge: Ptr::DANGLING,
+            });
+            // inner[i]: vs[i] -> vs[i+1], outer[i]: vs[i+1] -> vs[i]
+            let inner = [0, 1, 2].map(|i| self.new_halfedge(vs[i], Some(f)));
+            let outer = [0, 1, 2].map(|i| self.new_halfedge(vs[(i + 1) % 3], None));
+            unsafe {
+                for (i, value) in vec![e0, e1, e2].into_iter().enumerate() {
+                    self.new_edge(inner[i], outer[i], value);
+                    set_next(inner[i], inner[(i + 1) % 3]);
+                    set_next(outer[(i + 1) % 3], outer[i]);
+                    deref_mut(vs[i]).halfedge = inner[i];
+                }
+                deref_mut(f).halfedge = inner[0];
+            }
+            f
+        }
+
+        // 面を持たない孤立した頂点を作る。add_face で面に使われるまで halfedge は DANGLING
+        pub fn add_vertex(&mut self, value: V) -> VertexPtr<V, E, F> {
+            self.vertices.alloc(Vertex {
+                value,
+                halfedge: Ptr::DANGLING,
+            })
+        }
+
+        // 既にある三つの頂点 vs[0] -> vs[1] -> vs[2] を結ぶ三角形を張る。
+        // 境界の半辺が既にあればそれを面の半辺に使い、無ければ境界側の twin と組にして作る。
+        // 既にある辺の値はそのままで、edges[i] は vs[i] -> vs[i+1] の辺を新しく作るときだけ使う。
+        // 辺を共有せずに頂点だけで接する面や、境界ループを繋ぎ直す必要がある面は非多様体になるので作らない
+        pub fn add_face(
+            &mut self,
+            vs: [VertexPtr<V, E, F>; 3],
+            edges: [E; 3],
+            face: F,
+        ) -> Option<FacePtr<V, E, F>> {
+            if vs.iter().any(|v| self.vertices.pool.get(*v).is_none())
+                || vs[0] == vs[1]
+                || vs[1] == vs[2]
+                || vs[2] == vs[0]
+            {
+                return None;
+            }
+            let old = [0, 1, 2].map(|i| self.find_halfedge(vs[i], vs[(i + 1) % 3]));
+            unsafe {
+                if old.iter().flatten().any(|h| deref(*h).face.is_some()) {
+                    return None;
+                }
+                // 角 vs[i+1] で前後の辺が両方とも新しければ孤立した頂点、両方とも既にあれば繋がっていること
+                for i in 0..3 {
+                    let (j, v) = ((i + 1) % 3, vs[(i + 1) % 3]);
+                    match (old[i], old[j]) {
+                        (None, None) if deref(v).halfedge != Ptr::DANGLING => return None,
+                        (Some(a), Some(b)) if deref(a).next != b => return None,
+                        _ => {}
+                    }
+                }
+                // 境界ループのうち新しい面の外側に残る部分。面の半辺を繋ぐ前に読んでおく
+                let out = [0, 1, 2].map(|i| old[i].map(|h| deref(h).next));
+                let into = [0, 1, 2].map(|i| old[i].map(|h| deref(h).prev));
+
+                let f = self.faces.alloc(Face {
+                    value: face,
+                    halfedge: Ptr::DANGLING,
+                });
+                // inner[i]: vs[i] -> vs[i+1], outer[i]: vs[i+1] -> vs[i]
+                let mut inner = [Ptr::DANGLING; 3];
+                let mut outer = [None; 3];
+                let [e0, e1, e2] = edges;
+                for (i, value) in vec![e0, e1, e2].into_iter().enumerate() {
+                    inner[i] = match old[i] {
+                        Some(h) => h,
+                        None => {
+                            let h = self.new_halfedge(vs[i], Some(f));
+                            let t = self.new_halfedge(vs[(i + 1) % 3], None);
+                            self.new_edge(h, t, value);
+                            outer[i] = Some(t);
+                            h
+                        }
+                    };
+                    deref_mut(inner[i]).face = Some(f);
+                }
+                for i in 0..3 {
+                    let j = (i + 1) % 3;
+                    match (outer[i], outer[j]) {
+                        // vs[j] は孤立していた
+                        (Some(t), Some(u)) => set_next(u, t),
+                        (Some(t), None) => set_next(into[j].unwrap(), t),
+                        (None, Some(u)) => set_next(u, out[i].unwrap()),
+                        (None, None) => {}
+                    }
+                }
+                for i in 0..3 {
+                    set_next(inner[i], inner[(i + 1) % 3]);
+                    if deref(vs[i]).halfedge == Ptr::DANGLING {
+                        deref_mut(vs[i]).halfedge = inner[i];
+                    }
+                }
+                deref_mut(f).halfedge = inner[0];
+                Some(f)
+            }
+        }
+
+        // 辺の中点に頂点を挿入し、両側の三角形をそれぞれ二つに分割する。
+        // 分割された辺の後半は元の辺の値を複製し、新しい対角線は E::default() になる。
+        pub fn split_edge(&mut self, edge: EdgePtr<V, E, F>, value: V) -> Option<VertexPtr<V, E, F>>
+        where
+            E: Clone + Default,
+            F: Clone,
+        {
+            let h = self.edges.pool.get(edge)?.halfedge;
+            unsafe {
+                let t = deref(h).twin;
+                let b = deref(t).origin;
+                let m = self.vertices.alloc(Vertex {
+                    value,
+                    halfedge: Ptr::DANGLING,
+                });
+                // h: a -> m, h2: m -> b, t2: b -> m, t: m -> a
+                let h2 = self.new_halfedge(m, deref(h).face);
+                let t2 = self.new_halfedge(b, deref(t).face);
+                let value = deref(edge).value.clone();
+                self.new_edge(h2, t2, value);
+                deref_mut(t).origin = m;
+                deref_mut(m).halfedge = h2;
+                if deref(b).halfedge == t {
+                    deref_mut(b).halfedge = t2;
+                }
+                let hn = deref(h).next;
+                set_next(h2, hn);
+                set_next(h, h2);
+                let tp = deref(t).prev;
+                set_next(tp, t2);
+                set_next(t2, t);
+                if deref(h).face.is_some() {
+                    self.cut(h2);
+                }
+                if deref(t).face.is_some() {
+                    self.cut(t);
+                }
+                Some(m)
+            }
+        }
+
+        // 四角形 x -> y -> z -> w の面に x.origin と z.origin を結ぶ対角線を引く。
+        // x, y を含む側を新しい面にする。
+        unsafe fn cut(&mut self, x: HalfEdgePtr<V, E, F>)
+        where
+            E: Default,
+            F: Clone,
+        {
+            let y = deref(x).next;
+            let z = deref(y).next;
+            let w = deref(z).next;
+            let f = deref(x).face.unwrap();
+            let g = self.faces.alloc(Face {
+                value: deref(f).value.clone(),
+                halfedge: x,
+            });
+            let d = self.new_halfedge(deref(z).origin, Some(g));
+            let d2 = self.new_halfedge(deref(x).origin, Some(f));
+            self.new_edge(d, d2, E::default());
+            set_next(y, d);
+            set_next(d, x);
+            set_next(w, d2);
+            set_next(d2, z);
+            deref_mut(x).face = Some(g);
+            deref_mut(y).face = Some(g);
+            deref_mut(f).halfedge = d2;
+        }
+
+        // 二つの三角形 (a, b, c) と (b, a, d) が共有する辺 a-b を c-d に付け替える
+        pub fn flip_edge(&mut self, edge: EdgePtr<V, E, F>) -> bool {
+            let h = match self.edges.pool.get(edge) {
+                Some(edge) => edge.halfedge,
+                None => return false,
+            };
+            unsafe {
+                let t = deref(h).twin;
+                let (f, g) = match (deref(h).face, deref(t).face) {
+                    (Some(f), Some(g)) => (f, g),
+                    _ => return false,
+                };
+                let (hn, hp) = (deref(h).next, deref(h).prev);
+                let (tn, tp) = (deref(t).next, deref(t).prev);
+                let (a, b) = (deref(h).origin, deref(t).origin);
+                let (c, d) = (deref(hp).origin, deref(tp).origin);
+                if c == d || self.find_halfedge(c, d).is_some() {
+                    return false;
+                }
+                // h: d -> c, t: c -> d
+                deref_mut(h).origin = d;
+                deref_mut(t).origin = c;
+                set_next(h, hp);
+                set_next(hp, tn);
+                set_next(tn, h);
+                set_next(t, tp);
+                set_next(tp, hn);
+                set_next(hn, t);
+                deref_mut(tn).face = Some(f);
+                deref_mut(hn).face = Some(g);
+                deref_mut(f).halfedge = h;
+                deref_mut(g).halfedge = t;
+                if deref(a).halfedge == h {
+                    deref_mut(a).halfedge = tn;
+                }
+                if deref(b).halfedge == t {
+                    deref_mut(b).halfedge = hn;
+                }
+            }
+            true
+        }
+
+        // 半辺 h: a -> b の辺を潰して b を a に統合する。位相が壊れる場合は何もせず None を返す。
+        pub fn collapse_edge(&mut self, h: HalfEdgePtr<V, E, F>) -> Option<VertexPtr<V, E, F>> {
+            self.halfedges.pool.get(h)?;
+            unsafe {
+                let t = deref(h).twin;
+                let edge = deref(h).edge;
+                let (a, b) = (deref(h).origin, deref(t).origin);
+                if !self.can_collapse(h) {
+                    return None;
+                }
+                let mut candidates = self.outgoing(a);
+                let b_out = self.outgoing(b);
+                candidates.extend(b_out.iter().copied());
+
+                let mut removed = vec![h, t];
+                for s in [h, t].iter() {
+                    removed.extend(self.collapse_side(*s));
+                }
+                for r in removed.iter() {
+                    self.halfedges.free(*r);
+                }
+                self.edges.free(edge);
+                for h in b_out.iter().filter(|h| !removed.contains(h)) {
+                    deref_mut(*h).origin = a;
+                }
+                self.vertices.free(b);
+                deref_mut(a).halfedge = *candidates.iter().find(|h| !removed.contains(h)).unwrap();
+                Some(a)
+            }
+        }
+
+        // link condition:
+        //   a と b の共通の隣接頂点は辺の両側の三角形の頂点だけ
+        //   内部の辺なら a と b が両方とも境界上にあってはいけない
+        //   三角形の残りの二辺が両方とも境界だと、潰した後に面の無い辺が残ってしまう
+        unsafe fn can_collapse(&self, h: HalfEdgePtr<V, E, F>) -> bool {
+            let t = deref(h).twin;
+            let (a, b) = (deref(h).origin, deref(t).origin);
+            let mut opposite = Vec::new();
+            for s in [h, t].iter() {
+                if deref(*s).face.is_some() {
+                    let (n, p) = (deref(*s).next, deref(*s).prev);
+                    if deref(deref(n).twin).face.is_none() && deref(deref(p).twin).face.is_none() {
+                        return false;
+                    }
+                    opposite.push(deref(p).origin);
+                }
+            }
+            let nb = self.neighbors(b);
+            let common = self
+                .neighbors(a)
+                .into_iter()
+                .filter(|v| nb.contains(v))
+                .collect::<Vec<_>>();
+            if common.len() != opposite.len() || common.iter().any(|v| !opposite.contains(v)) {
+                return false;
+            }
+            let interior = deref(h).face.is_some() && deref(t).face.is_some();
+            !(interior && self.is_boundary_vertex(a) && self.is_boundary_vertex(b))
+        }
+
+        // 潰す辺の片側 s を処理し、削除すべき半辺を返す。
+        // 面があれば三角形を消して残りの二辺を一本にまとめ、境界なら境界ループから s を外す。
+        unsafe fn collapse_side(&mut self, s: HalfEdgePtr<V, E, F>) -> Vec<HalfEdgePtr<V, E, F>> {
+            let (n, p) = (deref(s).next, deref(s).prev);
+            let face = match deref(s).face {
+                Some(face) => face,
+                None => {
+                    set_next(p, n);
+                    return Vec::new();
+                }
+            };
+            let (n2, p2) = (deref(n).twin, deref(p).twin);
+            let c = deref(p).origin;
+            let kept = deref(p).edge;
+            self.edges.free(deref(n).edge);
+            deref_mut(p2).twin = n2;
+            deref_mut(n2).twin = p2;
+            deref_mut(n2).edge = kept;
+            deref_mut(kept).halfedge = p2;
+            if deref(c).halfedge == p {
+                deref_mut(c).halfedge = n2;
+            }
+            self.faces.free(face);
+            vec![n, p]
+        }
+    }
+}
+
+use mesh::*;
+
+#[cfg(test)]
+type Mesh = HalfEdgeMesh<usize, (), usize>;
+
+// 不変条件
+#[cfg(test)]
+fn test_invariant(mesh: &Mesh) {
+    use crate::test07_nodes_in_arena::pool::Ptr;
+    mesh.validate().assert_ok();
+    for p in mesh.faces() {
+        // 全ての面は三角形
+        let face = mesh.face(p).unwrap();
+        let h = face.halfedge();
+        assert_eq!(Ptr::from(h.next().next().next()), Ptr::from(h));
+    }
+    for p in mesh.vertices() {
+        for h in mesh.outgoing(p) {
+            assert_eq!(Ptr::from(mesh.halfedge(h).unwrap().origin()), p);
+        }
+    }
+    // 円板と同相なので V - E + F = 1
+    assert_eq!(
+        mesh.vertex_count() + mesh.face_count(),
+        mesh.edge_count() + 1
+    );
+}
+
+#[cfg(test)]
+fn face_values(mesh: &Mesh, f: FacePtr<usize, (), usize>) -> Vec<usize> {
+    let face = mesh.face(f).unwrap();
+    let h = face.halfedge();
+    let mut values = vec![
+        *h.origin().value(),
+        *h.next().origin().value(),
+        *h.next().next().origin().value(),
+    ];
+    values.sort();
+    values
+}
+
+#[test]
+fn test_make_triangle() {
+    use crate::test07_nodes_in_arena::pool::Ptr;
+    let mut mesh = Mesh::new();
+    let f = mesh.make_triangle([0, 1, 2], [(), (), ()], 10);
+    test_invariant(&mesh);
+    assert_eq!(mesh.vertex_count(), 3);
+    assert_eq!(mesh.edge_count(), 3);
+    assert_eq!(mesh.face_count(), 1);
+    assert_eq!(face_values(&mesh, f), vec![0, 1, 2]);
+    assert_eq!(*mesh.face(f).unwrap().value(), 10);
+    for v in mesh.vertices() {
+        assert!(mesh.is_boundary_vertex(v));
+        assert_eq!(mesh.neighbors(v).len(), 2);
+    }
+    // 面の半辺は prev でも一周し、その twin は境界にある
+    let face = mesh.face(f).unwrap();
+    let h = face.halfedge();
+    assert_eq!(Ptr::from(h.prev().prev().prev()), Ptr::from(h));
+    assert!(!h.is_boundary() && h.twin().is_boundary());
+    assert_eq!(
+        Ptr::from(h.origin().halfedge().origin()),
+        Ptr::from(h.origin())
+    );
+    assert_eq!(h.edge().value(), &());
+    let v = Ptr::from(h.origin());
+    let e = Ptr::from(h.edge());
+    *mesh.vertex_mut(v).unwrap() = 5;
+    *mesh.face_mut(f).unwrap() = 11;
+    *mesh.edge_mut(e).unwrap() = ();
+    assert_eq!(face_values(&mesh, f), vec![1, 2, 5]);
+    assert_eq!(*mesh.face(f).unwrap().value(), 11);
+
+    // 一つだけの三角形の辺は flip も collapse もできない
+    let e = mesh.edges().next().unwrap();
+    assert!(!mesh.flip_edge(e));
+    let h = mesh.edge(e).unwrap().halfedge().into();
+    assert!(mesh.collapse_edge(h).is_none());
+    test_invariant(&mesh);
+}
+
+#[test]
+fn test_split_flip_collapse() {
+    use crate::test07_nodes_in_arena::pool::Ptr;
+    let mut mesh = Mesh::new();
+    let f = mesh.make_triangle([0, 1, 2], [(), (), ()], 10);
+    let face = mesh.face(f).unwrap();
+    let h = face.halfedge();
+    let (v0, v1) = (Ptr::from(h.origin()), Ptr::from(h.next().origin()));
+    let e01 = Ptr::from(h.edge());
+
+    // 0-1 の間に 3 を挿入すると三角形が二つになる
+    let v3 = mesh.split_edge(e01, 3).unwrap();
+    test_invariant(&mesh);
+    assert_eq!(mesh.vertex_count(), 4);
+    assert_eq!(mesh.edge_count(), 5);
+    assert_eq!(mesh.face_count(), 2);
+    let mut faces = mesh
+        .faces()
+        .map(|f| face_values(&mesh, f))
+        .collect::<Vec<_>>();
+    faces.sort();
+    assert_eq!(faces, vec![vec![0, 2, 3], vec![1, 2, 3]]);
+    assert!(mesh.faces().all(|f| *mesh.face(f).unwrap().value() == 10));
+    assert!(mesh.find_halfedge(v0, v1).is_none());
+    assert!(mesh.find_halfedge(v0, v3).is_some());
+
+    // 内部の辺 3-2 を分割すると内部頂点 4 ができる
+    let v2 = mesh
+        .vertices()
+        .find(|v| *mesh.vertex(*v).unwrap().value() == 2)
+        .unwrap();
+    let h32 = mesh.find_halfedge(v3, v2).unwrap();
+    let e32 = Ptr::from(mesh.halfedge(h32).unwrap().edge());
+    let v4 = mesh.split_edge(e32, 4).unwrap();
+    test_invariant(&mesh);
+    assert_eq!(mesh.face_count(), 4);
+    assert!(!mesh.is_boundary_vertex(v4));
+    assert_eq!(mesh.neighbors(v4).len(), 4);
+
+    // 0-4 を flip すると 2-3 になる
+    let h04 = mesh.find_halfedge(v0, v4).unwrap();
+    let e04 = Ptr::from(mesh.halfedge(h04).unwrap().edge());
+    assert!(mesh.flip_edge(e04));
+    test_invariant(&mesh);
+    assert!(mesh.find_halfedge(v0, v4).is_none());
+    assert!(mesh.find_halfedge(v2, v3).is_some());
+    assert_eq!(mesh.neighbors(v4).len(), 3);
+    // 境界の辺は flip できない
+    let h03 = mesh.find_halfedge(v0, v3).unwrap();
+    assert!(!mesh.flip_edge(mesh.halfedge(h03).unwrap().edge().into()));
+
+    // 内部の辺 3-4 を潰すと 4 が 3 に統合される
+    let h34 = mesh.find_halfedge(v3, v4).unwrap();
+    let e34 = Ptr::from(mesh.halfedge(h34).unwrap().edge());
+    assert_eq!(mesh.collapse_edge(h34), Some(v3));
+    test_invariant(&mesh);
+    assert!(mesh.vertex(v4).is_none());
+    assert!(mesh.edge(e34).is_none());
+    assert_eq!(mesh.vertex_count(), 4);
+    assert_eq!(mesh.face_count(), 2);
+
+    // 両端が境界上にある内部の辺 2-3 は潰せない
+    let h23 = mesh.find_halfedge(v2, v3).unwrap();
+    assert!(mesh.collapse_edge(h23).is_none());
+    test_invariant(&mesh);
+
+    // 境界の辺 0-3 を潰すと三角形一つに戻る
+    let h03 = mesh.find_halfedge(v0, v3).unwrap();
+    assert_eq!(mesh.collapse_edge(h03), Some(v0));
+    test_invariant(&mesh);
+    assert_eq!(mesh.vertex_count(), 3);
+    assert_eq!(mesh.edge_count(), 3);
+    assert_eq!(mesh.face_count(), 1);
+    let f = mesh.faces().next().unwrap();
+    assert_eq!(face_values(&mesh, f), vec![0, 1, 2]);
+}
+
+// 既にある頂点で面を張ると境界の半辺が twin として使い回される
+#[test]
+fn test_add_face() {
+    let mut mesh = Mesh::new();
+    let [v0, v1, v2] = [0, 1, 2].map(|i| mesh.add_vertex(i));
+    // 孤立した頂点は出ていく半辺を持たない
+    mesh.validate().assert_ok();
+    assert!(mesh.outgoing(v0).is_empty());
+    mesh.add_face([v0, v1, v2], [(), (), ()], 10).unwrap();
+    test_invariant(&mesh);
+
+    // 四角形 0-1-2-3 を対角線 0-2 で二つの三角形にする。2 -> 0 の境界の半辺が面の半辺になる
+    let v3 = mesh.add_vertex(3);
+    let h20 = mesh.find_halfedge(v2, v0).unwrap();
+    let h02 = mesh.find_halfedge(v0, v2).unwrap();
+    let f = mesh.add_face([v0, v2, v3], [(), (), ()], 20).unwrap();
+    test_invariant(&mesh);
+    assert_eq!(mesh.find_halfedge(v0, v2), Some(h02));
+    assert_eq!(mesh.halfedge(h02).unwrap().face().unwrap().value(), &20);
+    assert_eq!(face_values(&mesh, f), vec![0, 2, 3]);
+    assert_eq!(mesh.vertex_count(), 4);
+    assert_eq!(mesh.edge_count(), 5);
+    assert_eq!(mesh.face_count(), 2);
+    assert!(mesh.vertices().all(|v| mesh.is_boundary_vertex(v)));
+    assert_eq!(mesh.neighbors(v0).len(), 3);
+    assert_eq!(mesh.neighbors(v1).len(), 2);
+
+    // 面のある半辺を使う面や、同じ頂点を二度使う面は作れない
+    assert!(mesh.add_face([v0, v1, v2], [(), (), ()], 30).is_none());
+    assert!(mesh.add_face([v0, v0, v1], [(), (), ()], 30).is_none());
+    test_invariant(&mesh);
+
+    // 対角線 0-2 を flip すると 1-3 になる
+    let e02 = mesh.halfedge(h20).unwrap().edge().into();
+    assert!(mesh.flip_edge(e02));
+    test_invariant(&mesh);
+    assert!(mesh.find_halfedge(v0, v2).is_none());
+    assert!(mesh.find_halfedge(v1, v3).is_some());
+    let mut faces = mesh
+        .faces()
+        .map(|f| face_values(&mesh, f))
+        .collect::<Vec<_>>();
+    faces.sort();
+    assert_eq!(faces, vec![vec![0, 1, 3], vec![1, 2, 3]]);
+
+    // 両端が境界上にある対角線は潰せないが、境界の辺 0-1 は潰せる
+    let h13 = mesh.find_halfedge(v1, v3).unwrap();
+    assert!(mesh.collapse_edge(h13).is_none());
+    let h01 = mesh.find_halfedge(v0, v1).unwrap();
+    assert_eq!(mesh.collapse_edge(h01), Some(v0));
+    test_invariant(&mesh);
+    assert!(mesh.vertex(v1).is_none());
+    assert_eq!(mesh.face_count(), 1);
+    let f = mesh.faces().next().unwrap();
+    assert_eq!(face_values(&mesh, f), vec![0, 2, 3]);
+
+    // 辺を共有せずに頂点 0 だけで接する面は作れない
+    let [v4, v5] = [4, 5].map(|i| mesh.add_vertex(i));
+    assert!(mesh.add_face([v0, v4, v5], [(), (), ()], 30).is_none());
+    // 向きが逆で面のある半辺 0 -> 2 を使うことになる面も作れない
+    assert!(mesh.add_face([v0, v2, v4], [(), (), ()], 30).is_none());
+    // 境界の辺 2-0 と 2-4 に沿って面を足していく
+    mesh.add_face([v0, v4, v2], [(), (), ()], 30).unwrap();
+    mesh.add_face([v4, v5, v2], [(), (), ()], 40).unwrap();
+    test_invariant(&mesh);
+    assert_eq!(mesh.vertex_count(), 5);
+    assert_eq!(mesh.edge_count(), 7);
+    assert_eq!(mesh.face_count(), 3);
+    assert!(!mesh.vertices().any(|v| mesh.outgoing(v).is_empty()));
+}
+
+#[test]
+fn test_validate() {
+    use crate::test07_nodes_in_arena::pool::{Error, Ptr};
+    use crate::validate::Violation;
+    let mut mesh1 = Mesh::new();
+    mesh1.make_triangle([0, 1, 2], [(), (), ()], 10);
+    let mut mesh2 = Mesh::new();
+    mesh2.make_triangle([3, 4, 5], [(), (), ()], 20);
+
+    // 頂点を丸ごと入れ替えると、その頂点の halfedge だけが別のメッシュを指す
+    let mut v1 = mesh1.vertices().next().unwrap();
+    let mut v2 = mesh2.vertices().next().unwrap();
+    unsafe { std::mem::swap(v1.as_mut(), v2.as_mut()) };
+    assert_eq!(
+        mesh1.validate().violations,
+        vec![Violation::InvalidPtr {
+            at: 0,
+            link: "vertex.halfedge",
+            reason: Error::ForeignPool.to_string()
+        }]
+    );
+    unsafe { std::mem::swap(v1.as_mut(), v2.as_mut()) };
+    mesh1.validate().assert_ok();
+
+    // 半辺の中身を twin と入れ替えると向きが逆になり、繋がりが閉じなくなる
+    let mut h = mesh1.halfedges().next().unwrap();
+    let mut twin = Ptr::from(mesh1.halfedge(h).unwrap().twin());
+    unsafe { std::mem::swap(h.as_mut(), twin.as_mut()) };
+    assert!(!mesh1.validate().is_ok());
+    unsafe { std::mem::swap(h.as_mut(), twin.as_mut()) };
+    mesh1.validate().assert_ok();
+}
@@ -0,0 +1,639 @@
+pub mod slab {
+    use crate::memory::Usage;
+    use lazy_static::lazy_static;
+    use std::ops::{Index, IndexMut};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    lazy_static! {
+        static ref COUNTER: AtomicU32 = AtomicU32::new(1);
+    }
+
+    // Vec の添字と世代でスロットを指す。ポインタを持たないので HashMap も unsafe も要らない。
+    // slab は Key を作った Slab の番号で、別の Slab の Key を見分けるのに使う。0 は DANGLING
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Key {
+        index: u32,
+        generation: u32,
+        slab: u32,
+    }
+    impl Key {
+        pub const DANGLING: Self = Self {
+            index: 0,
+            generation: 0,
+            slab: 0,
+        };
+        pub fn index(&self) -> usize {
+            self.index as usize
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub enum Error {
+        ForeignPool, // 別の Slab で確保された Key
+        Dangling,    // Key::DANGLING
+        Removed,     // free 済みのスロットを指している Key
+    }
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                Error::ForeignPool => write!(f, "pointer belongs to another pool"),
+                Error::Dangling => write!(f, "dangling pointer"),
+                Error::Removed => write!(f, "pointer to a removed object"),
+            }
+        }
+    }
+    impl std::error::Error for Error {}
+
+    const NIL: u32 = u32::MAX;
+
+    // 空いたスロットは次の空きスロットの添字を持って free list を作る。NIL なら最後
+    enum Entry<T> {
+        Occupied(T),
+        Vacant(u32),
+    }
+
+    // generation は free の度に増やすので、古い Key とは一致しなくなる
+    struct Slot<T> {
+        entry: Entry<T>,
+        generation: u32,
+    }
+
+    pub struct Slab<T> {
+        slots: Vec<Slot<T>>,
+        free_head: u32,
+        free: usize,
+        id: u32,
+        peak: usize,
+    }
+    impl<T> Slab<T> {
+        pub fn new() -> Self {
+            Self {
+                slots: Vec::new(),
+                free_head: NIL,
+                free: 0,
+                id: COUNTER.fetch_add(1, Ordering::Relaxed),
+                peak: 0,
+            }
+        }
+        pub fn alloc(&mut self, value: T) -> Key {
+            let index = if self.free_head != NIL {
+                let index = self.free_head;
+                let slot = &mut self.slots[index as usize];
+                match std::mem::replace(&mut slot.entry, Entry::Occupied(value)) {
+                    Entry::Vacant(next) => self.free_head = next,
+                    Entry::Occupied(_) => unreachable!(),
+                }
+                self.free -= 1;
+                index
+            } else {
+                assert!(self.slots.len() < NIL as usize, "too many slots");
+                self.slots.push(Slot {
+                    entry: Entry::Occupied(value),
+                    generation: 0,
+                });
+                self.peak = self.peak.max(self.bytes());
+                (self.slots.len() - 1) as u32
+            };
+            Key {
+                index,
+                generation: self.slots[index as usize].generation,
+                slab: self.id,
+            }
+        }
+        pub fn free(&mut self, key: Key) -> Option<T> {
+            self.check(key).ok()?;
+            let slot = &mut self.slots[key.index()];
+            slot.generation = slot.generation.wrapping_add(1);
+            match std::mem::replace(&mut slot.entry, Entry::Vacant(self.free_head)) {
+                Entry::Occupied(value) => {
+                    self.free_head = key.index;
+                    self.free += 1;
+                    Some(value)
+                }
+                Entry::Vacant(_) => unreachable!(),
+            }
+        }
+        // slab が一致すれば index は self.slots の範囲内にある
+        fn check(&self, key: Key) -> Result<(), Error> {
+            if key.slab == 0 {
+                Err(Error::Dangling)
+            } else if key.slab != self.id {
+                Err(Error::ForeignPool)
+            } else if self.slots[key.index()].generation != key.generation {
+                Err(Error::Removed)
+            } else {
+                Ok(())
+            }
+        }
+        pub fn try_get(&self, key: Key) -> Result<&T, Error> {
+            self.check(key)?;
+            match &self.slots[key.index()].entry {
+                Entry::Occupied(value) => Ok(value),
+                Entry::Vacant(_) => Err(Error::Removed),
+            }
+        }
+        pub fn try_get_mut(&mut self, key: Key) -> Result<&mut T, Error> {
+            self.check(key)?;
+            match &mut self.slots[key.index()].entry {
+                Entry::Occupied(value) => Ok(value),
+                Entry::Vacant(_) => Err(Error::Removed),
+            }
+        }
+        pub fn get(&self, key: Key) -> Option<&T> {
+            self.try_get(key).ok()
+        }
+        pub fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+            self.try_get_mut(key).ok()
+        }
+        // Vec は縮めないので、空きスロットも数に含まれる
+        fn bytes(&self) -> usize {
+            self.slots.capacity() * std::mem::size_of::<Slot<T>>()
+        }
+        pub fn usage(&self) -> Usage {
+            Usage {
+                live: self.slots.len() - self.free,
+                free: self.free,
+                bytes: Some(self.bytes()),
+                peak: Some(self.peak),
+            }
+        }
+    }
+    // 自分で張ったリンクを辿るときに使う。無効な Key なら panic する
+    impl<T> Index<Key> for Slab<T> {
+        type Output = T;
+        fn index(&self, key: Key) -> &T {
+            self.try_get(key).unwrap()
+        }
+    }
+    impl<T> IndexMut<Key> for Slab<T> {
+        fn index_mut(&mut self, key: Key) -> &mut T {
+            self.try_get_mut(key).unwrap()
+        }
+    }
+}
+
+#[test]
+fn test_slab() {
+    use slab::*;
+    let mut slab = Slab::new();
+    let k = slab.alloc(123);
+    assert_eq!(slab[k], 123);
+
+    slab[k] = 456;
+    assert_eq!(*slab.get(k).unwrap(), 456);
+
+    assert_eq!(slab.free(k), Some(456));
+    assert!(slab.get(k).is_none());
+    assert!(slab.get_mut(k).is_none());
+    assert!(slab.free(k).is_none());
+
+    // 解放したスロットが再利用されても古い Key では読めない
+    let l = slab.alloc(789);
+    assert_eq!(l.index(), k.index());
+    assert_ne!(l, k);
+    assert!(slab.get(k).is_none());
+    assert_eq!(slab[l], 789);
+
+    // free list は後に解放したものから使う
+    let keys = (0..4).map(|i| slab.alloc(i)).collect::<Vec<_>>();
+    slab.free(keys[1]);
+    slab.free(keys[3]);
+    assert_eq!(slab.alloc(10).index(), keys[3].index());
+    assert_eq!(slab.alloc(11).index(), keys[1].index());
+    assert_eq!(slab.alloc(12).index(), 5);
+}
+
+#[test]
+fn test_slab_try_get() {
+    use slab::*;
+    let mut slab1 = Slab::new();
+    let mut slab2 = Slab::new();
+    let k1 = slab1.alloc(1);
+    let k2 = slab2.alloc(2);
+    assert_eq!(k1.index(), k2.index());
+    assert_eq!(*slab1.try_get(k1).unwrap(), 1);
+    assert_eq!(slab1.try_get(k2), Err(Error::ForeignPool));
+    assert_eq!(slab1.try_get_mut(k2), Err(Error::ForeignPool));
+    assert_eq!(slab1.try_get(Key::DANGLING), Err(Error::Dangling));
+    assert!(slab1.free(k2).is_none());
+
+    assert_eq!(slab1.free(k1), Some(1));
+    assert_eq!(slab1.try_get(k1), Err(Error::Removed));
+    assert_eq!(slab1.try_get_mut(k1), Err(Error::Removed));
+    assert_eq!(*slab2.try_get_mut(k2).unwrap(), 2);
+}
+
+#[test]
+fn test_slab_usage() {
+    use slab::*;
+    let mut slab = Slab::new();
+    let keys = (0..10).map(|i| slab.alloc(i as u64)).collect::<Vec<_>>();
+    let usage = slab.usage();
+    assert_eq!((usage.live, usage.free), (10, 0));
+    assert_eq!(usage.peak, usage.bytes);
+    for &k in &keys[..4] {
+        slab.free(k);
+    }
+    let after = slab.usage();
+    assert_eq!((after.live, after.free), (6, 4));
+    assert_eq!(after.bytes, usage.bytes);
+}
+
+pub mod list {
+    use super::slab::*;
+    use crate::linked_list::LinkedList;
+    use crate::memory::Usage;
+    use crate::validate::{self, NodeLinks, Report};
+    use std::iter::FromIterator;
+
+    pub struct Node<T> {
+        value: Option<T>,
+        next: Key,
+        prev: Key,
+    }
+
+    // ノードは互いを Key で指すので、辿るときは必ず List を通す
+    pub struct List<T> {
+        nodes: Slab<Node<T>>,
+        sentinel: Key,
+        len: usize,
+    }
+    impl<T> List<T> {
+        pub fn new() -> Self {
+            let mut nodes = Slab::new();
+            let sentinel = nodes.alloc(Node {
+                value: None,
+                next: Key::DANGLING,
+                prev: Key::DANGLING,
+            });
+            nodes[sentinel].next = sentinel;
+            nodes[sentinel].prev = sentinel;
+            Self {
+                nodes,
+                sentinel,
+                len: 0,
+            }
+        }
+        pub fn sentinel(&self) -> Key {
+            self.sentinel
+        }
+        // 空なら sentinel を返す
+        pub fn head(&self) -> Key {
+            self.nodes[self.sentinel].next
+        }
+        pub fn tail(&self) -> Key {
+            self.nodes[self.sentinel].prev
+        }
+        pub fn is_empty(&self) -> bool {
+            self.len == 0
+        }
+        pub fn len(&self) -> usize {
+            self.len
+        }
+        // sentinel も辿れる。このリストの生きているノードでなければ None
+        pub fn next(&self, key: Key) -> Option<Key> {
+            self.nodes.get(key).map(|node| node.next)
+        }
+        pub fn prev(&self, key: Key) -> Option<Key> {
+            self.nodes.get(key).map(|node| node.prev)
+        }
+        pub fn get(&self, key: Key) -> Option<&T> {
+            self.nodes.get(key)?.value.as_ref()
+        }
+        pub fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+            self.nodes.get_mut(key)?.value.as_mut()
+        }
+        // sentinel から一周してリンクと len を検査する。変更の後に debug build で呼ぶ
+        pub fn validate(&self) -> Report {
+            validate::check_sentinel_list(self.sentinel, Some(self.len), |key| {
+                self.nodes
+                    .try_get(key)
+                    .map(|node| NodeLinks {
+                        key: key.index(),
+                        is_sentinel: node.value.is_none(),
+                        next: node.next,
+                        prev: node.prev,
+                    })
+                    .map_err(|e| e.to_string())
+            })
+        }
+        // validate の試験でノードを壊すために使う
+        #[cfg(test)]
+        pub(super) fn node_mut(&mut self, key: Key) -> &mut Node<T> {
+            &mut self.nodes[key]
+        }
+        // 近い方の端から辿る
+        pub fn nth(&self, i: usize) -> Option<Key> {
+            if i >= self.len {
+                None
+            } else if i <= self.len / 2 {
+                let mut key = self.head();
+                for _ in 0..i {
+                    key = self.nodes[key].next;
+                }
+                Some(key)
+            } else {
+                let mut key = self.tail();
+                for _ in 0..self.len - 1 - i {
+                    key = self.nodes[key].prev;
+                }
+                Some(key)
+            }
+        }
+        pub fn position(&self, key: Key) -> Option<usize> {
+            self.get(key)?;
+            let mut current = self.head();
+            let mut i = 0;
+            while current != self.sentinel {
+                if current == key {
+                    return Some(i);
+                }
+                current = self.nodes[current].next;
+                i += 1;
+            }
+            None
+        }
+        pub fn iter(&self) -> Iter<'_, T> {
+            Iter {
+                list: self,
+                head: self.head(),
+                tail: self.tail(),
+                len: self.len,
+            }
+        }
+        fn insert_before(&mut self, next: Key, value: T) -> Key {
+            let prev = self.nodes[next].prev;
+            let node = self.nodes.alloc(Node {
+                value: Some(value),
+                next,
+                prev,
+            });
+            self.nodes[next].prev = node;
+            self.nodes[prev].next = node;
+            self.len += 1;
+            node
+        }
+        // pos の直前に挿入する。pos が sentinel なら末尾に追加する
+        pub fn insert(&mut self, pos: Key, value: T) -> Option<Key> {
+            self.nodes.get(pos)?;
+            Some(self.insert_before(pos, value))
+        }
+        pub fn push_back(&mut self, value: T) -> Key {
+            self.insert_before(self.sentinel, value)
+        }
+        pub fn push_front(&mut self, value: T) -> Key {
+            self.insert_before(self.head(), value)
+        }
+        // sentinel は削除できない
+        pub fn remove(&mut self, key: Key) -> Option<T> {
+            self.get(key)?;
+            let node = self.nodes.free(key)?;
+            self.nodes[node.next].prev = node.prev;
+            self.nodes[node.prev].next = node.next;
+            self.len -= 1;
+            node.value
+        }
+        pub fn pop_front(&mut self) -> Option<T> {
+            self.remove(self.head())
+        }
+        pub fn pop_back(&mut self) -> Option<T> {
+            self.remove(self.tail())
+        }
+    }
+
+    pub struct Iter<'a, T> {
+        list: &'a List<T>,
+        head: Key,
+        tail: Key,
+        len: usize,
+    }
+    impl<'a, T> Iterator for Iter<'a, T> {
+        type Item = &'a T;
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = &self.list.nodes[self.head];
+            self.head = node.next;
+            node.value.as_ref()
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
+    }
+    impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            self.len -= 1;
+            let node = &self.list.nodes[self.tail];
+            self.tail = node.prev;
+            node.value.as_ref()
+        }
+    }
+    impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+    pub struct IntoIter<T>(List<T>);
+    impl<T> Iterator for IntoIter<T> {
+        type Item = T;
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0.pop_front()
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.0.len, Some(self.0.len))
+        }
+    }
+    impl<T> DoubleEndedIterator for IntoIter<T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            self.0.pop_back()
+        }
+    }
+    impl<T> ExactSizeIterator for IntoIter<T> {}
+
+    impl<T> IntoIterator for List<T> {
+        type Item = T;
+        type IntoIter = IntoIter<T>;
+        fn into_iter(self) -> Self::IntoIter {
+            IntoIter(self)
+        }
+    }
+    impl<'a, T> IntoIterator for &'a List<T> {
+        type Item = &'a T;
+        type IntoIter = Iter<'a, T>;
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+    impl<T> FromIterator<T> for List<T> {
+        fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+            let mut list = Self::new();
+            list.extend(iter);
+            list
+        }
+    }
+    impl<T> Extend<T> for List<T> {
+        fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+            for value in iter {
+                self.push_back(value);
+            }
+        }
+    }
+
+    // Handle は sentinel 以外のノードの Key。同名の inherent method と区別するために List:: を付けて呼ぶ
+    impl<T> List<T> {
+        fn handle(&self, key: Key) -> Option<Key> {
+            Some(key).filter(|&key| key != self.sentinel)
+        }
+    }
+    impl<T> LinkedList<T> for List<T> {
+        type Handle = Key;
+        type Iter<'a>
+            = Iter<'a, T>
+        where
+            T: 'a;
+        const NAME: &'static str = "slab";
+        fn new() -> Self {
+            List::new()
+        }
+        fn len(&self) -> usize {
+            self.len
+        }
+        fn iter(&self) -> Iter<'_, T> {
+            List::iter(self)
+        }
+        fn head(&self) -> Option<Key> {
+            self.handle(List::head(self))
+        }
+        fn tail(&self) -> Option<Key> {
+            self.handle(List::tail(self))
+        }
+        fn next(&self, h: Key) -> Option<Key> {
+            self.handle(List::next(self, self.handle(h)?)?)
+        }
+        fn prev(&self, h: Key) -> Option<Key> {
+            self.handle(List::prev(self, self.handle(h)?)?)
+        }
+        fn nth(&self, i: usize) -> Option<Key> {
+            List::nth(self, i)
+        }
+        fn position(&self, h: Key) -> Option<usize> {
+            List::position(self, h)
+        }
+        fn get(&self, h: Key) -> Option<&T> {
+            List::get(self, h)
+        }
+        fn get_mut(&mut self, h: Key) -> Option<&mut T> {
+            List::get_mut(self, h)
+        }
+        fn insert(&mut self, h: Key, value: T) -> Option<Key> {
+            List::insert(self, self.handle(h)?, value)
+        }
+        fn push_back(&mut self, value: T) -> Key {
+            List::push_back(self, value)
+        }
+        fn push_front(&mut self, value: T) -> Key {
+            List::push_front(self, value)
+        }
+        fn remove(&mut self, h: Key) -> Option<T> {
+            List::remove(self, h)
+        }
+        fn pop_front(&mut self) -> Option<T> {
+            List::pop_front(self)
+        }
+        fn pop_back(&mut self) -> Option<T> {
+            List::pop_back(self)
+        }
+        fn validate(&self) -> Report {
+            List::validate(self)
+        }
+        fn memory(&self) -> Usage {
+            self.nodes.usage()
+        }
+    }
+}
+
+pub use list::*;
+
+#[test]
+fn test_list() {
+    let mut list: List<usize> = List::new();
+    assert_eq!(list.head(), list.sentinel());
+    assert_eq!(list.tail(), list.sentinel());
+    assert!(list.is_empty());
+
+    let k1 = list.push_back(1);
+    assert_eq!(list.head(), k1);
+    assert_eq!(list.tail(), k1);
+    assert_eq!(list.next(k1), Some(list.sentinel()));
+    assert_eq!(list.prev(k1), Some(list.sentinel()));
+
+    let k2 = list.push_back(2);
+    let k3 = list.push_front(3);
+    assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![3, 1, 2]);
+
+    // sentinel の前に挿入すると末尾に入る
+    let k4 = list.insert(list.sentinel(), 4).unwrap();
+    let k5 = list.insert(k1, 5).unwrap();
+    assert_eq!(
+        list.iter().copied().collect::<Vec<_>>(),
+        vec![3, 5, 1, 2, 4]
+    );
+    assert_eq!(list.tail(), k4);
+    assert_eq!(list.next(k3), Some(k5));
+    assert_eq!(list.len(), 5);
+
+    *list.get_mut(k2).unwrap() = 20;
+    assert_eq!(list.remove(k3), Some(3));
+    assert_eq!(list.head(), k5);
+    assert!(list.get(k3).is_none());
+    assert!(list.remove(k3).is_none());
+    assert!(list.remove(list.sentinel()).is_none());
+    assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![5, 1, 20, 4]);
+
+    // 別のリストの Key や DANGLING を渡しても panic しない
+    let mut list2: List<usize> = List::new();
+    let other = list2.push_back(6);
+    assert!(list.get(other).is_none());
+    assert!(list.insert(other, 7).is_none());
+    assert!(list.remove(other).is_none());
+    assert!(list.next(slab::Key::DANGLING).is_none());
+    assert!(list.insert(slab::Key::DANGLING, 7).is_none());
+    assert_eq!(list.len(), 4);
+    list.validate().assert_ok();
+    list2.validate().assert_ok();
+}
+
+// 削除したスロットは次の確保で使い回すので、確保し直しても Slab は伸びない
+#[test]
+fn test_reuse_slots() {
+    use crate::linked_list::LinkedList;
+    let mut list: List<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+    let bytes = LinkedList::memory(&list).bytes;
+    while list.pop_front().is_some() {}
+    list.validate().assert_ok();
+    list.extend(["x", "y", "z"].iter().map(|s| s.to_string()));
+    assert_eq!(LinkedList::memory(&list).bytes, bytes);
+    assert_eq!(LinkedList::memory(&list).free, 0);
+}
+
+// 別の Slab の Key を指すようになり、try_get の理由がそのまま出る
+#[test]
+fn test_validate() {
+    crate::linked_list::exercise_validate::<List<usize>>(
+        |list1, k1, list2, k2| std::mem::swap(list1.node_mut(k1), list2.node_mut(k2)),
+        &slab::Error::ForeignPool.to_string(),
+    );
+}
+
+#[test]
+fn test_drop() {
+    use std::rc::Rc;
+    let value = Rc::new(0);
+    let mut list: List<Rc<usize>> = (0..5).map(|_| value.clone()).collect();
+    list.pop_front();
+    assert_eq!(Rc::strong_count(&value), 5);
+    drop(list);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
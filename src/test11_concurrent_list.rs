@@ -0,0 +1,293 @@
+#![cfg(test)]
+// test07 の List 全体を RwLock で守り、複数のスレッドから共有できるようにしたもの。
+// ノードを一つずつロックすると隣のノードとの間でデッドロックしやすく、削除したノードの Ptr も
+// Pool の世代で弾けるので、リスト単位のロックで十分と考えた。
+// Ref は読み取りロックより長く生きられないので、外には Ptr と値（の clone）だけを渡す。
+use crate::linked_list::LinkedList;
+use crate::test07_nodes_in_arena::list::{self as arena, Node};
+use crate::test07_nodes_in_arena::pool::Ptr;
+use crate::validate::Report;
+use std::iter::FromIterator;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+pub struct List<T> {
+    inner: RwLock<arena::List<T>>,
+}
+impl<T> List<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: RwLock::new(arena::List::new()),
+        }
+    }
+    // 他のスレッドが panic してロックが poison されても、List 自体は操作の途中で壊れないので使い続ける
+    fn read_lock(&self) -> RwLockReadGuard<'_, arena::List<T>> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner())
+    }
+    fn write_lock(&self) -> RwLockWriteGuard<'_, arena::List<T>> {
+        self.inner.write().unwrap_or_else(|e| e.into_inner())
+    }
+    pub fn len(&self) -> usize {
+        self.read_lock().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.read_lock().is_empty()
+    }
+    pub fn push_back(&self, value: T) -> Ptr<Node<T>> {
+        LinkedList::push_back(&mut *self.write_lock(), value)
+    }
+    pub fn push_front(&self, value: T) -> Ptr<Node<T>> {
+        LinkedList::push_front(&mut *self.write_lock(), value)
+    }
+    // pos の直前に挿入する。pos が既に削除されていれば None
+    pub fn insert(&self, pos: Ptr<Node<T>>, value: T) -> Option<Ptr<Node<T>>> {
+        LinkedList::insert(&mut *self.write_lock(), pos, value)
+    }
+    // 同じ Ptr を複数のスレッドが remove しても、値を受け取るのは一つだけ
+    pub fn remove(&self, ptr: Ptr<Node<T>>) -> Option<T> {
+        self.write_lock().take(ptr)
+    }
+    pub fn pop_front(&self) -> Option<T> {
+        self.write_lock().pop_front()
+    }
+    pub fn pop_back(&self) -> Option<T> {
+        self.write_lock().pop_back()
+    }
+    pub fn contains(&self, ptr: Ptr<Node<T>>) -> bool {
+        self.read_lock().get(ptr).is_some()
+    }
+    pub fn with<R>(&self, ptr: Ptr<Node<T>>, f: impl FnOnce(&T) -> R) -> Option<R> {
+        LinkedList::get(&*self.read_lock(), ptr).map(f)
+    }
+    pub fn with_mut<R>(&self, ptr: Ptr<Node<T>>, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        LinkedList::get_mut(&mut *self.write_lock(), ptr).map(f)
+    }
+    // 読み取りロックを持ったまま f に List を渡す。辿っている間は他のスレッドが変更できない
+    pub fn read<R>(&self, f: impl FnOnce(&arena::List<T>) -> R) -> R {
+        f(&self.read_lock())
+    }
+    // 複数の操作をまとめて、他のスレッドから途中の状態が見えないように行う
+    pub fn write<R>(&self, f: impl FnOnce(&mut arena::List<T>) -> R) -> R {
+        f(&mut self.write_lock())
+    }
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.read_lock().iter().cloned().collect()
+    }
+    pub fn validate(&self) -> Report {
+        self.read_lock().validate()
+    }
+    pub fn into_inner(self) -> arena::List<T> {
+        self.inner.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            inner: RwLock::new(iter.into_iter().collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+const THREADS: usize = if cfg!(miri) { 3 } else { 8 };
+#[cfg(test)]
+const PER_THREAD: usize = if cfg!(miri) { 20 } else { 2000 };
+
+#[test]
+fn test_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<List<usize>>();
+    assert_send_sync::<List<String>>();
+    assert_send_sync::<Ptr<Node<usize>>>();
+    assert_send_sync::<arena::List<usize>>();
+}
+
+#[test]
+fn test_list() {
+    let list: List<usize> = (1..=3).collect();
+    let p2 = list.read(|list| list.nth(1).unwrap().into());
+    let p0 = list.push_front(0);
+    let p4 = list.push_back(4);
+    assert!(list.insert(p2, 10).is_some());
+    assert_eq!(list.to_vec(), vec![0, 1, 10, 2, 3, 4]);
+
+    assert_eq!(list.with(p2, |&v| v), Some(2));
+    assert_eq!(list.with_mut(p2, |v| std::mem::replace(v, 20)), Some(2));
+    assert_eq!(list.remove(p2), Some(20));
+    assert!(!list.contains(p2));
+    assert!(list.remove(p2).is_none());
+    assert!(list.insert(p2, 5).is_none());
+    assert!(list.with(p2, |_| ()).is_none());
+
+    assert_eq!(list.pop_front(), Some(0));
+    assert_eq!(list.pop_back(), Some(4));
+    assert!(!list.contains(p0) && !list.contains(p4));
+    assert_eq!(list.len(), 3);
+    assert!(!list.is_empty());
+    list.write(|list| list.push_back(6));
+    list.validate().assert_ok();
+    assert_eq!(
+        list.into_inner().into_iter().collect::<Vec<_>>(),
+        vec![1, 10, 3, 6]
+    );
+}
+
+// 作業キューとして使う。複数の producer が push_back し、複数の consumer が pop_front する
+#[test]
+fn test_work_queue() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    let queue = Arc::new(List::new());
+    let produced = Arc::new(AtomicUsize::new(0));
+    let producers = (0..THREADS)
+        .map(|t| {
+            let queue = queue.clone();
+            let produced = produced.clone();
+            thread::spawn(move || {
+                for i in 0..PER_THREAD {
+                    queue.push_back(t * PER_THREAD + i);
+                    produced.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let consumers = (0..THREADS)
+        .map(|_| {
+            let queue = queue.clone();
+            let produced = produced.clone();
+            thread::spawn(move || {
+                let mut received = Vec::new();
+                loop {
+                    // pop_front より先に見ておかないと、最後の値を取り残して終わることがある
+                    let done = produced.load(Ordering::SeqCst) == THREADS * PER_THREAD;
+                    match queue.pop_front() {
+                        Some(value) => received.push(value),
+                        None if done => break received,
+                        None => thread::yield_now(),
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    let mut received = Vec::new();
+    for consumer in consumers {
+        let values = consumer.join().unwrap();
+        // 同じ producer の値は push した順に取り出される
+        for t in 0..THREADS {
+            let from_t = values.iter().filter(|&&v| v / PER_THREAD == t);
+            assert!(from_t.clone().zip(from_t.skip(1)).all(|(a, b)| a < b));
+        }
+        received.extend(values);
+    }
+    received.sort();
+    assert_eq!(received, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+    assert!(queue.is_empty());
+    queue.validate().assert_ok();
+}
+
+// 挿入・削除・走査を複数のスレッドで同時に行い、最後に中身と不変条件を確かめる
+#[test]
+fn test_stress() {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+    use std::thread;
+
+    let list: Arc<List<usize>> = Arc::new((0..100).collect());
+    let shared = list.read(|list| {
+        (0..list.len())
+            .map(|i| list.nth(i).unwrap().into())
+            .collect::<Vec<Ptr<Node<usize>>>>()
+    });
+    let shared = Arc::new(shared);
+
+    let writers = (0..THREADS)
+        .map(|t| {
+            let list = list.clone();
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let mut rng = StdRng::seed_from_u64(t as u64);
+                let mut own = Vec::new();
+                let mut removed_shared = 0;
+                for i in 0..PER_THREAD {
+                    let value = 1000 + t * PER_THREAD + i;
+                    match rng.gen_range(0, 4) {
+                        0 => own.push(list.push_back(value)),
+                        1 => own.push(list.push_front(value)),
+                        // 他のスレッドが消したかもしれない位置への挿入は失敗してよい
+                        2 => {
+                            let pos = shared[rng.gen_range(0, shared.len())];
+                            match list.insert(pos, value) {
+                                Some(ptr) => own.push(ptr),
+                                None => own.push(list.push_back(value)),
+                            }
+                        }
+                        _ => {
+                            if rng.gen() && !own.is_empty() {
+                                let ptr = own.swap_remove(rng.gen_range(0, own.len()));
+                                assert!(list.remove(ptr).is_some());
+                            } else if list
+                                .remove(shared[rng.gen_range(0, shared.len())])
+                                .is_some()
+                            {
+                                removed_shared += 1;
+                            }
+                        }
+                    }
+                }
+                (own, removed_shared)
+            })
+        })
+        .collect::<Vec<_>>();
+    let readers = (0..THREADS / 2)
+        .map(|_| {
+            let list = list.clone();
+            thread::spawn(move || {
+                for _ in 0..PER_THREAD / 10 {
+                    // 読み取りロックの中では前後どちらから辿っても同じ列が見える
+                    list.read(|list| {
+                        let forward = list.iter().copied().collect::<Vec<_>>();
+                        let mut backward = list.iter().rev().copied().collect::<Vec<_>>();
+                        backward.reverse();
+                        assert_eq!(forward, backward);
+                        assert_eq!(forward.len(), list.len());
+                    });
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    let mut expected = Vec::new();
+    let mut removed_shared = 0;
+    for writer in writers {
+        let (own, removed) = writer.join().unwrap();
+        for ptr in own {
+            expected.push(list.with(ptr, |&v| v).expect("own node was removed"));
+        }
+        removed_shared += removed;
+    }
+    // 共有のノードはどれか一つのスレッドだけが削除できる
+    let remaining_shared = shared.iter().filter(|&&ptr| list.contains(ptr)).count();
+    assert_eq!(remaining_shared + removed_shared, shared.len());
+    expected.extend(shared.iter().filter_map(|&ptr| list.with(ptr, |&v| v)));
+    expected.sort();
+    let mut actual = list.to_vec();
+    actual.sort();
+    assert_eq!(actual, expected);
+    list.validate().assert_ok();
+}
@@ -0,0 +1,502 @@
+#![cfg(test)]
+// test04 の片方向リスト（head / tail を生ポインタで持つ）を元にした Michael–Scott の lock-free キュー。
+// 外したノードをすぐに解放すると、他のスレッドがまだ読んでいる最中かもしれない。
+// そこで hazard pointer で「今読んでいるノード」を公開し、誰も公開していないノードだけを解放する。
+pub mod hazard {
+    use std::cell::UnsafeCell;
+    use std::collections::HashSet;
+    use std::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+    pub const HAZARDS: usize = 2; // 一つのスレッドが同時に守れるポインタの数
+    const SCAN_THRESHOLD: usize = 64; // retire したものがこれだけ溜まったら解放を試みる
+
+    struct Retired {
+        ptr: *mut u8,
+        free: unsafe fn(*mut u8),
+    }
+
+    unsafe fn free_box<T>(ptr: *mut u8) {
+        drop(Box::from_raw(ptr as *mut T))
+    }
+
+    // スレッド（正確には Guard）一つ分の枠。一度作った枠は Domain が drop されるまで使い回す
+    struct Record {
+        hazards: [AtomicPtr<u8>; HAZARDS],
+        active: AtomicBool,
+        next: *mut Record,
+        retired: UnsafeCell<Vec<Retired>>, // active にした Guard だけが触る
+        retired_len: AtomicUsize,          // retired の長さ。他のスレッドからはこちらを読む
+    }
+
+    pub struct Domain {
+        records: AtomicPtr<Record>,
+    }
+    // Record の retired は active を取れた一つの Guard だけが触るので、Domain は共有してよい
+    unsafe impl Send for Domain {}
+    unsafe impl Sync for Domain {}
+
+    impl Domain {
+        pub fn new() -> Self {
+            Self {
+                records: AtomicPtr::new(std::ptr::null_mut()),
+            }
+        }
+        fn records(&self) -> impl Iterator<Item = &Record> {
+            let mut p = self.records.load(Ordering::Acquire);
+            std::iter::from_fn(move || {
+                let record = unsafe { p.as_ref()? };
+                p = record.next;
+                Some(record)
+            })
+        }
+        // 空いている枠を借りる。無ければ新しく作って先頭に繋ぐ
+        pub fn acquire(&self) -> Guard<'_> {
+            for record in self.records() {
+                if !record.active.load(Ordering::Relaxed)
+                    && record
+                        .active
+                        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok()
+                {
+                    return Guard {
+                        domain: self,
+                        record,
+                    };
+                }
+            }
+            let record = Box::into_raw(Box::new(Record {
+                hazards: Default::default(),
+                active: AtomicBool::new(true),
+                next: std::ptr::null_mut(),
+                retired: UnsafeCell::new(Vec::new()),
+                retired_len: AtomicUsize::new(0),
+            }));
+            loop {
+                let head = self.records.load(Ordering::Acquire);
+                unsafe { (*record).next = head };
+                if self
+                    .records
+                    .compare_exchange(head, record, Ordering::Release, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    break;
+                }
+            }
+            Guard {
+                domain: self,
+                record: unsafe { &*record },
+            }
+        }
+        // retire されたがまだ解放されていないものの数。他のスレッドが操作中なら目安にしかならない
+        pub fn retired(&self) -> usize {
+            self.records()
+                .map(|record| record.retired_len.load(Ordering::Relaxed))
+                .sum()
+        }
+    }
+    impl Drop for Domain {
+        // &mut self なのでどの Guard も残っておらず、retire されたものは全て解放できる
+        fn drop(&mut self) {
+            let mut p = *self.records.get_mut();
+            while !p.is_null() {
+                let record = unsafe { Box::from_raw(p) };
+                for retired in record.retired.into_inner() {
+                    unsafe { (retired.free)(retired.ptr) }
+                }
+                p = record.next;
+            }
+        }
+    }
+
+    pub struct Guard<'a> {
+        domain: &'a Domain,
+        record: &'a Record,
+    }
+    impl<'a> Guard<'a> {
+        // src から読んだポインタを i 番目の hazard として公開して返す。
+        // 公開した後に src がまだ同じ値なら、そのポインタは retire されていないので解放されない
+        pub fn protect<T>(&self, i: usize, src: &AtomicPtr<T>) -> *mut T {
+            let mut ptr = src.load(Ordering::Acquire);
+            loop {
+                self.record.hazards[i].store(ptr as *mut u8, Ordering::SeqCst);
+                let current = src.load(Ordering::SeqCst);
+                if current == ptr {
+                    return ptr;
+                }
+                ptr = current;
+            }
+        }
+        pub fn clear(&self) {
+            for hazard in &self.record.hazards {
+                hazard.store(std::ptr::null_mut(), Ordering::Release);
+            }
+        }
+        // ptr はもう共有の構造から外れていて、Box::into_raw で作ったものであること
+        pub unsafe fn retire<T>(&self, ptr: *mut T) {
+            let retired = &mut *self.record.retired.get();
+            retired.push(Retired {
+                ptr: ptr as *mut u8,
+                free: free_box::<T>,
+            });
+            self.record
+                .retired_len
+                .store(retired.len(), Ordering::Relaxed);
+            if retired.len() >= SCAN_THRESHOLD {
+                self.scan();
+            }
+        }
+        // どの枠からも公開されていない retire 済みのポインタを解放する
+        pub fn scan(&self) {
+            atomic::fence(Ordering::SeqCst);
+            let protected = self
+                .domain
+                .records()
+                .flat_map(|record| record.hazards.iter())
+                .map(|hazard| hazard.load(Ordering::SeqCst))
+                .filter(|ptr| !ptr.is_null())
+                .collect::<HashSet<_>>();
+            let retired = unsafe { &mut *self.record.retired.get() };
+            let (keep, free): (Vec<_>, Vec<_>) = retired
+                .drain(..)
+                .partition(|retired| protected.contains(&retired.ptr));
+            *retired = keep;
+            self.record
+                .retired_len
+                .store(retired.len(), Ordering::Relaxed);
+            for retired in free {
+                unsafe { (retired.free)(retired.ptr) }
+            }
+        }
+    }
+    impl<'a> Drop for Guard<'a> {
+        fn drop(&mut self) {
+            self.clear();
+            self.record.active.store(false, Ordering::Release);
+        }
+    }
+}
+
+#[test]
+fn test_hazard() {
+    use hazard::Domain;
+    use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    struct Counted;
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let domain = Domain::new();
+    let ptr = Box::into_raw(Box::new(Counted));
+    let shared = AtomicPtr::new(ptr);
+    let reader = domain.acquire();
+    assert_eq!(reader.protect(0, &shared), ptr);
+
+    // 別の Guard は別の枠を使う
+    let writer = domain.acquire();
+    shared.store(std::ptr::null_mut(), Ordering::SeqCst);
+    unsafe { writer.retire(ptr) };
+    writer.scan();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+    assert_eq!(domain.retired(), 1);
+
+    reader.clear();
+    writer.scan();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+    assert_eq!(domain.retired(), 0);
+
+    // 返した枠は次の acquire で使い回され、残った retire 済みのものは Domain と一緒に解放される
+    drop(reader);
+    drop(writer);
+    let guard = domain.acquire();
+    unsafe { guard.retire(Box::into_raw(Box::new(Counted))) };
+    drop(guard);
+    assert_eq!(domain.retired(), 1);
+    drop(domain);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+}
+
+pub mod queue {
+    use super::hazard::Domain;
+    use std::cell::UnsafeCell;
+    use std::mem::MaybeUninit;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+
+    // 先頭のノードは値を持たないダミー。pop で次のノードから値を取り出し、そのノードが新しいダミーになる
+    struct Node<T> {
+        value: UnsafeCell<MaybeUninit<T>>,
+        next: AtomicPtr<Node<T>>,
+    }
+    impl<T> Node<T> {
+        fn alloc(value: MaybeUninit<T>) -> *mut Self {
+            Box::into_raw(Box::new(Self {
+                value: UnsafeCell::new(value),
+                next: AtomicPtr::new(std::ptr::null_mut()),
+            }))
+        }
+    }
+
+    pub struct Queue<T> {
+        head: AtomicPtr<Node<T>>,
+        tail: AtomicPtr<Node<T>>, // 最後のノードか、その一つ手前を指す
+        domain: Domain,
+    }
+    // 値は push したスレッドから pop したスレッドへ移るだけで、共有はされない
+    unsafe impl<T: Send> Send for Queue<T> {}
+    unsafe impl<T: Send> Sync for Queue<T> {}
+
+    impl<T> Queue<T> {
+        pub fn new() -> Self {
+            let dummy = Node::alloc(MaybeUninit::uninit());
+            Self {
+                head: AtomicPtr::new(dummy),
+                tail: AtomicPtr::new(dummy),
+                domain: Domain::new(),
+            }
+        }
+        pub fn push_back(&self, value: T) {
+            let node = Node::alloc(MaybeUninit::new(value));
+            let guard = self.domain.acquire();
+            loop {
+                let tail = guard.protect(0, &self.tail);
+                let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+                if tail != self.tail.load(Ordering::Acquire) {
+                    continue;
+                }
+                if next.is_null() {
+                    let linked = unsafe { &(*tail).next }
+                        .compare_exchange(next, node, Ordering::Release, Ordering::Relaxed)
+                        .is_ok();
+                    if linked {
+                        // 失敗しても、他のスレッドが tail を進めてくれている
+                        let _ = self.tail.compare_exchange(
+                            tail,
+                            node,
+                            Ordering::Release,
+                            Ordering::Relaxed,
+                        );
+                        return;
+                    }
+                } else {
+                    // 他のスレッドが繋いだまま tail を進めていないので手伝う
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        next,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+                }
+            }
+        }
+        pub fn pop_front(&self) -> Option<T> {
+            let guard = self.domain.acquire();
+            loop {
+                let head = guard.protect(0, &self.head);
+                let next = guard.protect(1, unsafe { &(*head).next });
+                // head がまだ先頭なら next も retire されていない
+                if head != self.head.load(Ordering::Acquire) {
+                    continue;
+                }
+                if next.is_null() {
+                    return None;
+                }
+                let tail = self.tail.load(Ordering::Acquire);
+                if head == tail {
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        next,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    );
+                    continue;
+                }
+                if self
+                    .head
+                    .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    // 値を読めるのは head を進めたこのスレッドだけ
+                    let value = unsafe { (*(*next).value.get()).as_ptr().read() };
+                    guard.clear();
+                    unsafe { guard.retire(head) };
+                    return Some(value);
+                }
+            }
+        }
+        // 他のスレッドが同時に push / pop していれば、返した時には変わっているかもしれない
+        pub fn is_empty(&self) -> bool {
+            let guard = self.domain.acquire();
+            let head = guard.protect(0, &self.head);
+            unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+        }
+    }
+    impl<T> Default for Queue<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+    impl<T> Drop for Queue<T> {
+        fn drop(&mut self) {
+            // &mut self なので他のスレッドはいない。ダミーの次から残っている値を drop する
+            let head = *self.head.get_mut();
+            let mut node = unsafe { Box::from_raw(head) };
+            loop {
+                let next = *node.next.get_mut();
+                if next.is_null() {
+                    break;
+                }
+                node = unsafe { Box::from_raw(next) };
+                unsafe { node.value.get_mut().as_mut_ptr().drop_in_place() };
+            }
+        }
+    }
+}
+
+use queue::Queue;
+
+#[cfg(test)]
+const THREADS: usize = if cfg!(miri) { 3 } else { 8 };
+#[cfg(test)]
+const PER_THREAD: usize = if cfg!(miri) { 30 } else { 10000 };
+
+#[test]
+fn test_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Queue<usize>>();
+    assert_send_sync::<Queue<Box<String>>>();
+    assert_send_sync::<hazard::Domain>();
+}
+
+#[test]
+fn test_queue() {
+    let queue = Queue::new();
+    assert!(queue.is_empty());
+    assert!(queue.pop_front().is_none());
+    for i in 0..200 {
+        queue.push_back(i);
+    }
+    assert!(!queue.is_empty());
+    for i in 0..100 {
+        assert_eq!(queue.pop_front(), Some(i));
+    }
+    queue.push_back(200);
+    assert_eq!(
+        std::iter::from_fn(|| queue.pop_front()).collect::<Vec<_>>(),
+        (100..=200).collect::<Vec<_>>()
+    );
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn test_drop() {
+    use std::rc::Rc;
+    let value = Rc::new(0);
+    let queue = Queue::new();
+    for _ in 0..100 {
+        queue.push_back(value.clone());
+    }
+    for _ in 0..70 {
+        queue.pop_front();
+    }
+    assert_eq!(Rc::strong_count(&value), 31);
+    drop(queue);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+// 複数の producer と consumer で、push した値がちょうど一回ずつ pop されることを確かめる
+#[test]
+fn test_mpmc() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    let queue = Arc::new(Queue::new());
+    let popped = Arc::new(AtomicUsize::new(0));
+    let producers = (0..THREADS)
+        .map(|t| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for i in 0..PER_THREAD {
+                    queue.push_back(t * PER_THREAD + i);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let consumers = (0..THREADS)
+        .map(|_| {
+            let queue = queue.clone();
+            let popped = popped.clone();
+            thread::spawn(move || {
+                let mut received = Vec::new();
+                while popped.load(Ordering::SeqCst) < THREADS * PER_THREAD {
+                    match queue.pop_front() {
+                        Some(value) => {
+                            received.push(value);
+                            popped.fetch_add(1, Ordering::SeqCst);
+                        }
+                        None => thread::yield_now(),
+                    }
+                }
+                received
+            })
+        })
+        .collect::<Vec<_>>();
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    let mut seen = vec![0; THREADS * PER_THREAD];
+    for consumer in consumers {
+        let received = consumer.join().unwrap();
+        // 一つの consumer から見ると、同じ producer の値は push した順に並んでいる
+        for t in 0..THREADS {
+            let from_t = received.iter().filter(|&&v| v / PER_THREAD == t);
+            assert!(from_t.clone().zip(from_t.skip(1)).all(|(a, b)| a < b));
+        }
+        for value in received {
+            seen[value] += 1;
+        }
+    }
+    assert!(seen.iter().all(|&count| count == 1));
+    assert!(queue.is_empty());
+}
+
+// 値を持つ型で、pop されずに残った値と pop された値の drop が合わせてちょうど一回ずつになることを確かめる
+#[test]
+fn test_mpmc_drop() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    struct Counted(Arc<AtomicUsize>);
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let queue = Arc::new(Queue::new());
+    let threads = (0..THREADS)
+        .map(|t| {
+            let queue = queue.clone();
+            let dropped = dropped.clone();
+            thread::spawn(move || {
+                for i in 0..PER_THREAD {
+                    queue.push_back(Counted(dropped.clone()));
+                    if (t + i) % 3 != 0 {
+                        queue.pop_front();
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let queue = Arc::try_unwrap(queue).ok().unwrap();
+    drop(queue);
+    assert_eq!(dropped.load(Ordering::SeqCst), THREADS * PER_THREAD);
+}
@@ -0,0 +1,1228 @@
+// 変更するたびに新しい版を返し、変わらなかったノードは古い版と Rc で共有する List と Graph。
+// 前後を両方向に繋ぐと一箇所の変更がリスト全体に伝わって共有できないので、List は並び順を表すキーの
+// treap にして、根から変更したノードまでの道だけを作り直す（path copying）。
+// ノードのアドレスは版ごとに変わるので、Ptr の代わりに版をまたいで同じ要素を指す Id を渡す。
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+lazy_static! {
+    // 別の版や別の List で同じ Id を使わないように、全体で一つの番号を振る
+    static ref NEXT_ID: AtomicU64 = AtomicU64::new(1);
+}
+
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+pub mod tree {
+    use crate::validate::{Report, Violation};
+    use std::cmp::Ordering;
+    use std::collections::HashSet;
+    use std::rc::Rc;
+
+    #[derive(Clone)]
+    pub struct Node<K, V> {
+        key: K,
+        value: V,
+        priority: u64,
+        size: usize, // このノードを根とする部分木のノード数
+        left: Tree<K, V>,
+        right: Tree<K, V>,
+    }
+
+    // Id から優先度を決める（splitmix64）。同じ要素はどの版でも同じ優先度になる
+    pub fn priority(id: u64) -> u64 {
+        let mut z = id.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // キーの順に並べ、親の優先度は子以上にする。空の木は None
+    pub struct Tree<K, V>(Option<Rc<Node<K, V>>>);
+    impl<K, V> Clone for Tree<K, V> {
+        fn clone(&self) -> Self {
+            Tree(self.0.clone())
+        }
+    }
+    impl<K, V> Default for Tree<K, V> {
+        fn default() -> Self {
+            Tree(None)
+        }
+    }
+
+    fn node<K, V>(
+        key: K,
+        value: V,
+        priority: u64,
+        left: Tree<K, V>,
+        right: Tree<K, V>,
+    ) -> Tree<K, V> {
+        let size = left.len() + right.len() + 1;
+        Tree(Some(Rc::new(Node {
+            key,
+            value,
+            priority,
+            size,
+            left,
+            right,
+        })))
+    }
+
+    fn push_left<'a, K, V>(stack: &mut Vec<&'a Node<K, V>>, mut tree: &'a Tree<K, V>) {
+        while let Some(node) = &tree.0 {
+            stack.push(node);
+            tree = &node.left;
+        }
+    }
+    fn push_right<'a, K, V>(stack: &mut Vec<&'a Node<K, V>>, mut tree: &'a Tree<K, V>) {
+        while let Some(node) = &tree.0 {
+            stack.push(node);
+            tree = &node.right;
+        }
+    }
+
+    impl<K, V> Tree<K, V> {
+        // Rc の参照カウントを含めたノード一つの大きさ
+        pub const NODE_BYTES: usize =
+            std::mem::size_of::<Node<K, V>>() + 2 * std::mem::size_of::<usize>();
+
+        pub fn new() -> Self {
+            Tree(None)
+        }
+        pub fn len(&self) -> usize {
+            self.0.as_ref().map_or(0, |node| node.size)
+        }
+        pub fn is_empty(&self) -> bool {
+            self.0.is_none()
+        }
+        pub fn nth(&self, mut i: usize) -> Option<(&K, &V)> {
+            let mut tree = self;
+            while let Some(node) = &tree.0 {
+                let left = node.left.len();
+                match i.cmp(&left) {
+                    Ordering::Less => tree = &node.left,
+                    Ordering::Equal => return Some((&node.key, &node.value)),
+                    Ordering::Greater => {
+                        i -= left + 1;
+                        tree = &node.right;
+                    }
+                }
+            }
+            None
+        }
+        pub fn first(&self) -> Option<(&K, &V)> {
+            self.nth(0)
+        }
+        pub fn last(&self) -> Option<(&K, &V)> {
+            self.nth(self.len().checked_sub(1)?)
+        }
+        pub fn iter(&self) -> Iter<'_, K, V> {
+            let mut iter = Iter {
+                front: Vec::new(),
+                back: Vec::new(),
+                len: self.len(),
+            };
+            push_left(&mut iter.front, self);
+            push_right(&mut iter.back, self);
+            iter
+        }
+        // other と共有しているノードの数。共有している部分木の中は辿らない
+        pub fn shared_with(&self, other: &Self) -> usize {
+            let mut others = HashSet::new();
+            let mut stack = vec![other];
+            while let Some(tree) = stack.pop() {
+                if let Some(node) = &tree.0 {
+                    others.insert(Rc::as_ptr(node));
+                    stack.push(&node.left);
+                    stack.push(&node.right);
+                }
+            }
+            let mut shared = 0;
+            let mut stack = vec![self];
+            while let Some(tree) = stack.pop() {
+                if let Some(node) = &tree.0 {
+                    if others.contains(&Rc::as_ptr(node)) {
+                        shared += node.size;
+                    } else {
+                        stack.push(&node.left);
+                        stack.push(&node.right);
+                    }
+                }
+            }
+            shared
+        }
+        // 部分木の大きさと優先度を検査して実際のノード数を返す。offset は部分木の先頭の位置
+        fn check(&self, report: &mut Report, offset: usize) -> usize {
+            let node = match &self.0 {
+                Some(node) => node,
+                None => return 0,
+            };
+            let left = node.left.check(report, offset);
+            let at = offset + left;
+            let right = node.right.check(report, at + 1);
+            for child in [&node.left, &node.right].iter() {
+                if child.0.as_ref().is_some_and(|c| c.priority > node.priority) {
+                    report.push(Violation::OutOfOrder {
+                        at,
+                        what: "priority",
+                    });
+                }
+            }
+            if node.size != left + right + 1 {
+                report.push(Violation::CountMismatch {
+                    what: "size",
+                    recorded: node.size,
+                    actual: left + right + 1,
+                });
+            }
+            left + right + 1
+        }
+    }
+
+    impl<K: Ord, V> Tree<K, V> {
+        pub fn get(&self, key: &K) -> Option<&V> {
+            let mut tree = self;
+            while let Some(node) = &tree.0 {
+                match key.cmp(&node.key) {
+                    Ordering::Less => tree = &node.left,
+                    Ordering::Equal => return Some(&node.value),
+                    Ordering::Greater => tree = &node.right,
+                }
+            }
+            None
+        }
+        // key より小さいキーの数
+        pub fn rank(&self, key: &K) -> usize {
+            let mut tree = self;
+            let mut rank = 0;
+            while let Some(node) = &tree.0 {
+                if node.key < *key {
+                    rank += node.left.len() + 1;
+                    tree = &node.right;
+                } else {
+                    tree = &node.left;
+                }
+            }
+            rank
+        }
+        // key より大きい最小のキー
+        pub fn after(&self, key: &K) -> Option<(&K, &V)> {
+            let mut tree = self;
+            let mut found = None;
+            while let Some(node) = &tree.0 {
+                if node.key > *key {
+                    found = Some((&node.key, &node.value));
+                    tree = &node.left;
+                } else {
+                    tree = &node.right;
+                }
+            }
+            found
+        }
+        // key より小さい最大のキー
+        pub fn before(&self, key: &K) -> Option<(&K, &V)> {
+            let mut tree = self;
+            let mut found = None;
+            while let Some(node) = &tree.0 {
+                if node.key < *key {
+                    found = Some((&node.key, &node.value));
+                    tree = &node.right;
+                } else {
+                    tree = &node.left;
+                }
+            }
+            found
+        }
+        pub fn validate(&self) -> Report {
+            let mut report = Report::default();
+            self.check(&mut report, 0);
+            for (at, ((a, _), (b, _))) in self.iter().zip(self.iter().skip(1)).enumerate() {
+                if a >= b {
+                    report.push(Violation::OutOfOrder {
+                        at: at + 1,
+                        what: "key",
+                    });
+                }
+            }
+            report
+        }
+    }
+
+    // 以下は新しい木を返し、self は変わらない
+    impl<K: Ord + Clone, V: Clone> Tree<K, V> {
+        // key が既にあれば値を置き換える
+        pub fn insert(&self, key: K, value: V, priority: u64) -> Self {
+            let node_ref = match &self.0 {
+                Some(node) => node,
+                None => return node(key, value, priority, Tree::new(), Tree::new()),
+            };
+            match key.cmp(&node_ref.key) {
+                Ordering::Equal => node(
+                    key,
+                    value,
+                    node_ref.priority,
+                    node_ref.left.clone(),
+                    node_ref.right.clone(),
+                ),
+                _ if priority > node_ref.priority => {
+                    let (left, right) = self.split(&key);
+                    node(key, value, priority, left, right)
+                }
+                Ordering::Less => node(
+                    node_ref.key.clone(),
+                    node_ref.value.clone(),
+                    node_ref.priority,
+                    node_ref.left.insert(key, value, priority),
+                    node_ref.right.clone(),
+                ),
+                Ordering::Greater => node(
+                    node_ref.key.clone(),
+                    node_ref.value.clone(),
+                    node_ref.priority,
+                    node_ref.left.clone(),
+                    node_ref.right.insert(key, value, priority),
+                ),
+            }
+        }
+        // key が無ければ None
+        pub fn remove(&self, key: &K) -> Option<Self> {
+            let node_ref = self.0.as_ref()?;
+            Some(match key.cmp(&node_ref.key) {
+                Ordering::Equal => Self::merge(&node_ref.left, &node_ref.right),
+                Ordering::Less => node(
+                    node_ref.key.clone(),
+                    node_ref.value.clone(),
+                    node_ref.priority,
+                    node_ref.left.remove(key)?,
+                    node_ref.right.clone(),
+                ),
+                Ordering::Greater => node(
+                    node_ref.key.clone(),
+                    node_ref.value.clone(),
+                    node_ref.priority,
+                    node_ref.left.clone(),
+                    node_ref.right.remove(key)?,
+                ),
+            })
+        }
+        // その場で書き換える。他の版と共有しているノードは複製してから書き換えるので、他の版は変わらない
+        pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+            self.get(key)?;
+            self.make_mut(key)
+        }
+        fn make_mut(&mut self, key: &K) -> Option<&mut V> {
+            let node = Rc::make_mut(self.0.as_mut()?);
+            match key.cmp(&node.key) {
+                Ordering::Less => node.left.make_mut(key),
+                Ordering::Equal => Some(&mut node.value),
+                Ordering::Greater => node.right.make_mut(key),
+            }
+        }
+        // key より小さいキーの木と大きいキーの木に分ける。key と等しいノードは捨てる
+        fn split(&self, key: &K) -> (Self, Self) {
+            let node_ref = match &self.0 {
+                Some(node) => node,
+                None => return (Tree::new(), Tree::new()),
+            };
+            match node_ref.key.cmp(key) {
+                Ordering::Less => {
+                    let (left, right) = node_ref.right.split(key);
+                    let left = node(
+                        node_ref.key.clone(),
+                        node_ref.value.clone(),
+                        node_ref.priority,
+                        node_ref.left.clone(),
+                        left,
+                    );
+                    (left, right)
+                }
+                Ordering::Greater => {
+                    let (left, right) = node_ref.left.split(key);
+                    let right = node(
+                        node_ref.key.clone(),
+                        node_ref.value.clone(),
+                        node_ref.priority,
+                        right,
+                        node_ref.right.clone(),
+                    );
+                    (left, right)
+                }
+                Ordering::Equal => (node_ref.left.clone(), node_ref.right.clone()),
+            }
+        }
+        // left のキーは全て right のキーより小さいこと
+        fn merge(left: &Self, right: &Self) -> Self {
+            match (&left.0, &right.0) {
+                (None, _) => right.clone(),
+                (_, None) => left.clone(),
+                (Some(l), Some(r)) if l.priority > r.priority => node(
+                    l.key.clone(),
+                    l.value.clone(),
+                    l.priority,
+                    l.left.clone(),
+                    Self::merge(&l.right, right),
+                ),
+                (Some(_), Some(r)) => node(
+                    r.key.clone(),
+                    r.value.clone(),
+                    r.priority,
+                    Self::merge(left, &r.left),
+                    r.right.clone(),
+                ),
+            }
+        }
+    }
+
+    // front と back から辿り、len が 0 になったら両側が出会ったとみなす
+    pub struct Iter<'a, K, V> {
+        front: Vec<&'a Node<K, V>>,
+        back: Vec<&'a Node<K, V>>,
+        len: usize,
+    }
+    impl<'a, K, V> Iterator for Iter<'a, K, V> {
+        type Item = (&'a K, &'a V);
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            let node = self.front.pop()?;
+            push_left(&mut self.front, &node.right);
+            self.len -= 1;
+            Some((&node.key, &node.value))
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
+    }
+    impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+            let node = self.back.pop()?;
+            push_right(&mut self.back, &node.left);
+            self.len -= 1;
+            Some((&node.key, &node.value))
+        }
+    }
+    impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+}
+
+pub mod list {
+    use super::tree::{self, Tree};
+    use crate::linked_list::LinkedList;
+    use crate::memory::Usage;
+    use crate::validate::{Report, Violation};
+    use std::iter::FromIterator;
+    use std::rc::Rc;
+
+    // 並び順を表すキー。2^32 進の小数 0.d0 d1 d2 ... を桁の列で表し、最後の桁は 0 にしない。
+    // そうしておくとどの二つのキーの間にも新しいキーを作れるので、挿入しても他のキーを振り直さなくてよい
+    #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+    struct Key(Rc<[u32]>);
+    impl Key {
+        // lo と hi の間のキー。None はそちら側に制限がないことを表す。
+        // 片側に制限がなければ STEP だけ離すので、push_back / push_front を続けても桁が増えにくい
+        fn between(lo: Option<&Key>, hi: Option<&Key>) -> Key {
+            const BASE: u64 = 1 << 32;
+            const STEP: u64 = 1 << 16;
+            let lo_digits: &[u32] = lo.map_or(&[], |key| &key.0);
+            let mut hi_digits: Option<&[u32]> = hi.map(|key| &key.0[..]);
+            let mut digits = Vec::new();
+            for i in 0.. {
+                let l = lo_digits.get(i).map_or(0, |&d| d as u64);
+                let h = hi_digits.map_or(BASE, |hi| hi.get(i).map_or(0, |&d| d as u64));
+                debug_assert!(l <= h);
+                if h - l >= 2 {
+                    let d = if h - l > 2 * STEP && lo.is_some() && hi_digits.is_none() {
+                        l + STEP
+                    } else if h - l > 2 * STEP && lo.is_none() && hi_digits.is_some() {
+                        h - STEP
+                    } else {
+                        l + (h - l) / 2
+                    };
+                    digits.push(d as u32);
+                    break;
+                }
+                digits.push(l as u32);
+                // ここで hi より小さくなったので、次の桁からは上に制限がない
+                if h - l == 1 {
+                    hi_digits = None;
+                }
+            }
+            Key(digits.into())
+        }
+    }
+
+    // 一度使った Id は別の版でも別の List でも使わないので、削除した要素の Id が他の要素を指すことはない
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+    pub struct Id(u64);
+
+    struct Entry<T> {
+        id: Id,
+        value: Rc<T>,
+    }
+    impl<T> Clone for Entry<T> {
+        fn clone(&self) -> Self {
+            Self {
+                id: self.id,
+                value: self.value.clone(),
+            }
+        }
+    }
+
+    // order は要素を並び順に持ち、index は Id から order のキーを引く
+    pub struct List<T> {
+        order: Tree<Key, Entry<T>>,
+        index: Tree<Id, Key>,
+    }
+    // 版の複製は Rc を二つ増やすだけ
+    impl<T> Clone for List<T> {
+        fn clone(&self) -> Self {
+            Self {
+                order: self.order.clone(),
+                index: self.index.clone(),
+            }
+        }
+    }
+    impl<T> List<T> {
+        pub fn new() -> Self {
+            Self {
+                order: Tree::new(),
+                index: Tree::new(),
+            }
+        }
+        pub fn len(&self) -> usize {
+            self.order.len()
+        }
+        pub fn is_empty(&self) -> bool {
+            self.order.is_empty()
+        }
+        fn key(&self, id: Id) -> Option<&Key> {
+            self.index.get(&id)
+        }
+        pub fn head(&self) -> Option<Id> {
+            self.order.first().map(|(_, entry)| entry.id)
+        }
+        pub fn tail(&self) -> Option<Id> {
+            self.order.last().map(|(_, entry)| entry.id)
+        }
+        pub fn next(&self, id: Id) -> Option<Id> {
+            self.order.after(self.key(id)?).map(|(_, entry)| entry.id)
+        }
+        pub fn prev(&self, id: Id) -> Option<Id> {
+            self.order.before(self.key(id)?).map(|(_, entry)| entry.id)
+        }
+        pub fn nth(&self, i: usize) -> Option<Id> {
+            self.order.nth(i).map(|(_, entry)| entry.id)
+        }
+        pub fn nth_back(&self, i: usize) -> Option<Id> {
+            self.nth(self.len().checked_sub(i + 1)?)
+        }
+        pub fn position(&self, id: Id) -> Option<usize> {
+            Some(self.order.rank(self.key(id)?))
+        }
+        pub fn contains(&self, id: Id) -> bool {
+            self.key(id).is_some()
+        }
+        pub fn get(&self, id: Id) -> Option<&T> {
+            self.get_rc(id).map(|value| &**value)
+        }
+        fn get_rc(&self, id: Id) -> Option<&Rc<T>> {
+            self.order.get(self.key(id)?).map(|entry| &entry.value)
+        }
+        pub fn iter(&self) -> Iter<'_, T> {
+            Iter(self.order.iter())
+        }
+        // other と共有している（order と index の）ノードの数
+        pub fn shared_with(&self, other: &Self) -> usize {
+            self.order.shared_with(&other.order) + self.index.shared_with(&other.index)
+        }
+        pub fn node_count(&self) -> usize {
+            self.order.len() + self.index.len()
+        }
+        // 二つの木をそれぞれ検査し、index が order の全ての要素を正しく指しているか確かめる
+        pub fn validate(&self) -> Report {
+            let mut report = self.order.validate();
+            report.violations.extend(self.index.validate().violations);
+            if self.index.len() != self.order.len() {
+                report.push(Violation::CountMismatch {
+                    what: "index",
+                    recorded: self.index.len(),
+                    actual: self.order.len(),
+                });
+            }
+            for (at, (key, entry)) in self.order.iter().enumerate() {
+                if self.index.get(&entry.id) != Some(key) {
+                    report.push(Violation::BrokenLink { at, link: "index" });
+                }
+            }
+            report
+        }
+        // ノードと値の大きさから見積もる。他の版と共有している分も含める
+        pub fn memory(&self) -> Usage {
+            let keys = self
+                .order
+                .iter()
+                .map(|(key, _)| std::mem::size_of_val(&*key.0) + 2 * std::mem::size_of::<usize>())
+                .sum::<usize>();
+            let values = self.len() * (std::mem::size_of::<T>() + 2 * std::mem::size_of::<usize>());
+            let bytes = self.order.len() * Tree::<Key, Entry<T>>::NODE_BYTES
+                + self.index.len() * Tree::<Id, Key>::NODE_BYTES
+                + keys
+                + values;
+            Usage {
+                live: self.node_count(),
+                free: 0,
+                bytes: Some(bytes),
+                peak: Some(bytes),
+            }
+        }
+
+        // 以下は新しい版を返し、self は変わらない
+        fn insert_between(&self, prev: Option<&Key>, next: Option<&Key>, value: T) -> (Self, Id) {
+            let id = Id(super::next_id());
+            let key = Key::between(prev, next);
+            let priority = tree::priority(id.0);
+            let entry = Entry {
+                id,
+                value: Rc::new(value),
+            };
+            let list = Self {
+                order: self.order.insert(key.clone(), entry, priority),
+                index: self.index.insert(id, key, priority),
+            };
+            (list, id)
+        }
+        // pos の直前に挿入する
+        pub fn insert(&self, pos: Id, value: T) -> Option<(Self, Id)> {
+            let next = self.key(pos)?;
+            let prev = self.order.before(next).map(|(key, _)| key);
+            Some(self.insert_between(prev, Some(next), value))
+        }
+        pub fn push_back(&self, value: T) -> (Self, Id) {
+            let last = self.order.last().map(|(key, _)| key);
+            self.insert_between(last, None, value)
+        }
+        pub fn push_front(&self, value: T) -> (Self, Id) {
+            let first = self.order.first().map(|(key, _)| key);
+            self.insert_between(None, first, value)
+        }
+        pub fn remove(&self, id: Id) -> Option<Self> {
+            let key = self.key(id)?;
+            Some(Self {
+                order: self.order.remove(key)?,
+                index: self.index.remove(&id)?,
+            })
+        }
+        pub fn pop_front(&self) -> Option<Self> {
+            self.remove(self.head()?)
+        }
+        pub fn pop_back(&self) -> Option<Self> {
+            self.remove(self.tail()?)
+        }
+        // 値だけを置き換える。Id と位置は変わらず、index はそのまま共有する
+        pub fn set(&self, id: Id, value: T) -> Option<Self> {
+            let key = self.key(id)?.clone();
+            let entry = Entry {
+                id,
+                value: Rc::new(value),
+            };
+            Some(Self {
+                order: self.order.insert(key, entry, tree::priority(id.0)),
+                index: self.index.clone(),
+            })
+        }
+    }
+    impl<T> Default for List<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+    impl<T> FromIterator<T> for List<T> {
+        fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+            iter.into_iter()
+                .fold(List::new(), |list, value| list.push_back(value).0)
+        }
+    }
+
+    pub struct Iter<'a, T>(tree::Iter<'a, Key, Entry<T>>);
+    impl<'a, T> Iterator for Iter<'a, T> {
+        type Item = &'a T;
+        fn next(&mut self) -> Option<Self::Item> {
+            self.0.next().map(|(_, entry)| &*entry.value)
+        }
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.0.size_hint()
+        }
+    }
+    impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            self.0.next_back().map(|(_, entry)| &*entry.value)
+        }
+    }
+    impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+    // 変更する操作は新しい版で self を置き換える。値を書き換えるので T: Clone が要る
+    impl<T: Clone> LinkedList<T> for List<T> {
+        type Handle = Id;
+        type Iter<'a>
+            = Iter<'a, T>
+        where
+            T: 'a;
+        const NAME: &'static str = "persistent";
+        fn new() -> Self {
+            List::new()
+        }
+        fn len(&self) -> usize {
+            List::len(self)
+        }
+        fn iter(&self) -> Iter<'_, T> {
+            List::iter(self)
+        }
+        fn head(&self) -> Option<Id> {
+            List::head(self)
+        }
+        fn tail(&self) -> Option<Id> {
+            List::tail(self)
+        }
+        fn next(&self, h: Id) -> Option<Id> {
+            List::next(self, h)
+        }
+        fn prev(&self, h: Id) -> Option<Id> {
+            List::prev(self, h)
+        }
+        fn nth(&self, i: usize) -> Option<Id> {
+            List::nth(self, i)
+        }
+        fn position(&self, h: Id) -> Option<usize> {
+            List::position(self, h)
+        }
+        fn get(&self, h: Id) -> Option<&T> {
+            List::get(self, h)
+        }
+        fn get_mut(&mut self, h: Id) -> Option<&mut T> {
+            let key = self.key(h)?.clone();
+            let entry = self.order.get_mut(&key)?;
+            Some(Rc::make_mut(&mut entry.value))
+        }
+        fn insert(&mut self, h: Id, value: T) -> Option<Id> {
+            let (list, id) = List::insert(self, h, value)?;
+            *self = list;
+            Some(id)
+        }
+        fn push_back(&mut self, value: T) -> Id {
+            let (list, id) = List::push_back(self, value);
+            *self = list;
+            id
+        }
+        fn push_front(&mut self, value: T) -> Id {
+            let (list, id) = List::push_front(self, value);
+            *self = list;
+            id
+        }
+        // 他の版が値を共有していなければ複製せずに取り出す
+        fn remove(&mut self, h: Id) -> Option<T> {
+            let value = self.get_rc(h)?.clone();
+            *self = List::remove(self, h)?;
+            Some(Rc::try_unwrap(value).unwrap_or_else(|value| (*value).clone()))
+        }
+        fn validate(&self) -> Report {
+            List::validate(self)
+        }
+        fn memory(&self) -> Usage {
+            List::memory(self)
+        }
+    }
+}
+
+// graph は benchmark では使わない
+#[cfg(test)]
+pub mod graph {
+    use super::tree::{self, Tree};
+    use crate::validate::{Report, Violation};
+    use std::rc::Rc;
+
+    // edges / ends の添字。test08 と同じく OUT は始点から出ていく側、IN は終点に入ってくる側
+    const OUT: usize = 0;
+    const IN: usize = 1;
+
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+    pub struct NodeId(u64);
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+    pub struct EdgeId(u64);
+
+    // 繋がっている辺は Id の集合で持つ。辺を足しても端点のノードと集合の道だけを作り直す
+    struct Node<N> {
+        value: Rc<N>,
+        edges: [Tree<EdgeId, ()>; 2],
+    }
+    impl<N> Clone for Node<N> {
+        fn clone(&self) -> Self {
+            Self {
+                value: self.value.clone(),
+                edges: self.edges.clone(),
+            }
+        }
+    }
+
+    struct Edge<E> {
+        value: Rc<E>,
+        ends: [NodeId; 2],
+    }
+    impl<E> Clone for Edge<E> {
+        fn clone(&self) -> Self {
+            Self {
+                value: self.value.clone(),
+                ends: self.ends,
+            }
+        }
+    }
+
+    // 有向グラフ
+    pub struct Graph<N, E> {
+        nodes: Tree<NodeId, Node<N>>,
+        edges: Tree<EdgeId, Edge<E>>,
+    }
+    impl<N, E> Clone for Graph<N, E> {
+        fn clone(&self) -> Self {
+            Self {
+                nodes: self.nodes.clone(),
+                edges: self.edges.clone(),
+            }
+        }
+    }
+    impl<N, E> Graph<N, E> {
+        pub fn new() -> Self {
+            Self {
+                nodes: Tree::new(),
+                edges: Tree::new(),
+            }
+        }
+        pub fn node_count(&self) -> usize {
+            self.nodes.len()
+        }
+        pub fn edge_count(&self) -> usize {
+            self.edges.len()
+        }
+        pub fn node(&self, p: NodeId) -> Option<&N> {
+            self.nodes.get(&p).map(|node| &*node.value)
+        }
+        pub fn edge(&self, p: EdgeId) -> Option<&E> {
+            self.edges.get(&p).map(|edge| &*edge.value)
+        }
+        // (始点, 終点)
+        pub fn ends(&self, p: EdgeId) -> Option<(NodeId, NodeId)> {
+            self.edges
+                .get(&p)
+                .map(|edge| (edge.ends[OUT], edge.ends[IN]))
+        }
+        // 追加した順
+        pub fn nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+            self.nodes.iter().map(|(&p, _)| p)
+        }
+        fn edges_of(&self, p: NodeId, side: usize) -> impl Iterator<Item = EdgeId> + '_ {
+            self.nodes
+                .get(&p)
+                .into_iter()
+                .flat_map(move |node| node.edges[side].iter().map(|(&e, _)| e))
+        }
+        pub fn out_edges(&self, p: NodeId) -> impl Iterator<Item = EdgeId> + '_ {
+            self.edges_of(p, OUT)
+        }
+        pub fn in_edges(&self, p: NodeId) -> impl Iterator<Item = EdgeId> + '_ {
+            self.edges_of(p, IN)
+        }
+        // 出ていく辺の終点
+        pub fn neighbors(&self, p: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+            self.out_edges(p)
+                .map(move |e| self.edges.get(&e).unwrap().ends[IN])
+        }
+        pub fn find_edge(&self, from: NodeId, to: NodeId) -> Option<EdgeId> {
+            self.out_edges(from)
+                .find(|e| self.edges.get(e).unwrap().ends[IN] == to)
+        }
+        // other と共有している（ノード・辺・各ノードの辺の集合の）ノードの数
+        pub fn shared_with(&self, other: &Self) -> usize {
+            let adjacency = self
+                .nodes
+                .iter()
+                .filter_map(|(p, node)| Some((node, other.nodes.get(p)?)))
+                .map(|(node, other)| {
+                    node.edges[OUT].shared_with(&other.edges[OUT])
+                        + node.edges[IN].shared_with(&other.edges[IN])
+                })
+                .sum::<usize>();
+            self.nodes.shared_with(&other.nodes) + self.edges.shared_with(&other.edges) + adjacency
+        }
+        // 辺の端点が存在して、その辺を集合に持っているか。集合の辺がその端点を持っているか
+        pub fn validate(&self) -> Report {
+            let mut report = self.nodes.validate();
+            report.violations.extend(self.edges.validate().violations);
+            for (at, (p, edge)) in self.edges.iter().enumerate() {
+                for &(side, link) in [(OUT, "from.out_edges"), (IN, "to.in_edges")].iter() {
+                    match self.nodes.get(&edge.ends[side]) {
+                        None => report.push(Violation::InvalidPtr {
+                            at,
+                            link: if side == OUT { "from" } else { "to" },
+                            reason: "node does not exist".to_string(),
+                        }),
+                        Some(node) if node.edges[side].get(p).is_none() => {
+                            report.push(Violation::BrokenLink { at, link })
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+            for (at, (&p, node)) in self.nodes.iter().enumerate() {
+                for &(side, link) in [(OUT, "out_edges"), (IN, "in_edges")].iter() {
+                    report
+                        .violations
+                        .extend(node.edges[side].validate().violations);
+                    for (e, _) in node.edges[side].iter() {
+                        let reason = match self.edges.get(e) {
+                            None => "edge does not exist",
+                            Some(edge) if edge.ends[side] != p => "edge does not end at the node",
+                            Some(_) => continue,
+                        };
+                        report.push(Violation::InvalidPtr {
+                            at,
+                            link,
+                            reason: reason.to_string(),
+                        });
+                    }
+                }
+            }
+            report
+        }
+
+        // 以下は新しい版を返し、self は変わらない
+        fn link(
+            nodes: &Tree<NodeId, Node<N>>,
+            p: NodeId,
+            side: usize,
+            e: EdgeId,
+            linked: bool,
+        ) -> Tree<NodeId, Node<N>> {
+            let mut node = nodes.get(&p).unwrap().clone();
+            node.edges[side] = if linked {
+                node.edges[side].insert(e, (), tree::priority(e.0))
+            } else {
+                node.edges[side].remove(&e).unwrap()
+            };
+            nodes.insert(p, node, tree::priority(p.0))
+        }
+        pub fn add_node(&self, value: N) -> (Self, NodeId) {
+            let p = NodeId(super::next_id());
+            let node = Node {
+                value: Rc::new(value),
+                edges: [Tree::new(), Tree::new()],
+            };
+            let graph = Self {
+                nodes: self.nodes.insert(p, node, tree::priority(p.0)),
+                edges: self.edges.clone(),
+            };
+            (graph, p)
+        }
+        // 端点のどちらかが無ければ None。同じ端点の辺を何本でも、自己ループも追加できる
+        pub fn add_edge(&self, from: NodeId, to: NodeId, value: E) -> Option<(Self, EdgeId)> {
+            self.nodes.get(&from)?;
+            self.nodes.get(&to)?;
+            let e = EdgeId(super::next_id());
+            let edge = Edge {
+                value: Rc::new(value),
+                ends: [from, to],
+            };
+            let nodes = Self::link(&self.nodes, from, OUT, e, true);
+            let graph = Self {
+                nodes: Self::link(&nodes, to, IN, e, true),
+                edges: self.edges.insert(e, edge, tree::priority(e.0)),
+            };
+            Some((graph, e))
+        }
+        // test02 / test03 の reset_node に当たる。辺の集合はそのまま共有する
+        pub fn set_node(&self, p: NodeId, value: N) -> Option<Self> {
+            let node = Node {
+                value: Rc::new(value),
+                edges: self.nodes.get(&p)?.edges.clone(),
+            };
+            Some(Self {
+                nodes: self.nodes.insert(p, node, tree::priority(p.0)),
+                edges: self.edges.clone(),
+            })
+        }
+        pub fn set_edge(&self, p: EdgeId, value: E) -> Option<Self> {
+            let edge = Edge {
+                value: Rc::new(value),
+                ends: self.edges.get(&p)?.ends,
+            };
+            Some(Self {
+                nodes: self.nodes.clone(),
+                edges: self.edges.insert(p, edge, tree::priority(p.0)),
+            })
+        }
+        pub fn remove_edge(&self, p: EdgeId) -> Option<Self> {
+            let [from, to] = self.edges.get(&p)?.ends;
+            let nodes = Self::link(&self.nodes, from, OUT, p, false);
+            Some(Self {
+                nodes: Self::link(&nodes, to, IN, p, false),
+                edges: self.edges.remove(&p)?,
+            })
+        }
+        // 繋がっている辺も取り除く
+        pub fn remove_node(&self, p: NodeId) -> Option<Self> {
+            let incident = self
+                .out_edges(p)
+                .chain(self.in_edges(p))
+                .collect::<Vec<_>>();
+            self.nodes.get(&p)?;
+            let mut graph = self.clone();
+            for e in incident {
+                // 自己ループは両方の集合に入っているので二度目は何もしない
+                if let Some(removed) = graph.remove_edge(e) {
+                    graph = removed;
+                }
+            }
+            graph.nodes = graph.nodes.remove(&p)?;
+            Some(graph)
+        }
+    }
+    impl<N, E> Default for Graph<N, E> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+}
+
+#[cfg(test)]
+use graph::*;
+pub use list::*;
+
+#[test]
+fn test_tree() {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use tree::{priority, Tree};
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut tree = Tree::new();
+    let mut model = BTreeMap::new();
+    let steps = if cfg!(miri) { 100 } else { 3000 };
+    for _ in 0..steps {
+        let key = rng.gen_range(0, 200u64);
+        let old = tree.clone();
+        let old_model = model.clone();
+        match rng.gen_range(0, 3) {
+            0 => {
+                tree = tree.insert(key, key * 10, priority(key));
+                model.insert(key, key * 10);
+            }
+            1 => match tree.remove(&key) {
+                Some(removed) => {
+                    tree = removed;
+                    assert!(model.remove(&key).is_some());
+                }
+                None => assert!(!model.contains_key(&key)),
+            },
+            _ => {
+                if let Some(value) = tree.get_mut(&key) {
+                    *value += 1;
+                }
+                if let Some(value) = model.get_mut(&key) {
+                    *value += 1;
+                }
+            }
+        }
+        // 古い版は変わらない
+        assert!(old.iter().map(|(&k, &v)| (k, v)).eq(old_model.into_iter()));
+        assert!(tree
+            .iter()
+            .map(|(&k, &v)| (k, v))
+            .eq(model.iter().map(|(&k, &v)| (k, v))));
+        assert!(tree
+            .iter()
+            .rev()
+            .map(|(&k, _)| k)
+            .eq(model.keys().rev().copied()));
+        assert_eq!(tree.len(), model.len());
+        assert_eq!(tree.is_empty(), model.is_empty());
+        assert_eq!(tree.rank(&key), model.range(..key).count());
+        assert_eq!(
+            tree.after(&key).map(|(&k, _)| k),
+            model.range(key + 1..).next().map(|(&k, _)| k)
+        );
+        assert_eq!(
+            tree.before(&key).map(|(&k, _)| k),
+            model.range(..key).next_back().map(|(&k, _)| k)
+        );
+        let i = rng.gen_range(0, model.len() + 1);
+        assert_eq!(tree.nth(i).map(|(&k, _)| k), model.keys().nth(i).copied());
+    }
+    tree.validate().assert_ok();
+}
+
+#[test]
+fn test_list() {
+    let list: List<usize> = (1..=3).collect();
+    let p2 = list.nth(1).unwrap();
+    let (list, p0) = list.push_front(0);
+    let (list, p4) = list.push_back(4);
+    let (list, p10) = list.insert(p2, 10).unwrap();
+    assert_eq!(
+        list.iter().copied().collect::<Vec<_>>(),
+        vec![0, 1, 10, 2, 3, 4]
+    );
+    assert_eq!(
+        list.iter().rev().copied().collect::<Vec<_>>(),
+        vec![4, 3, 2, 10, 1, 0]
+    );
+    assert_eq!(list.len(), 6);
+    assert_eq!(list.head(), Some(p0));
+    assert_eq!(list.tail(), Some(p4));
+    assert_eq!(list.next(p10), Some(p2));
+    assert_eq!(list.prev(p2), Some(p10));
+    assert_eq!(list.prev(p0), None);
+    assert_eq!(list.next(p4), None);
+    assert_eq!(list.nth(2), Some(p10));
+    assert_eq!(list.nth_back(0), Some(p4));
+    assert_eq!(list.nth(6), None);
+    assert_eq!(list.position(p2), Some(3));
+    assert_eq!(list.get(p10), Some(&10));
+    list.validate().assert_ok();
+
+    let removed = list.remove(p2).unwrap();
+    assert!(removed.get(p2).is_none());
+    assert!(list.contains(p2) && !removed.contains(p2));
+    assert!(removed.remove(p2).is_none());
+    assert!(removed.insert(p2, 5).is_none());
+    assert!(removed.set(p2, 5).is_none());
+    assert_eq!(removed.next(p10), removed.nth(3));
+    let popped = removed.pop_front().unwrap().pop_back().unwrap();
+    assert_eq!(popped.iter().copied().collect::<Vec<_>>(), vec![1, 10, 3]);
+    popped.validate().assert_ok();
+    assert!(!popped.is_empty() && List::<usize>::new().is_empty());
+    // 別の List の Id は使えない
+    let (other, foreign) = List::new().push_back(9);
+    assert!(list.get(foreign).is_none());
+    assert!(list.insert(foreign, 5).is_none());
+    assert_eq!(other.position(foreign), Some(0));
+}
+
+// 更新しても古い版はそのまま残り、変わらなかったノードを共有する
+#[test]
+fn test_versions() {
+    // 作り直す道は log n 程度なので、n が小さすぎると共有の割合が 9/10 を下回る
+    let n = if cfg!(miri) { 200 } else { 1000 };
+    let v0: List<usize> = (0..n).collect();
+    let middle = v0.nth(n / 2).unwrap();
+    let v1 = v0.set(middle, 0).unwrap();
+    let (v2, inserted) = v1.insert(middle, 1).unwrap();
+    let v3 = v2.remove(middle).unwrap();
+
+    assert_eq!(v0.get(middle), Some(&(n / 2)));
+    assert_eq!(v1.get(middle), Some(&0));
+    assert_eq!(v1.position(middle), Some(n / 2));
+    assert!(v1.get(inserted).is_none());
+    assert_eq!(v2.position(inserted), Some(n / 2));
+    assert_eq!(v2.next(inserted), Some(middle));
+    assert!(v3.get(middle).is_none());
+    assert_eq!(v3.next(inserted), v0.nth(n / 2 + 1));
+    assert!(v0.iter().copied().eq(0..n));
+    for (i, v) in [&v0, &v1, &v2, &v3].iter().enumerate() {
+        v.validate().assert_ok();
+        assert_eq!(v.len(), if i == 2 { n + 1 } else { n });
+    }
+
+    // 値を置き換えても index は作り直さないので、共有していないのは order の道の分だけ
+    assert!(v1.shared_with(&v0) > v0.len());
+    for (new, old) in [(&v1, &v0), (&v2, &v1), (&v3, &v2)].iter() {
+        assert!(new.shared_with(old) > old.node_count() * 9 / 10);
+    }
+}
+
+#[test]
+fn test_drop() {
+    use std::rc::Rc;
+    let value = Rc::new(0);
+    let mut versions = vec![List::new()];
+    for i in 0..100 {
+        let last = versions.last().unwrap();
+        let next = if i % 3 == 2 {
+            last.pop_front().unwrap()
+        } else {
+            last.push_back(value.clone()).0
+        };
+        versions.push(next);
+    }
+    // 値は版の間で共有するので、push した数だけ増える
+    assert_eq!(Rc::strong_count(&value), 1 + 67);
+    versions.truncate(50);
+    assert!(Rc::strong_count(&value) > 1);
+    drop(versions);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[test]
+fn test_graph() {
+    let graph: Graph<&str, usize> = Graph::new();
+    let (graph, a) = graph.add_node("a");
+    let (graph, b) = graph.add_node("b");
+    let (graph, c) = graph.add_node("c");
+    let (graph, ab) = graph.add_edge(a, b, 1).unwrap();
+    let (graph, bc) = graph.add_edge(b, c, 2).unwrap();
+    let (v0, cc) = graph.add_edge(c, c, 3).unwrap();
+    assert_eq!((v0.node_count(), v0.edge_count()), (3, 3));
+    assert_eq!(v0.nodes().collect::<Vec<_>>(), vec![a, b, c]);
+    assert_eq!(v0.neighbors(a).collect::<Vec<_>>(), vec![b]);
+    assert_eq!(v0.in_edges(c).collect::<Vec<_>>(), vec![bc, cc]);
+    assert_eq!(v0.ends(cc), Some((c, c)));
+    assert_eq!(v0.find_edge(b, c), Some(bc));
+    assert_eq!(v0.find_edge(c, b), None);
+    v0.validate().assert_ok();
+
+    // test03 の reset_node1 と違い、古い版の値も辺もそのまま残る
+    let v1 = v0.set_node(a, "A").unwrap().set_edge(ab, 10).unwrap();
+    assert_eq!((v0.node(a), v0.edge(ab)), (Some(&"a"), Some(&1)));
+    assert_eq!((v1.node(a), v1.edge(ab)), (Some(&"A"), Some(&10)));
+    assert_eq!(v1.neighbors(a).collect::<Vec<_>>(), vec![b]);
+
+    let v2 = v1.remove_node(c).unwrap();
+    assert!(v2.node(c).is_none());
+    assert!(v2.edge(bc).is_none() && v2.edge(cc).is_none());
+    assert_eq!(v2.out_edges(b).count(), 0);
+    assert_eq!((v2.node_count(), v2.edge_count()), (2, 1));
+    assert!(v2.add_edge(a, c, 0).is_none());
+    assert!(v2.remove_node(c).is_none());
+    assert_eq!(v1.out_edges(b).collect::<Vec<_>>(), vec![bc]);
+
+    let v3 = v2.remove_edge(ab).unwrap();
+    assert!(v3.remove_edge(ab).is_none());
+    assert_eq!(v3.in_edges(b).count(), 0);
+    assert_eq!(v2.in_edges(b).collect::<Vec<_>>(), vec![ab]);
+    for graph in [&v0, &v1, &v2, &v3].iter() {
+        graph.validate().assert_ok();
+    }
+}
+
+#[test]
+fn test_graph_sharing() {
+    let n = if cfg!(miri) { 150 } else { 300 };
+    let mut graph = Graph::new();
+    let mut nodes = Vec::new();
+    for i in 0..n {
+        let (next, p) = graph.add_node(i);
+        graph = next;
+        nodes.push(p);
+    }
+    for i in 0..n {
+        let (next, _) = graph.add_edge(nodes[i], nodes[(i + 1) % n], i).unwrap();
+        graph = next;
+    }
+    let (updated, _) = graph.add_edge(nodes[0], nodes[n / 2], n).unwrap();
+    let total = graph.node_count() + graph.edge_count() + 2 * graph.edge_count();
+    assert!(updated.shared_with(&graph) > total * 9 / 10);
+    assert_eq!(graph.edge_count() + 1, updated.edge_count());
+    updated.validate().assert_ok();
+}
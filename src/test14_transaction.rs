@@ -0,0 +1,485 @@
+#![cfg(test)]
+// test07 の List への insert / remove / 値の書き換えをまとめて取り消せるようにする。
+// 取り消しで同じ Ptr を返すには、削除したノードを解放すると別のノードにスロットを使われてしまう。
+// そこで Transaction の中の remove はノードを外すだけにして（List::detach）、どの記録からも
+// 戻せなくなったときに初めて解放する。取り消し・やり直しは外したノードを同じ位置に繋ぎ直すだけになる。
+use crate::linked_list::LinkedList;
+use crate::test07_nodes_in_arena::list::{List, Node};
+use crate::test07_nodes_in_arena::pool::Ptr;
+use std::collections::HashSet;
+
+// 行った変更。取り消すときは逆の操作をする
+enum Op<T> {
+    Link {
+        node: Ptr<Node<T>>,
+        next: Ptr<Node<T>>, // node を next の直前に繋いだ
+    },
+    Unlink {
+        node: Ptr<Node<T>>,
+        next: Ptr<Node<T>>, // next の直前にあった node を外した
+    },
+    Set {
+        node: Ptr<Node<T>>,
+        value: T, // 書き換える前の値。取り消し・やり直しのたびにノードの値と入れ替える
+    },
+}
+impl<T> Op<T> {
+    // 繋いだり外したりしたノード
+    fn linked_node(&self) -> Option<Ptr<Node<T>>> {
+        match self {
+            Op::Link { node, .. } | Op::Unlink { node, .. } => Some(*node),
+            Op::Set { .. } => None,
+        }
+    }
+    // 記録したときと同じ状態のリストにしか適用しない。そうでなければ History の不具合
+    fn redo(&mut self, list: &mut List<T>) {
+        match self {
+            Op::Link { node, next } => assert!(list.attach(*node, *next)),
+            Op::Unlink { node, next } => assert_eq!(list.detach(*node), Some(*next)),
+            Op::Set { node, value } => {
+                std::mem::swap(LinkedList::get_mut(list, *node).unwrap(), value)
+            }
+        }
+    }
+    fn undo(&mut self, list: &mut List<T>) {
+        match self {
+            Op::Link { node, next } => assert_eq!(list.detach(*node), Some(*next)),
+            Op::Unlink { node, next } => assert!(list.attach(*node, *next)),
+            Op::Set { node, value } => {
+                std::mem::swap(LinkedList::get_mut(list, *node).unwrap(), value)
+            }
+        }
+    }
+}
+
+// Transaction 一つ分の記録を積んだもの
+type Stack<T> = Vec<Vec<Op<T>>>;
+
+// 捨てる記録が外したままにしたノードは、もうどこからも戻せないので解放する
+fn discard<T>(list: &mut List<T>, ops: impl IntoIterator<Item = Op<T>>) {
+    for op in ops {
+        if let Some(node) = op.linked_node() {
+            list.free_detached(node);
+        }
+    }
+}
+
+// List を持ち、変更は Transaction を通してだけ行う。
+// そうしておけば取り消すときのリストは必ず記録したときと同じ状態になる
+pub struct History<T> {
+    list: List<T>,
+    log: Vec<Op<T>>, // 進行中の Transaction の記録。Transaction の外では空
+    undo: Stack<T>,
+    redo: Stack<T>,
+}
+impl<T> History<T> {
+    pub fn new(list: List<T>) -> Self {
+        Self {
+            list,
+            log: Vec::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+    pub fn list(&self) -> &List<T> {
+        &self.list
+    }
+    pub fn transaction(&mut self) -> Transaction<'_, T> {
+        Transaction {
+            list: &mut self.list,
+            log: &mut self.log,
+            start: 0,
+            stacks: Some((&mut self.undo, &mut self.redo)),
+            finished: false,
+        }
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+    // 最後に commit した Transaction を取り消す
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(mut ops) => {
+                for op in ops.iter_mut().rev() {
+                    op.undo(&mut self.list);
+                }
+                self.redo.push(ops);
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(mut ops) => {
+                for op in ops.iter_mut() {
+                    op.redo(&mut self.list);
+                }
+                self.undo.push(ops);
+                true
+            }
+            None => false,
+        }
+    }
+    // 取り消し・やり直しの記録を捨てて、外したまま取っておいたノードを解放する
+    pub fn clear(&mut self) {
+        let ops = self.undo.drain(..).chain(self.redo.drain(..)).flatten();
+        discard(&mut self.list, ops);
+    }
+    pub fn into_inner(mut self) -> List<T> {
+        self.clear();
+        self.list
+    }
+}
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self::new(List::new())
+    }
+}
+
+// commit も rollback もせずに drop すると rollback する。
+// savepoint は親を借用するので、入れ子は必ず内側から終わる
+pub struct Transaction<'a, T> {
+    list: &'a mut List<T>,
+    log: &'a mut Vec<Op<T>>,
+    start: usize, // log のうちこの Transaction の記録が始まる位置
+    stacks: Option<(&'a mut Stack<T>, &'a mut Stack<T>)>, // 一番外側だけが undo / redo を持つ
+    finished: bool,
+}
+impl<'a, T> Transaction<'a, T> {
+    pub fn list(&self) -> &List<T> {
+        self.list
+    }
+    pub fn get(&self, p: Ptr<Node<T>>) -> Option<&T> {
+        LinkedList::get(&*self.list, p)
+    }
+    fn linked(&mut self, node: Ptr<Node<T>>) -> Ptr<Node<T>> {
+        let next = self.list.get(node).unwrap().get().next().into();
+        self.log.push(Op::Link { node, next });
+        node
+    }
+    // pos の直前に挿入する
+    pub fn insert(&mut self, pos: Ptr<Node<T>>, value: T) -> Option<Ptr<Node<T>>> {
+        let node = LinkedList::insert(&mut *self.list, pos, value)?;
+        Some(self.linked(node))
+    }
+    pub fn push_back(&mut self, value: T) -> Ptr<Node<T>> {
+        let node = LinkedList::push_back(&mut *self.list, value);
+        self.linked(node)
+    }
+    pub fn push_front(&mut self, value: T) -> Ptr<Node<T>> {
+        let node = LinkedList::push_front(&mut *self.list, value);
+        self.linked(node)
+    }
+    // 値は取り消しに備えてノードに残るので返さない
+    pub fn remove(&mut self, p: Ptr<Node<T>>) -> bool {
+        match self.list.detach(p) {
+            Some(next) => {
+                self.log.push(Op::Unlink { node: p, next });
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn set(&mut self, p: Ptr<Node<T>>, value: T) -> bool {
+        match LinkedList::get_mut(&mut *self.list, p) {
+            Some(current) => {
+                let value = std::mem::replace(current, value);
+                self.log.push(Op::Set { node: p, value });
+                true
+            }
+            None => false,
+        }
+    }
+    // ここから先の変更だけを rollback できる入れ子の Transaction
+    pub fn savepoint(&mut self) -> Transaction<'_, T> {
+        let start = self.log.len();
+        Transaction {
+            list: &mut *self.list,
+            log: &mut *self.log,
+            start,
+            stacks: None,
+            finished: false,
+        }
+    }
+    // 入れ子なら記録を外側に残すだけ。一番外側なら記録を undo に積み、やり直しの記録は捨てる
+    pub fn commit(mut self) {
+        self.finished = true;
+        let (undo, redo) = match self.stacks.take() {
+            Some(stacks) => stacks,
+            None => return,
+        };
+        let ops = self.log.drain(..).collect::<Vec<_>>();
+        if ops.is_empty() {
+            return;
+        }
+        // この Transaction で外したノードは undo から戻すので解放しない
+        let kept = ops
+            .iter()
+            .filter_map(Op::linked_node)
+            .collect::<HashSet<_>>();
+        let dropped = redo
+            .drain(..)
+            .flatten()
+            .filter(|op| op.linked_node().is_none_or(|node| !kept.contains(&node)));
+        discard(self.list, dropped);
+        undo.push(ops);
+    }
+    pub fn rollback(mut self) {
+        self.finished = true;
+        self.rollback_unfinished();
+    }
+    fn rollback_unfinished(&mut self) {
+        let mut ops = self.log.drain(self.start..).collect::<Vec<_>>();
+        for op in ops.iter_mut().rev() {
+            op.undo(self.list);
+        }
+        discard(self.list, ops);
+    }
+}
+impl<'a, T> Drop for Transaction<'a, T> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.rollback_unfinished();
+        }
+    }
+}
+
+// 先頭から (Ptr, 値) を集める。Ptr まで元に戻っていることを確かめるのに使う
+#[cfg(test)]
+fn snapshot(list: &List<usize>) -> Vec<(Ptr<Node<usize>>, usize)> {
+    let mut result = Vec::new();
+    let mut node = LinkedList::head(list);
+    while let Some(p) = node {
+        result.push((p, *LinkedList::get(list, p).unwrap()));
+        node = LinkedList::next(list, p);
+    }
+    result
+}
+
+#[test]
+fn test_commit_rollback() {
+    let mut history = History::new((0..5).collect::<List<usize>>());
+    let before = snapshot(history.list());
+    let p1 = before[1].0;
+    let p3 = before[3].0;
+
+    let mut tx = history.transaction();
+    let q = tx.push_back(5);
+    assert!(tx.insert(p1, 10).is_some());
+    assert!(tx.remove(p3));
+    assert!(!tx.remove(p3));
+    assert!(tx.get(p3).is_none());
+    assert!(tx.set(p1, 11));
+    assert_eq!(
+        tx.list().iter().copied().collect::<Vec<_>>(),
+        vec![0, 10, 11, 2, 4, 5]
+    );
+    tx.rollback();
+    assert_eq!(snapshot(history.list()), before);
+    assert!(LinkedList::get(history.list(), q).is_none());
+    assert!(!history.can_undo());
+    history.list().validate().assert_ok();
+
+    // drop しても rollback する
+    {
+        let mut tx = history.transaction();
+        tx.push_front(9);
+        assert!(tx.remove(p1));
+    }
+    assert_eq!(snapshot(history.list()), before);
+
+    let mut tx = history.transaction();
+    assert!(tx.remove(p3));
+    tx.push_front(9);
+    tx.commit();
+    assert_eq!(
+        history.list().iter().copied().collect::<Vec<_>>(),
+        vec![9, 0, 1, 2, 4]
+    );
+    assert!(history.can_undo());
+    history.list().validate().assert_ok();
+}
+
+// 取り消し・やり直しの前後で外に持っている Ptr がそのまま使える
+#[test]
+fn test_undo_redo() {
+    let mut history = History::new((0..4).collect::<List<usize>>());
+    let v0 = snapshot(history.list());
+    let p2 = v0[2].0;
+
+    let mut tx = history.transaction();
+    assert!(tx.remove(p2));
+    let q = tx.insert(v0[1].0, 10).unwrap();
+    assert!(tx.set(v0[0].0, 20));
+    tx.commit();
+    let v1 = snapshot(history.list());
+
+    let mut tx = history.transaction();
+    assert!(tx.remove(q));
+    tx.commit();
+    let v2 = snapshot(history.list());
+
+    assert!(history.undo());
+    assert_eq!(snapshot(history.list()), v1);
+    assert!(history.undo());
+    assert_eq!(snapshot(history.list()), v0);
+    assert_eq!(LinkedList::position(history.list(), p2), Some(2));
+    assert!(LinkedList::get(history.list(), q).is_none());
+    assert!(!history.undo());
+
+    assert!(history.redo());
+    assert_eq!(snapshot(history.list()), v1);
+    assert_eq!(LinkedList::get(history.list(), q), Some(&10));
+    assert!(history.redo());
+    assert_eq!(snapshot(history.list()), v2);
+    assert!(!history.redo());
+    history.list().validate().assert_ok();
+
+    // 新しく commit するとやり直しの記録は捨てられる
+    assert!(history.undo());
+    let mut tx = history.transaction();
+    tx.push_back(7);
+    tx.commit();
+    assert!(!history.can_redo());
+    assert!(history.undo());
+    assert!(history.undo());
+    assert_eq!(snapshot(history.list()), v0);
+}
+
+#[test]
+fn test_savepoint() {
+    let mut history = History::new(List::new());
+    let mut tx = history.transaction();
+    let p1 = tx.push_back(1);
+    {
+        let mut sp1 = tx.savepoint();
+        sp1.push_back(2);
+        {
+            let mut sp2 = sp1.savepoint();
+            sp2.push_back(3);
+            assert!(sp2.remove(p1));
+            sp2.rollback();
+        }
+        assert_eq!(sp1.list().iter().copied().collect::<Vec<_>>(), vec![1, 2]);
+        {
+            // commit しても外側の rollback で取り消される
+            let mut sp2 = sp1.savepoint();
+            assert!(sp2.set(p1, 10));
+            sp2.commit();
+        }
+        sp1.commit();
+    }
+    {
+        let mut sp3 = tx.savepoint();
+        sp3.push_front(0);
+    }
+    assert_eq!(tx.list().iter().copied().collect::<Vec<_>>(), vec![10, 2]);
+    tx.commit();
+
+    assert!(history.undo());
+    assert!(history.list().is_empty());
+    assert!(history.redo());
+    assert_eq!(LinkedList::get(history.list(), p1), Some(&10));
+}
+
+// 戻せなくなったノードは解放され、Pool には繋がっているノードと sentinel だけが残る
+#[test]
+fn test_free_detached() {
+    let mut history = History::new((0..10).collect::<List<usize>>());
+    let live = |history: &History<usize>| LinkedList::memory(history.list()).live;
+    let ptrs = snapshot(history.list());
+
+    let mut tx = history.transaction();
+    for &(p, _) in &ptrs[..5] {
+        tx.remove(p);
+    }
+    tx.commit();
+    // 取り消しに備えて外したノードを持っている
+    assert_eq!(live(&history), 10 + 1);
+    assert!(history.undo());
+    let mut tx = history.transaction();
+    tx.push_back(10);
+    tx.remove(ptrs[0].0);
+    tx.commit();
+    assert_eq!(live(&history), 11 + 1);
+    history.clear();
+    assert_eq!(live(&history), 10 + 1);
+    assert!(!history.undo());
+
+    let list = history.into_inner();
+    assert_eq!(
+        list.iter().copied().collect::<Vec<_>>(),
+        (1..=10).collect::<Vec<_>>()
+    );
+    list.validate().assert_ok();
+}
+
+// ランダムな Transaction と取り消し・やり直しを繰り返し、各版の (Ptr, 値) の列と比べる
+#[test]
+fn test_random() {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    let steps = if cfg!(miri) { 20 } else { 500 };
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut history = History::new((0..10).collect::<List<usize>>());
+    let mut versions = vec![snapshot(history.list())];
+    let mut current = 0; // versions のうち今のリストに当たる版
+    for step in 0..steps {
+        match rng.gen_range(0, 4) {
+            0 => {
+                if history.undo() {
+                    current -= 1;
+                }
+            }
+            1 => {
+                if history.redo() {
+                    current += 1;
+                }
+            }
+            _ => {
+                let mut tx = history.transaction();
+                let mut recorded = false; // 変更が差し引きで無くても、記録があれば一つの版になる
+                for i in 0..rng.gen_range(0, 4) {
+                    let mut sp = tx.savepoint();
+                    let ops = rng.gen_range(0, 4);
+                    for _ in 0..ops {
+                        let target = LinkedList::nth(sp.list(), rng.gen_range(0, 20));
+                        let value = step * 100 + i;
+                        match (rng.gen_range(0, 4), target) {
+                            (0, Some(p)) => assert!(sp.insert(p, value).is_some()),
+                            (1, Some(p)) => assert!(sp.remove(p)),
+                            (2, Some(p)) => assert!(sp.set(p, value)),
+                            _ => {
+                                sp.push_back(value);
+                            }
+                        }
+                    }
+                    if rng.gen() {
+                        sp.commit();
+                        recorded |= ops > 0;
+                    }
+                }
+                let commit = rng.gen_range(0, 4) != 0;
+                if commit {
+                    tx.commit();
+                } else {
+                    tx.rollback();
+                }
+                if commit && recorded {
+                    versions.truncate(current + 1);
+                    versions.push(snapshot(history.list()));
+                    current += 1;
+                }
+            }
+        }
+        assert_eq!(snapshot(history.list()), versions[current], "step {}", step);
+        assert_eq!(history.can_undo(), current > 0);
+        assert_eq!(history.can_redo(), current + 1 < versions.len());
+        history.list().validate().assert_ok();
+    }
+}
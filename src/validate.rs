@@ -0,0 +1,241 @@
+// 各 List / Graph の validate() が返す検査結果
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    // at 番目の要素から link を辿っても自分に戻ってこない（例えば "next.prev"）
+    BrokenLink {
+        at: usize,
+        link: &'static str,
+    },
+    // at 番目の要素の link が自分の Pool の生きている要素を指していない
+    InvalidPtr {
+        at: usize,
+        link: &'static str,
+        reason: String,
+    },
+    // sentinel がちょうど一つではない
+    SentinelCount(usize),
+    // at 番目の要素から先が始点に戻らずに循環している
+    Cycle {
+        at: usize,
+    },
+    // 覚えている個数と実際に辿れた個数が違う
+    CountMismatch {
+        what: &'static str,
+        recorded: usize,
+        actual: usize,
+    },
+    // at 番目の要素が what の順序を満たしていない（木のキーの順や treap の優先度など）
+    OutOfOrder {
+        at: usize,
+        what: &'static str,
+    },
+}
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::BrokenLink { at, link } => {
+                write!(f, "#{}: {} does not point back", at, link)
+            }
+            Violation::InvalidPtr { at, link, reason } => {
+                write!(f, "#{}: {} is invalid ({})", at, link, reason)
+            }
+            Violation::SentinelCount(n) => write!(f, "{} sentinels found", n),
+            Violation::Cycle { at } => {
+                write!(f, "#{}: cycle that does not return to the start", at)
+            }
+            Violation::CountMismatch {
+                what,
+                recorded,
+                actual,
+            } => write!(f, "{} is {} but {} are linked", what, recorded, actual),
+            Violation::OutOfOrder { at, what } => write!(f, "#{}: {} is out of order", at, what),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub violations: Vec<Violation>,
+}
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+    pub fn push(&mut self, violation: Violation) {
+        self.violations.push(violation);
+    }
+    // debug build で変更の後に debug_assert! の代わりに呼ぶ
+    pub fn assert_ok(&self) {
+        assert!(self.is_ok(), "{}", self);
+    }
+}
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "ok");
+        }
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+// sentinel 付き双方向リストのノードから読み取ったリンク。key はノードのアドレス。
+pub struct NodeLinks<P> {
+    pub key: usize,
+    pub is_sentinel: bool,
+    pub next: P,
+    pub prev: P,
+}
+
+// sentinel から next を辿ってリストを検査する。at は sentinel を 0 とした位置。
+// resolve は P が自分の Pool の生きているノードを指していればそのリンクを、そうでなければ理由を返す。
+pub fn check_sentinel_list<P: Copy>(
+    sentinel: P,
+    len: Option<usize>,
+    resolve: impl Fn(P) -> Result<NodeLinks<P>, String>,
+) -> Report {
+    let mut report = Report::default();
+    let start = match resolve(sentinel) {
+        Ok(start) => start,
+        Err(reason) => {
+            report.push(Violation::InvalidPtr {
+                at: 0,
+                link: "sentinel",
+                reason,
+            });
+            return report;
+        }
+    };
+    let mut visited = HashSet::new();
+    visited.insert(start.key);
+    let mut sentinels = if start.is_sentinel { 1 } else { 0 };
+    let mut linked = 0;
+    let mut at = 0;
+    let mut current = start.key;
+    let mut next = start.next;
+    let completed = loop {
+        let node = match resolve(next) {
+            Ok(node) => node,
+            Err(reason) => {
+                report.push(Violation::InvalidPtr {
+                    at,
+                    link: "next",
+                    reason,
+                });
+                break false;
+            }
+        };
+        match resolve(node.prev) {
+            Ok(back) if back.key == current => {}
+            Ok(_) => report.push(Violation::BrokenLink {
+                at,
+                link: "next.prev",
+            }),
+            Err(reason) => report.push(Violation::InvalidPtr {
+                at: at + 1,
+                link: "prev",
+                reason,
+            }),
+        }
+        if node.key == start.key {
+            break true;
+        }
+        at += 1;
+        if !visited.insert(node.key) {
+            report.push(Violation::Cycle { at });
+            break false;
+        }
+        if node.is_sentinel {
+            sentinels += 1;
+        } else {
+            linked += 1;
+        }
+        current = node.key;
+        next = node.next;
+    };
+    if completed && sentinels != 1 {
+        report.push(Violation::SentinelCount(sentinels));
+    }
+    match len {
+        Some(len) if completed && len != linked => report.push(Violation::CountMismatch {
+            what: "len",
+            recorded: len,
+            actual: linked,
+        }),
+        _ => {}
+    }
+    report
+}
+
+#[test]
+fn test_check_sentinel_list() {
+    // 添字をポインタの代わりにした (is_sentinel, next, prev) の配列
+    fn check(nodes: &[(bool, usize, usize)], len: usize) -> Report {
+        check_sentinel_list(0, Some(len), |i: usize| {
+            nodes
+                .get(i)
+                .map(|&(is_sentinel, next, prev)| NodeLinks {
+                    key: i,
+                    is_sentinel,
+                    next,
+                    prev,
+                })
+                .ok_or_else(|| "out of range".to_string())
+        })
+    }
+    assert!(check(&[(true, 1, 2), (false, 2, 0), (false, 0, 1)], 2).is_ok());
+    assert!(check(&[(true, 0, 0)], 0).is_ok());
+
+    let report = check(&[(true, 1, 2), (false, 2, 0), (false, 0, 0)], 2);
+    assert_eq!(
+        report.violations,
+        vec![Violation::BrokenLink {
+            at: 1,
+            link: "next.prev"
+        }]
+    );
+    assert_eq!(report.to_string(), "#1: next.prev does not point back");
+
+    let report = check(&[(true, 1, 2), (false, 5, 0), (false, 0, 1)], 2);
+    assert_eq!(
+        report.violations,
+        vec![Violation::InvalidPtr {
+            at: 1,
+            link: "next",
+            reason: "out of range".to_string()
+        }]
+    );
+
+    let report = check(&[(true, 1, 2), (false, 2, 0), (false, 1, 1)], 2);
+    assert_eq!(
+        report.violations,
+        vec![
+            Violation::BrokenLink {
+                at: 2,
+                link: "next.prev"
+            },
+            Violation::Cycle { at: 3 }
+        ]
+    );
+
+    let report = check(&[(true, 1, 2), (true, 2, 0), (false, 0, 1)], 2);
+    assert_eq!(
+        report.violations,
+        vec![
+            Violation::SentinelCount(2),
+            Violation::CountMismatch {
+                what: "len",
+                recorded: 2,
+                actual: 1
+            }
+        ]
+    );
+}